@@ -4,6 +4,7 @@ use serde_json::Value;
 
 use things::thing::bounds::BoundsRefinement;
 use things::thing::primitives::PrimitiveRefinement;
+use things::thing::validation::ValidationIssue;
 use things::thing::{Thing, ThingLike};
 
 use crate::vm::OperationResult;
@@ -18,7 +19,7 @@ fn set_value(
     value: &mut Value,
     thing: &impl ThingLike,
     new_value: impl Into<Value>,
-) -> anyhow::Result<Vec<anyhow::Error>> {
+) -> Result<Vec<ValidationIssue>, Box<ValidationIssue>> {
     let mut new_value = new_value.into();
     let warns = thing.apply(&mut new_value)?;
     *value = new_value;
@@ -1,5 +1,7 @@
 ﻿use anyhow::Error;
 
+use things::thing::validation::ValidationIssue;
+
 use crate::vm::VmErrors;
 
 #[derive(Debug)]
@@ -9,8 +11,8 @@ pub enum OperationResult {
     Err(Error),
 }
 
-impl<T: Into<OperationResult>> From<Result<Vec<Error>, T>> for OperationResult {
-    fn from(value: Result<Vec<Error>, T>) -> Self {
+impl<W: Into<OperationResult>, T: Into<OperationResult>> From<Result<W, T>> for OperationResult {
+    fn from(value: Result<W, T>) -> Self {
         match value {
             Ok(warns) => warns.into(),
             Err(err) => err.into(),
@@ -28,6 +30,23 @@ impl From<Vec<Error>> for OperationResult {
     }
 }
 
+/// Issues stay downcastable from the errors, so editors can locate them by their paths
+impl From<Vec<ValidationIssue>> for OperationResult {
+    fn from(issues: Vec<ValidationIssue>) -> Self {
+        issues
+            .into_iter()
+            .map(Error::from)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+impl From<Box<ValidationIssue>> for OperationResult {
+    fn from(issue: Box<ValidationIssue>) -> Self {
+        OperationResult::Err((*issue).into())
+    }
+}
+
 impl From<Error> for OperationResult {
     fn from(err: Error) -> Self {
         return OperationResult::Err(err);
@@ -161,12 +161,4 @@ pub enum Path<'src> {
     AnyScope(&'src str),
 }
 
-macro_rules! ast_err {
-    () => {
-        panic!("AST has an error node!")
-    };
-}
-
-pub(crate) use ast_err;
-
 impl<'src> Expr<'src> {}
@@ -0,0 +1,239 @@
+use crate::errors::RuntimeError;
+use crate::intrinsics::Intrinsic;
+use crate::value::{Value, ValueMap, ISA_KEY};
+use std::rc::Rc;
+
+/// Conversion of host values into script values
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+/// Conversion of script values into host values
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError>;
+}
+
+/// Host type with methods callable from scripts, usually implemented via `#[script_methods]`
+pub trait ScriptMethods {
+    fn script_methods() -> Vec<Intrinsic>;
+
+    /// Builds a map holding all script methods of this type, to be used as `__isa` of objects
+    fn script_class() -> Value {
+        let mut map = ValueMap::default();
+        for method in Self::script_methods() {
+            map.insert(Value::from(method.name()), Value::from(method));
+        }
+        Value::new_map(map)
+    }
+
+    /// Converts the value into a map that inherits from the given class
+    fn to_script_object(&self, class: &Value) -> Value
+    where
+        Self: ToValue,
+    {
+        let value = self.to_value();
+        if let Value::Map(map) = &value {
+            map.borrow_mut().insert(Value::from(ISA_KEY), class.clone());
+        }
+        value
+    }
+}
+
+/// Copies entries of `source` map into the `target` map, keeping the `target` identity
+pub fn write_back(target: &Value, source: Value) -> Result<(), RuntimeError> {
+    match (target, source) {
+        (Value::Map(target), Value::Map(source)) => {
+            if !Rc::ptr_eq(target, &source) {
+                let mut target = target.borrow_mut();
+                for (key, value) in source.borrow().iter() {
+                    target.insert(key.clone(), value.clone());
+                }
+            }
+            Ok(())
+        }
+        (Value::Map(_), source) => Err(type_mismatch("map", &source)),
+        (target, _) => Err(type_mismatch("map", target)),
+    }
+}
+
+/// Reads a field of a map, used by the derived `FromValue` implementations
+pub fn get_field<T: FromValue>(value: &Value, name: &str) -> Result<T, RuntimeError> {
+    let Value::Map(map) = value else {
+        return Err(type_mismatch("map", value));
+    };
+    let map = map.borrow();
+    let field = map
+        .get(&Value::from(name))
+        .ok_or_else(|| RuntimeError::KeyNotFound(name.to_string()))?;
+    T::from_value(field)
+}
+
+fn type_mismatch(expected: &'static str, got: &Value) -> RuntimeError {
+    RuntimeError::TypeMismatch {
+        expected,
+        got: got.type_name(),
+    }
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        Ok(value.clone())
+    }
+}
+
+impl ToValue for () {
+    fn to_value(&self) -> Value {
+        Value::Null
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::from(*self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        Ok(value.as_bool())
+    }
+}
+
+fn number(value: &Value) -> Result<f64, RuntimeError> {
+    value
+        .as_f64_checked()
+        .ok_or_else(|| type_mismatch("number", value))
+}
+
+/// Whether the number is whole and within the range of an integer type. The upper bound is
+/// exclusive, since `MAX as f64` of 64-bit types is rounded up to the next power of two
+fn is_exact_integer(num: f64, min: f64, max: f64) -> bool {
+    num.fract() == 0. && num >= min && num < max + 1.
+}
+
+macro_rules! numeric_binding {
+    ($type:ty) => {
+        impl ToValue for $type {
+            fn to_value(&self) -> Value {
+                Value::from(*self)
+            }
+        }
+
+        impl FromValue for $type {
+            fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+                let num = number(value)?;
+                if !is_exact_integer(num, <$type>::MIN as f64, <$type>::MAX as f64) {
+                    return Err(RuntimeError::NumberOutOfRange {
+                        number: num,
+                        target: stringify!($type),
+                    });
+                }
+                Ok(num as $type)
+            }
+        }
+    };
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::from(*self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        number(value)
+    }
+}
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::from(*self)
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        let num = number(value)?;
+        // Precision is lost like in any `f64` to `f32` conversion, but overflows are reported
+        if num.is_finite() && (num as f32).is_infinite() {
+            return Err(RuntimeError::NumberOutOfRange {
+                number: num,
+                target: "f32",
+            });
+        }
+        Ok(num as f32)
+    }
+}
+
+numeric_binding!(u8);
+numeric_binding!(u16);
+numeric_binding!(u32);
+numeric_binding!(u64);
+numeric_binding!(usize);
+numeric_binding!(i8);
+numeric_binding!(i16);
+numeric_binding!(i32);
+numeric_binding!(i64);
+numeric_binding!(isize);
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::from(self.as_str())
+    }
+}
+
+impl ToValue for &str {
+    fn to_value(&self) -> Value {
+        Value::from(*self)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| type_mismatch("string", value))
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            None => Value::Null,
+            Some(value) => value.to_value(),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::new_list(self.iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        let Value::List(list) = value else {
+            return Err(type_mismatch("list", value));
+        };
+        let list = list.borrow();
+        list.iter().map(T::from_value).collect()
+    }
+}
@@ -0,0 +1,89 @@
+use crate::errors::MsError;
+use crate::lint::LintWarning;
+use crate::vm::chunk::Chunk;
+use crate::vm::Vm;
+use serde::Serialize;
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Machine readable form of errors and warnings, for editors and CI tools
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: u16,
+    pub message: String,
+    pub file: String,
+    /// Byte offsets in the source code
+    pub span: Option<Range<usize>>,
+    /// One-based line and column of the span start, when the source code is available
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    pub fn from_error(
+        error: &MsError,
+        src: Option<&str>,
+        chunk: Option<&Chunk>,
+        vm: Option<&Vm>,
+    ) -> Self {
+        Self::new(
+            Severity::Error,
+            error.code(),
+            error.to_string(),
+            &error.src_id,
+            error.span(chunk, vm),
+            src,
+        )
+    }
+
+    pub fn from_warning(warning: &LintWarning, src_id: &str, src: &str) -> Self {
+        Self::new(
+            Severity::Warning,
+            warning.code(),
+            warning.message.clone(),
+            src_id,
+            Some(warning.span.into_range()),
+            Some(src),
+        )
+    }
+
+    fn new(
+        severity: Severity,
+        code: u16,
+        message: String,
+        file: &str,
+        span: Option<Range<usize>>,
+        src: Option<&str>,
+    ) -> Self {
+        let position = span
+            .as_ref()
+            .zip(src)
+            .and_then(|(span, src)| position(src, span.start));
+        Self {
+            severity,
+            code,
+            message,
+            file: file.to_string(),
+            span,
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        }
+    }
+}
+
+/// One-based line and column of the byte offset, with columns counted in characters
+fn position(src: &str, offset: usize) -> Option<(usize, usize)> {
+    let before = src.get(..offset)?;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Some((
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    ))
+}
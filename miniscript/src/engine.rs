@@ -0,0 +1,230 @@
+use crate::bindings::FromValue;
+use crate::errors::{MsError, RuntimeError};
+use crate::intrinsics::sandboxed::Capabilities;
+use crate::intrinsics::{Intrinsic, IntrinsicParam};
+use crate::parse;
+use crate::symbol::Symbol;
+use crate::value::Value;
+use crate::vm::chunk::{compile_chunk_with_result, Chunk};
+use crate::vm::op_code::call_value;
+use crate::vm::{DefaultRunner, Vm, VmRunner};
+use ariadne::Report;
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+use std::fs;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// Source id of snippets evaluated by `Engine::eval`
+pub const EVAL_SRC_ID: &str = "<eval>";
+/// Source id of errors raised by functions that the host called directly
+pub const CALL_SRC_ID: &str = "<call>";
+
+/// Finds source code of the modules that scripts import
+pub trait ModuleLoader {
+    /// Source code of the module, or `None` if there's no such module
+    fn load(&self, name: &str) -> Option<String>;
+}
+
+impl<F: Fn(&str) -> Option<String>> ModuleLoader for F {
+    fn load(&self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+/// Loads modules from `<name>.ms` files, with `/` in names leading into subdirectories
+pub struct DirectoryLoader {
+    root: PathBuf,
+}
+
+impl DirectoryLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ModuleLoader for DirectoryLoader {
+    fn load(&self, name: &str) -> Option<String> {
+        let path = Path::new(name);
+        // Names can't lead outside of the directory
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        fs::read_to_string(self.root.join(path).with_extension("ms")).ok()
+    }
+}
+
+/// Modules imported so far, shared with the `import` intrinsic
+struct Modules {
+    loader: Box<dyn ModuleLoader>,
+    /// Values of imported modules, with `None` for the ones that are still running
+    loaded: RefCell<FxHashMap<String, Option<Value>>>,
+}
+
+/// Receives lines printed by scripts
+pub type OutputSink = Box<dyn FnMut(&str)>;
+
+/// Scripting environment for embedding, which keeps globals, intrinsics and settings between
+/// evaluations.
+///
+/// Top level variables of scripts only live until the evaluation ends, so values that have to
+/// persist should be kept in globals by the host.
+#[derive(Default)]
+pub struct Engine {
+    vm: Vm,
+    /// Chunk of the last evaluation, which locates its runtime errors
+    chunk: Option<Chunk>,
+    output: Option<OutputSink>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the snippet, returning the value of its last statement if that's an expression, and
+    /// null otherwise.
+    ///
+    /// Only the first compilation error is returned, `compile` reports all of them.
+    pub fn eval(&mut self, src: &str) -> Result<Value, MsError> {
+        let ast = parse(EVAL_SRC_ID, src).map_err(|mut errors| errors.swap_remove(0))?;
+        let chunk = compile_chunk_with_result(ast);
+        self.vm.cursor = 0;
+        self.vm.stack = vec![Value::Null; chunk.stack_size()];
+        self.vm.returned = None;
+        let result = DefaultRunner.run(&chunk, &mut self.vm);
+        self.chunk = Some(chunk);
+        self.flush_output();
+        result?;
+        Ok(self.vm.returned.take().unwrap_or(Value::Null))
+    }
+
+    /// Calls a function value, like an intrinsic read from globals
+    pub fn call(&mut self, function: &Value, args: Vec<Value>) -> Result<Value, MsError> {
+        let result = call_value(&mut self.vm, function.clone(), args).map_err(|err| MsError {
+            src_id: CALL_SRC_ID.to_string(),
+            error_type: err,
+        });
+        self.flush_output();
+        result
+    }
+
+    pub fn set_global(&mut self, name: impl Into<Symbol>, value: impl Into<Value>) {
+        self.vm.set_global(name, value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.vm.get_global(name)
+    }
+
+    pub fn register_intrinsic(&mut self, intrinsic: Intrinsic) {
+        self.vm.register_intrinsic(intrinsic);
+    }
+
+    /// Defines `import(name)`, which runs the module found by the loader once, and returns the
+    /// value of its last expression on every import
+    pub fn set_loader(&mut self, loader: impl ModuleLoader + 'static) {
+        let modules = Rc::new(Modules {
+            loader: Box::new(loader),
+            loaded: Default::default(),
+        });
+        self.vm.register_intrinsic(import_intrinsic(modules));
+    }
+
+    /// Passes lines printed by scripts to the sink instead of stdout, once each call finishes
+    pub fn set_output(&mut self, sink: impl FnMut(&str) + 'static) {
+        self.vm.capture_output();
+        self.output = Some(Box::new(sink));
+    }
+
+    pub fn enable(&mut self, capabilities: Capabilities) {
+        self.vm.enable(capabilities);
+    }
+
+    pub fn set_file_root(&mut self, root: impl Into<PathBuf>) {
+        self.vm.set_file_root(root);
+    }
+
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut Vm {
+        &mut self.vm
+    }
+
+    /// Report of an error returned by the engine, pointing into the last evaluated snippet
+    pub fn report<'a>(&self, err: &'a MsError) -> Report<'a, (String, Range<usize>)> {
+        let chunk = self.chunk.as_ref().filter(|_| err.src_id == EVAL_SRC_ID);
+        err.report(chunk, Some(&self.vm))
+    }
+
+    fn flush_output(&mut self) {
+        if let (Some(sink), Some(lines)) = (&mut self.output, &mut self.vm.output) {
+            for line in lines.drain(..) {
+                sink(&line);
+            }
+        }
+    }
+}
+
+fn import_intrinsic(modules: Rc<Modules>) -> Intrinsic {
+    Intrinsic::new(
+        "import",
+        vec![IntrinsicParam::new("name")],
+        move |vm, args| {
+            let name = String::from_value(&args[0])?;
+            let failed = |message: String| RuntimeError::Import {
+                module: name.clone(),
+                message,
+            };
+            let loaded = modules.loaded.borrow().get(&name).cloned();
+            match loaded {
+                Some(Some(value)) => return Ok(value),
+                Some(None) => return Err(failed("module imports itself".to_string())),
+                None => {}
+            }
+
+            let src = modules
+                .loader
+                .load(&name)
+                .ok_or_else(|| failed("module is not found".to_string()))?;
+            modules.loaded.borrow_mut().insert(name.clone(), None);
+            let result = run_module(vm, &name, &src);
+            match &result {
+                Ok(value) => modules
+                    .loaded
+                    .borrow_mut()
+                    .insert(name.clone(), Some(value.clone())),
+                // Failed modules can be fixed and imported again
+                Err(_) => modules.loaded.borrow_mut().remove(&name),
+            };
+            result.map_err(failed)
+        },
+    )
+}
+
+/// Runs the module in the VM that imports it, so it shares globals, the random generator,
+/// capabilities and output with the importing script. Instructions of the module are not counted
+/// by the runner of the importing script, so its budget doesn't cover them
+fn run_module(vm: &mut Vm, name: &str, src: &str) -> Result<Value, String> {
+    let ast = parse(name, src).map_err(|errors| errors[0].to_string())?;
+    let chunk = compile_chunk_with_result(ast);
+    // State of the importing script, which continues once the module finishes
+    let cursor = std::mem::replace(&mut vm.cursor, 0);
+    let stack = std::mem::replace(&mut vm.stack, vec![Value::Null; chunk.stack_size()]);
+    let returned = vm.returned.take();
+    let suspension = vm.suspension.take();
+
+    let result = DefaultRunner.run(&chunk, vm);
+    let value = std::mem::replace(&mut vm.returned, returned);
+    vm.cursor = cursor;
+    vm.stack = stack;
+    vm.suspension = suspension;
+    result.map_err(|err| err.to_string())?;
+    Ok(value.unwrap_or(Value::Null))
+}
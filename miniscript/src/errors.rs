@@ -6,11 +6,14 @@ use chumsky::error::Rich;
 use derive_more::Display;
 use std::fmt::Display;
 use std::ops::Range;
+use strum::{EnumMessage, IntoEnumIterator};
+use strum_macros::{EnumDiscriminants, EnumIter, EnumMessage, IntoStaticStr};
 use thiserror::Error;
 
 pub const CODE_INTERNAL: u16 = 0;
 pub const CODE_RUNTIME: u16 = 1000;
 pub const CODE_COMPILE: u16 = 2000;
+pub const CODE_LINT: u16 = 3000;
 
 #[derive(Debug, Error, Display)]
 #[display(fmt = "{}", error_type)]
@@ -38,6 +41,18 @@ impl MsError {
         }
     }
 
+    /// Location of the error in the source code. Errors raised at runtime need the chunk and the
+    /// VM they were raised in to be located
+    pub fn span(&self, chunk: Option<&Chunk>, vm: Option<&Vm>) -> Option<Range<usize>> {
+        match (&self.error_type, chunk, vm) {
+            (MsErrorType::Compile(item), _, _) => Some(item.span().clone()),
+            (_, Some(chunk), Some(vm)) => {
+                chunk.spans().get(vm.cursor).map(|span| span.into_range())
+            }
+            _ => None,
+        }
+    }
+
     pub fn report(&self, chunk: Option<&Chunk>, vm: Option<&Vm>) -> Report<(String, Range<usize>)> {
         match &self.error_type {
             MsErrorType::Internal(item) => item.report(&self.src_id, chunk, vm),
@@ -86,6 +101,8 @@ pub enum InternalError {
     NotImplemented,
     #[error("OpCode at {} is unpatched", .0)]
     UnpatchedOpCode(usize),
+    #[error("Code with syntax errors was executed")]
+    SyntaxError,
 }
 
 impl InternalError {
@@ -93,6 +110,7 @@ impl InternalError {
         match self {
             InternalError::NotImplemented => 0,
             InternalError::UnpatchedOpCode(_) => 1,
+            InternalError::SyntaxError => 2,
         }
     }
 
@@ -116,22 +134,157 @@ impl InternalError {
                 span,
                 "",
             ),
+            InternalError::SyntaxError => add_span_info(
+                report.with_message("Code with syntax errors was executed"),
+                src_id,
+                span,
+                "",
+            ),
         }
         .finish()
     }
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, EnumDiscriminants)]
+#[strum_discriminants(name(RuntimeErrorKind), derive(EnumIter, EnumMessage, IntoStaticStr))]
 #[non_exhaustive]
 pub enum RuntimeError {
     #[error("{}", .0)]
+    #[strum_discriminants(strum(message = "Error raised by the script itself"))]
     Custom(Value),
+    #[error("{}", .0)]
+    #[strum_discriminants(strum(
+        message = "Error raised by the bytecode with a constant message"
+    ))]
+    Message(String),
+    #[error("Type mismatch: expected {expected}, got {got}")]
+    #[strum_discriminants(strum(message = "Operation received a value of an unexpected type"))]
+    TypeMismatch {
+        expected: &'static str,
+        got: &'static str,
+    },
+    #[error("Index {index} is out of range for a sequence of length {len}")]
+    #[strum_discriminants(strum(message = "List or string index is outside of its bounds"))]
+    IndexOutOfRange { index: i64, len: usize },
+    #[error("Key `{}` is not found", .0)]
+    #[strum_discriminants(strum(message = "Map does not contain the requested key"))]
+    KeyNotFound(String),
+    #[error("Undefined identifier `{}`", .0)]
+    #[strum_discriminants(strum(message = "Variable is not defined in any visible scope"))]
+    UndefinedIdentifier(String),
+    #[error("Attempted to call a value of type {}", .0)]
+    #[strum_discriminants(strum(message = "Called value is not a function"))]
+    NotCallable(&'static str),
+    #[error("Too many arguments: expected at most {expected}, got {got}")]
+    #[strum_discriminants(strum(
+        message = "Function was called with more arguments than it accepts"
+    ))]
+    TooManyArguments { expected: usize, got: usize },
+    #[error("Stack overflow: call depth exceeded {}", .0)]
+    #[strum_discriminants(strum(message = "Call depth exceeded the VM limit"))]
+    StackOverflow(usize),
+    #[error("Instruction budget of {} is exhausted", .0)]
+    #[strum_discriminants(strum(message = "Script executed more instructions than allowed"))]
+    BudgetExceeded(usize),
+    #[error("Permission denied: {}", .0)]
+    #[strum_discriminants(strum(
+        message = "Script accessed a resource that the host didn't allow"
+    ))]
+    PermissionDenied(String),
+    #[error("I/O error: {}", .0)]
+    #[strum_discriminants(strum(message = "Reading or writing a file failed"))]
+    Io(String),
+    #[error("JSON conversion failed at `{path}`: {message}")]
+    #[strum_discriminants(strum(message = "Value could not be converted to or from JSON"))]
+    Json { path: String, message: String },
+    #[error("Module `{module}` could not be imported: {message}")]
+    #[strum_discriminants(strum(message = "Imported module failed to load, compile or run"))]
+    Import { module: String, message: String },
+    #[error("Number {number} can't be converted to {target} exactly")]
+    #[strum_discriminants(strum(
+        message = "Number does not fit into the numeric type of the host"
+    ))]
+    NumberOutOfRange { number: f64, target: &'static str },
+}
+
+impl RuntimeErrorKind {
+    fn raw_code(&self) -> u16 {
+        match self {
+            RuntimeErrorKind::Custom => 0,
+            RuntimeErrorKind::Message => 1,
+            RuntimeErrorKind::TypeMismatch => 2,
+            RuntimeErrorKind::IndexOutOfRange => 3,
+            RuntimeErrorKind::KeyNotFound => 4,
+            RuntimeErrorKind::UndefinedIdentifier => 5,
+            RuntimeErrorKind::NotCallable => 6,
+            RuntimeErrorKind::TooManyArguments => 7,
+            RuntimeErrorKind::StackOverflow => 8,
+            RuntimeErrorKind::BudgetExceeded => 9,
+            RuntimeErrorKind::PermissionDenied => 10,
+            RuntimeErrorKind::Io => 11,
+            RuntimeErrorKind::Json => 12,
+            RuntimeErrorKind::Import => 13,
+            RuntimeErrorKind::NumberOutOfRange => 14,
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.raw_code() + CODE_RUNTIME
+    }
 }
 
 impl RuntimeError {
     fn raw_code(&self) -> u16 {
+        RuntimeErrorKind::from(self).raw_code()
+    }
+
+    fn help(&self) -> Option<String> {
         match self {
-            RuntimeError::Custom(_) => 0,
+            RuntimeError::Custom(_) | RuntimeError::Message(_) => None,
+            RuntimeError::TypeMismatch { expected, .. } => Some(format!(
+                "Make sure that the value is a {expected} before using it here"
+            )),
+            RuntimeError::IndexOutOfRange { len, .. } => Some(format!(
+                "Valid indices are in range from -{len} to {}, check the length with `len` first",
+                *len as i64 - 1
+            )),
+            RuntimeError::KeyNotFound(_) => Some(
+                "Use `hasIndex` to check whether the key is present before accessing it"
+                    .to_string(),
+            ),
+            RuntimeError::UndefinedIdentifier(_) => Some(
+                "Check for typos, and make sure that the variable is assigned before it is used"
+                    .to_string(),
+            ),
+            RuntimeError::NotCallable(_) => {
+                Some("Only functions can be called with arguments".to_string())
+            }
+            RuntimeError::TooManyArguments { .. } => {
+                Some("Remove extra arguments, or add parameters to the function".to_string())
+            }
+            RuntimeError::StackOverflow(_) => {
+                Some("This is usually caused by unbounded recursion".to_string())
+            }
+            RuntimeError::BudgetExceeded(_) => {
+                Some("Check the script for infinite loops, or raise the budget".to_string())
+            }
+            RuntimeError::PermissionDenied(_) => Some(
+                "Ask the host to enable the capability, and keep paths inside of its root directory"
+                    .to_string(),
+            ),
+            RuntimeError::Io(_) => {
+                Some("Make sure that the file exists and is accessible".to_string())
+            }
+            RuntimeError::Json { .. } => Some(
+                "JSON has no functions, cycles, NaN or infinities, and parsed text must be valid"
+                    .to_string(),
+            ),
+            RuntimeError::Import { .. } => Some(
+                "Check that the module can be found by the loader, and runs on its own".to_string(),
+            ),
+            RuntimeError::NumberOutOfRange { target, .. } => Some(format!(
+                "Round the number with `floor` or `round`, and keep it within the range of {target}"
+            )),
         }
     }
 
@@ -142,12 +295,28 @@ impl RuntimeError {
         vm: Option<&Vm>,
     ) -> Report<(String, Range<usize>)> {
         let (report, span) = report_template(src_id, chunk, vm);
-        let report = report.with_code(self.code());
-        match self {
-            RuntimeError::Custom(msg) => add_span_info(report.with_message(msg), src_id, span, ""),
+        let mut report = report.with_code(self.code());
+        if let Some(help) = self.help() {
+            report = report.with_help(help);
         }
-        .finish()
+        let message = self.to_string();
+        add_span_info(report.with_message(&message), src_id, span, &message).finish()
+    }
+}
+
+/// Generates a markdown table of all runtime errors with their codes
+pub fn runtime_error_table() -> String {
+    let mut table = "| Code | Error | Description |\n|------|-------|-------------|\n".to_string();
+    for kind in RuntimeErrorKind::iter() {
+        let name: &'static str = kind.into();
+        table += &format!(
+            "| {} | {} | {} |\n",
+            kind.code(),
+            name,
+            kind.get_message().unwrap_or_default()
+        );
     }
+    table
 }
 
 #[derive(Debug, Error)]
@@ -188,6 +357,13 @@ impl CompileError {
         .finish()
     }
 
+    /// Location of the error in the source code
+    pub fn span(&self) -> &Range<usize> {
+        match self {
+            CompileError::Compilation(_, span, _) => span,
+        }
+    }
+
     pub fn from_compilation<T: Display>(error: Rich<T>) -> Self {
         Self::Compilation(
             error.reason().to_string(),
@@ -0,0 +1,148 @@
+use crate::ast::{Span, Spanned};
+use crate::errors::MsError;
+use crate::parse;
+use crate::parsing::parser::{lexer, Keyword, Token};
+use chumsky::Parser;
+
+const INDENT: &str = "\t";
+
+/// Formats the source code, normalizing indentation, spacing between tokens and blank lines.
+///
+/// Formatting works on tokens, so comments are kept, and the parsed code doesn't change. Code
+/// with syntax errors is not formatted, and its errors are returned instead.
+pub fn format(src_id: &str, src: &str) -> Result<String, Vec<MsError>> {
+    parse(src_id, src)?;
+    let tokens = lexer()
+        .parse(src)
+        .into_output()
+        .expect("Source code was parsed, but can't be tokenized");
+
+    let mut result = String::new();
+    let mut depth = 0usize;
+    let mut blank_line = false;
+    for line in tokens.split(|(token, _)| *token == Token::EOL) {
+        let Some((first, _)) = line.first() else {
+            // Consecutive blank lines are merged, and leading ones are removed
+            blank_line = !result.is_empty();
+            continue;
+        };
+        if blank_line {
+            result.push('\n');
+            blank_line = false;
+        }
+
+        let indent = match first {
+            Token::Keyword(Keyword::End | Keyword::Else) => depth.saturating_sub(1),
+            _ => depth,
+        };
+        result += &INDENT.repeat(indent);
+        result += &format_line(src, line);
+        result.push('\n');
+        depth = (depth + opened_blocks(line)).saturating_sub(closed_blocks(line));
+    }
+    Ok(result)
+}
+
+fn format_line(src: &str, line: &[Spanned<Token>]) -> String {
+    let mut result = String::new();
+    // Kinds of brackets that are currently open
+    let mut brackets = vec![];
+    for (i, (token, span)) in line.iter().enumerate() {
+        if i > 0 && needs_space(line, i, brackets.last()) {
+            result.push(' ');
+        }
+        result += src[span.into_range()].trim_end();
+        match token {
+            Token::LParen | Token::LSquare | Token::LCurly => brackets.push(token.clone()),
+            Token::RParen | Token::RSquare | Token::RCurly => {
+                brackets.pop();
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Whether a space goes between the token at `i` and the one before it
+fn needs_space(line: &[Spanned<Token>], i: usize, bracket: Option<&Token>) -> bool {
+    let (prev, prev_span) = &line[i - 1];
+    let (next, next_span) = &line[i];
+    match (prev, next) {
+        (_, Token::Comment(_)) => true,
+        (
+            _,
+            Token::Comma
+            | Token::Semicolon
+            | Token::Colon
+            | Token::Dot
+            | Token::RParen
+            | Token::RSquare
+            | Token::RCurly,
+        ) => false,
+        (Token::LParen | Token::LSquare | Token::LCurly | Token::Dot | Token::AddressOf, _) => {
+            false
+        }
+        // Slices are written without spaces, unlike maps
+        (Token::Colon, _) => bracket != Some(&Token::LSquare),
+        // Negation sticks to its operand, unless it would merge with another minus
+        (Token::OpMinus, next) if !follows_operand(line, i - 1) => *next == Token::OpMinus,
+        (Token::Keyword(Keyword::Function), Token::LParen) => false,
+        // Calls and indexing are kept as written, as they are commonly spaced for commands
+        (prev, Token::LParen | Token::LSquare) if is_operand_end(prev) => {
+            !is_adjacent(*prev_span, *next_span)
+        }
+        _ => true,
+    }
+}
+
+/// Whether the token at `i` is placed right after an operand, so a minus there is binary
+fn follows_operand(line: &[Spanned<Token>], i: usize) -> bool {
+    i > 0 && is_operand_end(&line[i - 1].0)
+}
+
+fn is_operand_end(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Number(_)
+            | Token::String(_)
+            | Token::Identifier(_)
+            | Token::RParen
+            | Token::RSquare
+            | Token::RCurly
+            | Token::Keyword(Keyword::Null | Keyword::True | Keyword::False)
+    )
+}
+
+fn is_adjacent(prev: Span, next: Span) -> bool {
+    prev.end == next.start
+}
+
+/// Number of blocks started on the line, which are indented until their `end`
+fn opened_blocks(line: &[Spanned<Token>]) -> usize {
+    let statement = match line.first() {
+        Some((Token::Keyword(Keyword::While | Keyword::For), _)) => 1,
+        // Single line `if` has its statement after `then`
+        Some((Token::Keyword(Keyword::If), _)) => usize::from(matches!(
+            line.iter()
+                .rev()
+                .find(|(token, _)| !matches!(token, Token::Comment(_))),
+            Some((Token::Keyword(Keyword::Then), _))
+        )),
+        _ => 0,
+    };
+    let functions = line
+        .iter()
+        .enumerate()
+        .filter(|(i, (token, _))| {
+            *token == Keyword::Function.token()
+                && (*i == 0 || line[i - 1].0 != Keyword::End.token())
+        })
+        .count();
+    statement + functions
+}
+
+fn closed_blocks(line: &[Spanned<Token>]) -> usize {
+    line.iter()
+        .filter(|(token, _)| *token == Keyword::End.token())
+        .count()
+}
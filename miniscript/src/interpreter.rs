@@ -0,0 +1,247 @@
+use crate::ast::{
+    BinaryOp, Body, Comparison, Expr, Path, Spanned, Statement, Value as Literal, AST,
+};
+use crate::errors::{InternalError, MsError, MsErrorType, RuntimeError};
+use crate::value::Value;
+use crate::vm::op_code::call_value;
+use crate::vm::Vm;
+use rustc_hash::FxHashMap;
+
+type EvalResult<T> = Result<T, MsErrorType>;
+
+/// Runs the AST directly, without compiling it to bytecode.
+///
+/// This is a reference implementation that is slow, but simple enough to be obviously correct.
+/// It's used to check the compiler for miscompilations by comparing results of both engines.
+/// Globals, intrinsics, RNG and printing are provided by the `vm`, and its stack is not used.
+pub fn interpret(ast: &AST, vm: &mut Vm) -> Result<(), MsError> {
+    let mut interpreter = Interpreter {
+        vm,
+        locals: Default::default(),
+    };
+    interpreter.body(ast.body()).map_err(|error_type| MsError {
+        src_id: ast.src_id().to_string(),
+        error_type,
+    })
+}
+
+struct Interpreter<'vm, 'src> {
+    vm: &'vm mut Vm,
+    locals: FxHashMap<&'src str, Value>,
+}
+
+impl<'vm, 'src> Interpreter<'vm, 'src> {
+    fn body(&mut self, body: &Body<'src>) -> EvalResult<()> {
+        for (statement, _) in body {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement<'src>) -> EvalResult<()> {
+        match statement {
+            Statement::Expression(expr) => {
+                self.expr(expr, false)?;
+            }
+            Statement::Assignment(lhs, rhs) => self.assignment(lhs, rhs)?,
+            Statement::If(chain, else_body) => {
+                for ((condition, _), body) in chain {
+                    if self.expr(condition, false)?.as_bool() {
+                        return self.body(body);
+                    }
+                }
+                if let Some(body) = else_body {
+                    self.body(body)?;
+                }
+            }
+            Statement::While((condition, _), body) => {
+                while self.expr(condition, false)?.as_bool() {
+                    self.body(body)?;
+                }
+            }
+            Statement::For(_, _, _)
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Return(_) => return Err(InternalError::NotImplemented.into()),
+            Statement::Error => return Err(InternalError::SyntaxError.into()),
+        }
+        Ok(())
+    }
+
+    fn assignment(
+        &mut self,
+        (lhs, _): &Spanned<Expr<'src>>,
+        rhs: &Spanned<Expr<'src>>,
+    ) -> EvalResult<()> {
+        match lhs {
+            Expr::Path(Path::AnyScope(ident)) => {
+                let value = self.expr(rhs, false)?;
+                self.locals.insert(ident, value);
+            }
+            Expr::Index(target, name) => {
+                let target = self.expr(target, false)?;
+                let value = self.expr(rhs, false)?;
+                target.set_index(Value::from(*name), value)?;
+            }
+            Expr::ExprIndex(target, index) => {
+                let target = self.expr(target, false)?;
+                let index = self.expr(index, false)?;
+                let value = self.expr(rhs, false)?;
+                target.set_index(index, value)?;
+            }
+            _ => unreachable!("Invalid assignment target"),
+        }
+        Ok(())
+    }
+
+    /// Evaluates an expression. Functions found by variable or member access are called, unless
+    /// `suppress_call` is set
+    fn expr(&mut self, (expr, _): &Spanned<Expr<'src>>, suppress_call: bool) -> EvalResult<Value> {
+        match expr {
+            Expr::Value(literal) => Ok(match literal {
+                Literal::Null => Value::Null,
+                Literal::Num(num) => Value::from(*num),
+                Literal::String(str) => Value::from(*str),
+                Literal::Boolean(condition) => Value::from(*condition),
+            }),
+            Expr::Path(Path::AnyScope(ident)) => {
+                let value = self.variable(ident)?;
+                if suppress_call {
+                    Ok(value)
+                } else {
+                    call_value(self.vm, value, vec![])
+                }
+            }
+            Expr::Comparison(lhs, chain) => self.comparison(lhs, chain),
+            Expr::Binary(lhs, op, rhs) => self.binary(lhs, *op, rhs),
+            Expr::Call(callee, args) => self.call(callee, args),
+            Expr::ExprIndex(target, index) => {
+                let target = self.expr(target, false)?;
+                let index = self.expr(index, false)?;
+                Ok(target.get_index(&index)?)
+            }
+            Expr::Index(target, name) => {
+                if suppress_call {
+                    let target = self.expr(target, false)?;
+                    Ok(target.get_index(&Value::from(*name))?)
+                } else {
+                    self.method_call(target, name, &[])
+                }
+            }
+            Expr::List(_) | Expr::Map(_) | Expr::FunctionDefinition(_, _) | Expr::Unary(_, _) => {
+                Err(InternalError::NotImplemented.into())
+            }
+            Expr::Error => Err(InternalError::SyntaxError.into()),
+        }
+    }
+
+    fn variable(&self, ident: &str) -> EvalResult<Value> {
+        if let Some(value) = self.locals.get(ident) {
+            return Ok(value.clone());
+        }
+        self.vm
+            .get_global(ident)
+            .cloned()
+            .ok_or_else(|| RuntimeError::UndefinedIdentifier(ident.to_string()).into())
+    }
+
+    fn comparison(
+        &mut self,
+        lhs: &Spanned<Expr<'src>>,
+        chain: &[(Comparison, Spanned<Expr<'src>>)],
+    ) -> EvalResult<Value> {
+        let mut lhs = self.expr(lhs, false)?;
+        if let [(comparison, rhs)] = chain {
+            let rhs = self.expr(rhs, false)?;
+            return Ok(compare(*comparison, &lhs, &rhs));
+        }
+
+        // Chain holds when every comparison holds. All operands are evaluated regardless
+        let mut result = Value::from(true);
+        for (comparison, rhs) in chain {
+            let rhs = self.expr(rhs, false)?;
+            result = result.and(&compare(*comparison, &lhs, &rhs));
+            lhs = rhs;
+        }
+        Ok(result)
+    }
+
+    fn binary(
+        &mut self,
+        lhs: &Spanned<Expr<'src>>,
+        op: BinaryOp,
+        rhs: &Spanned<Expr<'src>>,
+    ) -> EvalResult<Value> {
+        let lhs = self.expr(lhs, false)?;
+        // Logic operators short-circuit when the left side is fully false or fully true
+        match op {
+            BinaryOp::And if !lhs.as_bool() => return Ok(Value::from(0.)),
+            BinaryOp::Or if lhs.as_f64() >= 1. => return Ok(Value::from(1.)),
+            _ => {}
+        }
+        let rhs = self.expr(rhs, false)?;
+        Ok(match op {
+            BinaryOp::Or => lhs.fuzzy_or(&rhs),
+            BinaryOp::And => lhs.fuzzy_and(&rhs),
+            BinaryOp::Add => lhs + rhs,
+            BinaryOp::Sub => lhs - rhs,
+            BinaryOp::Mul => lhs * rhs,
+            BinaryOp::Div => lhs / rhs,
+            BinaryOp::Pow => lhs.pow(&rhs),
+        })
+    }
+
+    fn call(
+        &mut self,
+        callee: &Spanned<Expr<'src>>,
+        args: &[Spanned<Expr<'src>>],
+    ) -> EvalResult<Value> {
+        if let Expr::Path(Path::AnyScope("print")) = callee.0 {
+            let [arg] = args else {
+                return Err(InternalError::NotImplemented.into());
+            };
+            let value = self.expr(arg, false)?;
+            self.vm.print(&value);
+            return Ok(Value::Null);
+        }
+
+        if let Expr::Index(target, name) = &callee.0 {
+            return self.method_call(target, name, args);
+        }
+
+        let function = self.expr(callee, true)?;
+        let args = self.arguments(args)?;
+        call_value(self.vm, function, args)
+    }
+
+    fn method_call(
+        &mut self,
+        target: &Spanned<Expr<'src>>,
+        name: &str,
+        args: &[Spanned<Expr<'src>>],
+    ) -> EvalResult<Value> {
+        let this = self.expr(target, false)?;
+        let function = this.get_index(&Value::from(name))?;
+        let mut args = self.arguments(args)?;
+        // Object is only passed to functions that accept `self`
+        if matches!(&function, Value::Function(func) if func.is_method()) {
+            args.insert(0, this);
+        }
+        call_value(self.vm, function, args)
+    }
+
+    fn arguments(&mut self, args: &[Spanned<Expr<'src>>]) -> EvalResult<Vec<Value>> {
+        args.iter().map(|arg| self.expr(arg, false)).collect()
+    }
+}
+
+fn compare(comparison: Comparison, lhs: &Value, rhs: &Value) -> Value {
+    match comparison {
+        Comparison::Eq => Value::from(lhs == rhs),
+        Comparison::NotEq => Value::from(lhs != rhs),
+        Comparison::Gt => lhs.gt(rhs),
+        Comparison::Lt => lhs.lt(rhs),
+        Comparison::GtEq => lhs.gte(rhs),
+        Comparison::LtEq => lhs.lte(rhs),
+    }
+}
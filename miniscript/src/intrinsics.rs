@@ -0,0 +1,126 @@
+use crate::bindings::FromValue;
+use crate::errors::RuntimeError;
+use crate::intrinsics::json::json_intrinsics;
+use crate::value::Value;
+use crate::vm::Vm;
+use rand::Rng;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+pub mod json;
+pub mod sandboxed;
+
+/// Name of the parameter that receives the object on method calls
+pub const SELF_PARAM: &str = "self";
+
+pub type IntrinsicFn = dyn Fn(&mut Vm, Vec<Value>) -> Result<Value, RuntimeError>;
+
+#[derive(Debug, Clone)]
+pub struct IntrinsicParam {
+    pub name: String,
+    pub default_value: Value,
+}
+
+impl IntrinsicParam {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            default_value: Value::Null,
+        }
+    }
+
+    pub fn with_default(name: impl Into<String>, default_value: impl Into<Value>) -> Self {
+        Self {
+            name: name.into(),
+            default_value: default_value.into(),
+        }
+    }
+}
+
+/// Function implemented on the host side and callable from scripts
+pub struct Intrinsic {
+    name: String,
+    params: Vec<IntrinsicParam>,
+    func: Box<IntrinsicFn>,
+}
+
+impl Intrinsic {
+    pub fn new(
+        name: impl Into<String>,
+        params: Vec<IntrinsicParam>,
+        func: impl Fn(&mut Vm, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            params,
+            func: Box::new(func),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn params(&self) -> &[IntrinsicParam] {
+        &self.params
+    }
+
+    /// Whether the first parameter of this intrinsic receives the object it was called on
+    pub fn is_method(&self) -> bool {
+        self.params
+            .first()
+            .map(|param| param.name == SELF_PARAM)
+            .unwrap_or(false)
+    }
+
+    /// Calls the intrinsic, filling missing arguments with parameter defaults
+    pub fn invoke(&self, vm: &mut Vm, mut args: Vec<Value>) -> Result<Value, RuntimeError> {
+        if args.len() > self.params.len() {
+            // The object is passed implicitly, so it's not counted towards the arguments
+            let implicit = usize::from(self.is_method());
+            return Err(RuntimeError::TooManyArguments {
+                expected: self.params.len() - implicit,
+                got: args.len() - implicit,
+            });
+        }
+        args.extend(
+            self.params[args.len()..]
+                .iter()
+                .map(|param| param.default_value.clone()),
+        );
+        (self.func)(vm, args)
+    }
+}
+
+impl Debug for Intrinsic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Intrinsic")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<Intrinsic> for Value {
+    fn from(value: Intrinsic) -> Self {
+        Value::Function(Rc::new(value))
+    }
+}
+
+/// Intrinsics that are available to every script
+pub fn standard_intrinsics() -> Vec<Intrinsic> {
+    let mut intrinsics = vec![Intrinsic::new(
+        "rnd",
+        vec![IntrinsicParam::with_default("seed", 0)],
+        // Returns a number in [0, 1), reseeding the generator first if non-zero seed is given
+        |vm, args| {
+            let seed = i64::from_value(&args[0])?;
+            if seed != 0 {
+                vm.seed_rng(seed as u64);
+            }
+            Ok(Value::from(vm.rng.gen::<f64>()))
+        },
+    )];
+    intrinsics.extend(json_intrinsics());
+    intrinsics
+}
@@ -0,0 +1,130 @@
+use crate::bindings::FromValue;
+use crate::errors::RuntimeError;
+use crate::intrinsics::{Intrinsic, IntrinsicParam};
+use crate::value::Value;
+use std::fmt::{Display, Formatter};
+
+pub(crate) fn json_intrinsics() -> Vec<Intrinsic> {
+    vec![
+        Intrinsic::new(
+            "toJSON",
+            vec![
+                IntrinsicParam::new("value"),
+                IntrinsicParam::with_default("pretty", 0),
+            ],
+            |_, args| to_json(&args[0], args[1].as_bool()).map(Value::from),
+        ),
+        Intrinsic::new("parseJSON", vec![IntrinsicParam::new("text")], |_, args| {
+            parse_json(&String::from_value(&args[0])?)
+        }),
+    ]
+}
+
+/// Converts the value into JSON text, indenting it when `pretty` is set.
+///
+/// Fails on functions, on lists and maps that contain themselves, and on numbers that are not
+/// finite, which `serde_json` would silently write as `null`.
+pub fn to_json(value: &Value, pretty: bool) -> Result<String, RuntimeError> {
+    let mut path = vec![];
+    if let Some(message) = find_unsupported(value, &mut vec![], &mut path) {
+        return Err(RuntimeError::Json {
+            path: Segments(&path).to_string(),
+            message,
+        });
+    }
+
+    let mut json = vec![];
+    let result = if pretty {
+        serde_path_to_error::serialize(value, &mut serde_json::Serializer::pretty(&mut json))
+    } else {
+        serde_path_to_error::serialize(value, &mut serde_json::Serializer::new(&mut json))
+    };
+    result.map_err(|err| RuntimeError::Json {
+        path: err.path().to_string(),
+        message: err.inner().to_string(),
+    })?;
+    Ok(String::from_utf8(json).expect("JSON is not valid UTF-8"))
+}
+
+/// Parses JSON text, with booleans turning into numbers like everywhere else in scripts
+pub fn parse_json(text: &str) -> Result<Value, RuntimeError> {
+    let mut deserializer = serde_json::Deserializer::from_str(text);
+    let value =
+        serde_path_to_error::deserialize(&mut deserializer).map_err(|err| RuntimeError::Json {
+            path: err.path().to_string(),
+            message: err.inner().to_string(),
+        })?;
+    // Only whitespace may follow the value
+    deserializer.end().map_err(|err| RuntimeError::Json {
+        path: ".".to_string(),
+        message: err.to_string(),
+    })?;
+    Ok(value)
+}
+
+enum Segment {
+    Index(usize),
+    Key(String),
+}
+
+/// Path formatted like the ones of `serde_path_to_error`, as in `a.b[0]`
+struct Segments<'a>(&'a [Segment]);
+
+impl Display for Segments<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, ".");
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Index(index) => write!(f, "[{index}]")?,
+                Segment::Key(key) if i == 0 => write!(f, "{key}")?,
+                Segment::Key(key) => write!(f, ".{key}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds a list or map that contains itself, or a number that is not finite, returning what's
+/// wrong with it and leaving the path to it in `path`.
+///
+/// Values shared by several parents without forming a cycle are fine, and are written out in
+/// full at each place.
+fn find_unsupported(
+    value: &Value,
+    ancestors: &mut Vec<*const ()>,
+    path: &mut Vec<Segment>,
+) -> Option<String> {
+    if let Value::Number(num) = value {
+        return (!num.is_finite()).then(|| format!("number {num} can't be represented in JSON"));
+    }
+    let pointer = value.container_ptr()?;
+    if ancestors.contains(&pointer) {
+        return Some("value contains itself".to_string());
+    }
+
+    ancestors.push(pointer);
+    let mut visit = |segment: Segment, item: &Value| {
+        path.push(segment);
+        let found = find_unsupported(item, ancestors, path);
+        if found.is_none() {
+            path.pop();
+        }
+        found
+    };
+    let found = match value {
+        Value::List(list) => list
+            .borrow()
+            .iter()
+            .enumerate()
+            .find_map(|(i, item)| visit(Segment::Index(i), item)),
+        Value::Map(map) => map
+            .borrow()
+            .iter()
+            .find_map(|(key, item)| visit(Segment::Key(key.to_string()), item)),
+        _ => None,
+    };
+    ancestors.pop();
+    found
+}
@@ -0,0 +1,142 @@
+use crate::bindings::FromValue;
+use crate::errors::RuntimeError;
+use crate::intrinsics::{Intrinsic, IntrinsicParam};
+use crate::value::{Value, ValueMap};
+use crate::vm::Vm;
+use bitflags::bitflags;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+bitflags! {
+    /// Optional intrinsic modules, defined only on VMs that the host enabled them for
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+    pub struct Capabilities: u8 {
+        /// `file.read`, restricted to the file root of the VM
+        const FILE_READ = 1;
+        /// `file.write`, restricted to the file root of the VM
+        const FILE_WRITE = 1 << 1;
+        /// `time` and `wait`
+        const TIME = 1 << 2;
+    }
+}
+
+/// Defines globals of the modules enabled on the VM
+pub(crate) fn register_modules(vm: &mut Vm) {
+    let mut file = ValueMap::default();
+    if vm.capabilities.contains(Capabilities::FILE_READ) {
+        insert(&mut file, file_read());
+    }
+    if vm.capabilities.contains(Capabilities::FILE_WRITE) {
+        insert(&mut file, file_write());
+    }
+    if !file.is_empty() {
+        vm.set_global("file", Value::new_map(file));
+    }
+    if vm.capabilities.contains(Capabilities::TIME) {
+        vm.register_intrinsic(time());
+        vm.register_intrinsic(wait());
+    }
+}
+
+fn insert(module: &mut ValueMap, intrinsic: Intrinsic) {
+    module.insert(Value::from(intrinsic.name()), intrinsic.into());
+}
+
+fn file_read() -> Intrinsic {
+    Intrinsic::new("read", vec![IntrinsicParam::new("path")], |vm, args| {
+        check(vm, Capabilities::FILE_READ, "file.read")?;
+        let path = resolve(vm, &String::from_value(&args[0])?)?;
+        fs::read_to_string(path)
+            .map(Value::from)
+            .map_err(|err| RuntimeError::Io(err.to_string()))
+    })
+}
+
+fn file_write() -> Intrinsic {
+    Intrinsic::new(
+        "write",
+        vec![IntrinsicParam::new("path"), IntrinsicParam::new("content")],
+        |vm, args| {
+            check(vm, Capabilities::FILE_WRITE, "file.write")?;
+            let path = resolve(vm, &String::from_value(&args[0])?)?;
+            fs::write(path, String::from_value(&args[1])?)
+                .map(|()| Value::Null)
+                .map_err(|err| RuntimeError::Io(err.to_string()))
+        },
+    )
+}
+
+/// Seconds since the VM was created
+fn time() -> Intrinsic {
+    Intrinsic::new("time", vec![], |vm, _| {
+        check(vm, Capabilities::TIME, "time")?;
+        Ok(Value::Number(vm.started.elapsed().as_secs_f64()))
+    })
+}
+
+/// Suspends the script for the given number of seconds, when it's run by `ResumableRunner`
+fn wait() -> Intrinsic {
+    Intrinsic::new(
+        "wait",
+        vec![IntrinsicParam::with_default("seconds", 1)],
+        |vm, args| {
+            check(vm, Capabilities::TIME, "wait")?;
+            // Negative durations don't wait at all
+            let seconds = f64::from_value(&args[0])?;
+            vm.suspension = Some(Duration::try_from_secs_f64(seconds).unwrap_or_default());
+            Ok(Value::Null)
+        },
+    )
+}
+
+/// Intrinsics stay callable after being copied into another VM, so capabilities are checked on
+/// every call too
+fn check(vm: &Vm, capability: Capabilities, name: &str) -> Result<(), RuntimeError> {
+    if vm.capabilities.contains(capability) {
+        Ok(())
+    } else {
+        Err(RuntimeError::PermissionDenied(format!(
+            "`{name}` is not enabled"
+        )))
+    }
+}
+
+/// Resolves the path relative to the file root, rejecting paths that lead outside of it
+fn resolve(vm: &Vm, path: &str) -> Result<PathBuf, RuntimeError> {
+    let Some(root) = &vm.file_root else {
+        return Err(RuntimeError::PermissionDenied(
+            "no root directory is set for files".to_string(),
+        ));
+    };
+    let relative = Path::new(path);
+    let outside = || RuntimeError::PermissionDenied(format!("`{path}` is outside of the root"));
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+
+    // Symbolic links inside of the root may still point outside of it. Files that don't exist
+    // yet are checked by their directory, while dangling links are rejected, since writing
+    // through them would create their target wherever it is
+    let io_error = |err: std::io::Error| RuntimeError::Io(err.to_string());
+    let root = root.canonicalize().map_err(io_error)?;
+    let full = root.join(relative);
+    let existing = if full.exists() {
+        full.as_path()
+    } else if full.symlink_metadata().is_ok() {
+        return Err(outside());
+    } else {
+        full.parent().unwrap_or(&root)
+    };
+    if !existing
+        .canonicalize()
+        .map_err(io_error)?
+        .starts_with(&root)
+    {
+        return Err(outside());
+    }
+    Ok(full)
+}
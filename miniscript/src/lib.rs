@@ -0,0 +1,82 @@
+use crate::ast::AST;
+use crate::errors::{CompileError, MsError};
+use crate::parsing::ast_parser::ast_parser;
+use crate::parsing::parser::{lexer, Token};
+use crate::vm::chunk::{compile_chunk, Chunk};
+use chumsky::error::Rich;
+use chumsky::prelude::Input;
+use chumsky::Parser;
+use std::fmt::Display;
+
+// Allows derive macros to refer to `::miniscript` from inside of this crate
+extern crate self as miniscript;
+
+pub use miniscript_macro::{script_methods, ScriptMap};
+
+pub mod ast;
+pub mod bindings;
+pub mod diagnostics;
+pub mod engine;
+pub mod errors;
+pub mod format;
+pub mod interpreter;
+pub mod intrinsics;
+pub mod lint;
+pub mod parsing;
+pub mod symbol;
+#[cfg(test)]
+pub mod tests;
+pub mod value;
+pub mod vm;
+
+fn format_errors<T: Display>(errors: Vec<Rich<T>>) -> Vec<CompileError> {
+    errors
+        .into_iter()
+        .map(CompileError::from_compilation)
+        .collect()
+}
+
+/// Parses the source code into an AST, reporting all lexer and parser errors at once
+pub fn parse<'src>(src_id: &str, src: &'src str) -> Result<AST<'src>, Vec<MsError>> {
+    let lexer = lexer();
+    let (tokens, lexer_errors) = lexer.parse(src).into_output_errors();
+    let mut errors = format_errors(lexer_errors);
+
+    // Lexer recovers from invalid characters, so parsing continues to report all errors at once
+    let ast = tokens.and_then(|tokens| {
+        let stripped = tokens
+            .into_iter()
+            .filter(|token| !matches!(token.0, Token::Comment(_)))
+            .collect::<Vec<_>>();
+        let spanned = stripped.spanned((src.len()..src.len()).into());
+
+        let ast_parser = ast_parser();
+
+        let (ast, parser_errors) = ast_parser.parse(spanned).into_output_errors();
+        errors.extend(format_errors(parser_errors));
+        ast
+    });
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|err| err.span().start);
+        return Err(errors
+            .into_iter()
+            .map(|err| MsError {
+                error_type: err.into(),
+                src_id: src_id.to_string(),
+            })
+            .collect());
+    }
+
+    let ast = ast.expect("AST output is none, but no errors were emitted either");
+
+    AST::from_body(ast, src_id.to_string()).map_err(|err| vec![err])
+}
+
+pub fn compile(src_id: &str, src: &str) -> Result<Chunk, Vec<MsError>> {
+    let ast = parse(src_id, src)?;
+
+    let chunk = compile_chunk(ast);
+
+    Ok(chunk)
+}
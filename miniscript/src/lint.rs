@@ -0,0 +1,432 @@
+use crate::ast::{Body, Expr, FunctionArgument, Path, Span, Spanned, Statement};
+use crate::errors::{MsError, CODE_LINT};
+use crate::intrinsics::standard_intrinsics;
+use crate::parse;
+use crate::parsing::parser::{lexer, Token};
+use crate::vm::chunk::can_have_side_effects;
+use ariadne::{Color, Label, Report, ReportKind};
+use chumsky::Parser;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::ops::Range;
+use strum::EnumMessage;
+use strum_macros::{EnumMessage, IntoStaticStr};
+
+/// Kinds of issues reported by the linter.
+///
+/// Warnings are suppressed with a `// lint-allow: <name or code>, ...` comment, placed either at
+/// the end of the offending line, or on a separate line before it
+#[derive(Copy, Clone, Debug, Eq, PartialEq, EnumMessage, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum LintKind {
+    #[strum(message = "Variable is assigned, but its value is never read")]
+    UnusedVariable,
+    #[strum(message = "Assigned value is overwritten before it is read")]
+    UnreadAssignment,
+    #[strum(message = "Expression statement has no effect and is skipped by the compiler")]
+    NoEffect,
+    #[strum(message = "Value is compared with itself")]
+    SelfComparison,
+    #[strum(message = "Code after `return`, `break` or `continue` is never executed")]
+    UnreachableCode,
+    #[strum(message = "Assignment hides a built-in function")]
+    ShadowedIntrinsic,
+}
+
+impl LintKind {
+    fn raw_code(&self) -> u16 {
+        match self {
+            LintKind::UnusedVariable => 0,
+            LintKind::UnreadAssignment => 1,
+            LintKind::NoEffect => 2,
+            LintKind::SelfComparison => 3,
+            LintKind::UnreachableCode => 4,
+            LintKind::ShadowedIntrinsic => 5,
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.raw_code() + CODE_LINT
+    }
+
+    /// Name used to suppress the warning
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LintWarning {
+    pub kind: LintKind,
+    pub message: String,
+    pub span: Span,
+}
+
+impl LintWarning {
+    pub fn code(&self) -> u16 {
+        self.kind.code()
+    }
+
+    pub fn report(&self, src_id: &str) -> Report<(String, Range<usize>)> {
+        let span = self.span.into_range();
+        Report::build(ReportKind::Warning, src_id.to_string(), span.start)
+            .with_code(self.code())
+            .with_message(&self.message)
+            .with_label(
+                Label::new((src_id.to_string(), span))
+                    .with_message(self.kind.get_message().unwrap_or_default())
+                    .with_color(Color::Yellow),
+            )
+            .with_help(format!(
+                "Add `// lint-allow: {}` to suppress this warning",
+                self.kind.name()
+            ))
+            .finish()
+    }
+}
+
+/// Checks the source code for suspicious constructs that are valid, but likely to be mistakes.
+///
+/// Code with syntax errors is not linted, and its errors are returned instead.
+pub fn lint(src_id: &str, src: &str) -> Result<Vec<LintWarning>, Vec<MsError>> {
+    let ast = parse(src_id, src)?;
+    let mut linter = Linter {
+        // `print` is compiled into a dedicated instruction, but it behaves as an intrinsic
+        intrinsics: standard_intrinsics()
+            .iter()
+            .map(|intrinsic| intrinsic.name().to_string())
+            .chain(["print".to_string()])
+            .collect(),
+        warnings: vec![],
+    };
+    linter.scope(&[], ast.body(), true);
+
+    let suppressions = Suppressions::new(src);
+    let mut warnings = linter.warnings;
+    warnings.retain(|warning| !suppressions.is_suppressed(warning));
+    warnings.sort_by_key(|warning| warning.span.start);
+    Ok(warnings)
+}
+
+/// Variables assigned and read within a single function, or the top level code
+#[derive(Default)]
+struct Usage<'src> {
+    /// First assignment of each variable, in order of appearance
+    assigned: Vec<(&'src str, Span)>,
+    /// Parameters and loop variables, which are not reported when unused
+    bound: FxHashSet<&'src str>,
+    reads: FxHashSet<&'src str>,
+}
+
+impl<'src> Usage<'src> {
+    fn assign(&mut self, name: &'src str, span: Span) {
+        if !self.is_local(name) {
+            self.assigned.push((name, span));
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.bound.contains(name) || self.assigned.iter().any(|(local, _)| *local == name)
+    }
+}
+
+#[derive(Default)]
+struct Linter {
+    intrinsics: FxHashSet<String>,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter {
+    fn warn(&mut self, kind: LintKind, message: String, span: Span) {
+        self.warnings.push(LintWarning {
+            kind,
+            message,
+            span,
+        });
+    }
+
+    /// Lints the body of a function or the top level code, returning the variables it reads
+    /// from the outer scopes
+    fn scope<'src>(
+        &mut self,
+        params: &[FunctionArgument<'src>],
+        body: &Body<'src>,
+        global: bool,
+    ) -> FxHashSet<&'src str> {
+        let mut usage = Usage::default();
+        usage.bound.extend(params.iter().map(|param| param.name.0));
+        self.body(body, &mut usage, global);
+
+        for (name, span) in &usage.assigned {
+            if !usage.reads.contains(name) {
+                self.warn(
+                    LintKind::UnusedVariable,
+                    format!("Variable `{name}` is never read"),
+                    *span,
+                );
+            }
+        }
+
+        usage
+            .reads
+            .iter()
+            .filter(|name| !usage.is_local(name))
+            .copied()
+            .collect()
+    }
+
+    fn body<'src>(&mut self, body: &Body<'src>, usage: &mut Usage<'src>, global: bool) {
+        self.unreachable_code(body);
+        self.unread_assignments(body, global);
+        for (statement, _) in body {
+            self.statement(statement, usage, global);
+        }
+    }
+
+    fn statement<'src>(
+        &mut self,
+        statement: &Statement<'src>,
+        usage: &mut Usage<'src>,
+        global: bool,
+    ) {
+        match statement {
+            Statement::Assignment(lhs, rhs) => {
+                if let Expr::Path(Path::AnyScope(name)) = lhs.0 {
+                    if self.intrinsics.contains(name) {
+                        self.warn(
+                            LintKind::ShadowedIntrinsic,
+                            format!("Assignment to `{name}` hides the built-in function"),
+                            lhs.1,
+                        );
+                    }
+                    usage.assign(name, lhs.1);
+                } else {
+                    // Indexed assignments read the object they modify
+                    self.expr(lhs, usage);
+                }
+                self.expr(rhs, usage);
+            }
+            Statement::Expression(expr) => {
+                if !can_have_side_effects(&expr.0, false) {
+                    self.warn(
+                        LintKind::NoEffect,
+                        "Expression statement has no effect".to_string(),
+                        expr.1,
+                    );
+                }
+                self.expr(expr, usage);
+            }
+            Statement::If(chain, else_body) => {
+                for ((condition, _), body) in chain {
+                    self.expr(condition, usage);
+                    self.body(body, usage, global);
+                }
+                if let Some(body) = else_body {
+                    self.body(body, usage, global);
+                }
+            }
+            Statement::While((condition, _), body) => {
+                self.expr(condition, usage);
+                self.body(body, usage, global);
+            }
+            Statement::For((name, _), iterable, body) => {
+                usage.bound.insert(name);
+                self.expr(iterable, usage);
+                self.body(body, usage, global);
+            }
+            Statement::Return(Some(expr)) => self.expr(expr, usage),
+            Statement::Return(None) | Statement::Break | Statement::Continue | Statement::Error => {
+            }
+        }
+    }
+
+    fn expr<'src>(&mut self, (expr, _): &Spanned<Expr<'src>>, usage: &mut Usage<'src>) {
+        match expr {
+            Expr::Value(_) | Expr::Error => {}
+            Expr::Path(Path::AnyScope(name)) => {
+                usage.reads.insert(name);
+            }
+            Expr::List(items) => {
+                for item in items {
+                    self.expr(item, usage);
+                }
+            }
+            Expr::Map(items) => {
+                for (key, value) in items {
+                    self.expr(key, usage);
+                    self.expr(value, usage);
+                }
+            }
+            Expr::FunctionDefinition(params, body) => {
+                for default in params
+                    .iter()
+                    .filter_map(|param| param.default_value.as_ref())
+                {
+                    self.expr(default, usage);
+                }
+                // Variables that the function doesn't define itself come from the outer scopes
+                let outer_reads = self.scope(params, body, false);
+                usage.reads.extend(outer_reads);
+            }
+            Expr::Comparison(lhs, chain) => {
+                let operands = std::iter::once(&**lhs).chain(chain.iter().map(|(_, rhs)| rhs));
+                for (lhs, rhs) in operands.clone().zip(operands.skip(1)) {
+                    if let Some(name) = same_path(&lhs.0, &rhs.0) {
+                        self.warn(
+                            LintKind::SelfComparison,
+                            format!("`{name}` is compared with itself"),
+                            Span::from(lhs.1.start..rhs.1.end),
+                        );
+                    }
+                }
+                self.expr(lhs, usage);
+                for (_, rhs) in chain {
+                    self.expr(rhs, usage);
+                }
+            }
+            Expr::Binary(lhs, _, rhs) | Expr::ExprIndex(lhs, rhs) => {
+                self.expr(lhs, usage);
+                self.expr(rhs, usage);
+            }
+            Expr::Unary(_, expr) | Expr::Index(expr, _) => self.expr(expr, usage),
+            Expr::Call(callee, args) => {
+                self.expr(callee, usage);
+                for arg in args {
+                    self.expr(arg, usage);
+                }
+            }
+        }
+    }
+
+    fn unreachable_code(&mut self, body: &Body) {
+        let exit = body.iter().position(|(statement, _)| {
+            matches!(
+                statement,
+                Statement::Return(_) | Statement::Break | Statement::Continue
+            )
+        });
+        let (Some(exit), Some((_, last))) = (exit, body.last()) else {
+            return;
+        };
+        if let Some((_, first)) = body.get(exit + 1) {
+            self.warn(
+                LintKind::UnreachableCode,
+                "Unreachable code".to_string(),
+                Span::from(first.start..last.end),
+            );
+        }
+    }
+
+    /// Finds assignments that are followed by another assignment to the same variable, with no
+    /// reads in between
+    fn unread_assignments(&mut self, body: &Body, global: bool) {
+        for (i, (statement, _)) in body.iter().enumerate() {
+            let Statement::Assignment((Expr::Path(Path::AnyScope(name)), span), _) = statement
+            else {
+                continue;
+            };
+            for (next, _) in &body[i + 1..] {
+                if statement_reads(next, name)
+                    // Functions can read globals, so any call might observe the value
+                    || (global && statement_can_call(next))
+                {
+                    break;
+                }
+                match next {
+                    Statement::Assignment((Expr::Path(Path::AnyScope(next_name)), _), _)
+                        if next_name == name =>
+                    {
+                        self.warn(
+                            LintKind::UnreadAssignment,
+                            format!("Value assigned to `{name}` is overwritten before being read"),
+                            *span,
+                        );
+                        break;
+                    }
+                    Statement::Assignment(_, _) | Statement::Expression(_) => {}
+                    // Control flow may skip the reassignment
+                    _ => break,
+                }
+            }
+        }
+    }
+}
+
+/// Name of the variable or member path, if both expressions refer to the same one
+fn same_path(lhs: &Expr, rhs: &Expr) -> Option<String> {
+    match (lhs, rhs) {
+        (Expr::Path(Path::AnyScope(lhs)), Expr::Path(Path::AnyScope(rhs))) if lhs == rhs => {
+            Some(lhs.to_string())
+        }
+        (Expr::Index(lhs, lhs_name), Expr::Index(rhs, rhs_name)) if lhs_name == rhs_name => {
+            same_path(&lhs.0, &rhs.0).map(|path| format!("{path}.{lhs_name}"))
+        }
+        _ => None,
+    }
+}
+
+fn statement_reads(statement: &Statement, name: &str) -> bool {
+    // Warnings of the throwaway linter are discarded, it's only used to collect the reads
+    let mut usage = Usage::default();
+    Linter::default().statement(statement, &mut usage, false);
+    usage.reads.contains(name)
+}
+
+fn statement_can_call(statement: &Statement) -> bool {
+    match statement {
+        Statement::Assignment((Expr::Path(_), _), (rhs, _)) => can_have_side_effects(rhs, false),
+        Statement::Expression((expr, _)) => can_have_side_effects(expr, false),
+        _ => true,
+    }
+}
+
+/// Lines with suppressed warnings, read from `// lint-allow: ...` comments
+struct Suppressions {
+    /// Offsets of line starts, used to find the line of a span
+    line_starts: Vec<usize>,
+    /// Names or codes of warnings allowed on each line
+    allowed: FxHashMap<usize, Vec<String>>,
+}
+
+impl Suppressions {
+    fn new(src: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let mut result = Self {
+            line_starts,
+            allowed: Default::default(),
+        };
+        // Source was already parsed successfully, so lexing it again produces the tokens
+        let tokens = lexer().parse(src).into_output().unwrap_or_default();
+        for (token, span) in tokens {
+            let Token::Comment(comment) = token else {
+                continue;
+            };
+            let Some(entries) = comment.trim().strip_prefix("lint-allow:") else {
+                continue;
+            };
+            let entries = entries.split(',').map(|entry| entry.trim().to_string());
+            // Comment on a separate line applies to the next line, otherwise to its own line
+            let mut line = result.line(span.start);
+            if src[result.line_starts[line]..span.start].trim().is_empty() {
+                line += 1;
+            }
+            result.allowed.entry(line).or_default().extend(entries);
+        }
+        result
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset) - 1
+    }
+
+    fn is_suppressed(&self, warning: &LintWarning) -> bool {
+        let Some(allowed) = self.allowed.get(&self.line(warning.span.start)) else {
+            return false;
+        };
+        let code = warning.code().to_string();
+        allowed
+            .iter()
+            .any(|entry| entry == warning.kind.name() || *entry == code)
+    }
+}
@@ -1,97 +1,415 @@
-use crate::ast::{Spanned, AST};
-use crate::errors::{CompileError, MsError, MsErrorType};
-use crate::parsing::ast_parser::ast_parser;
-use crate::parsing::parser::{lexer, Token};
-use crate::value::Value;
-use crate::vm::chunk::{compile_chunk, pretty_print, Chunk};
-use crate::vm::{DefaultRunner, Vm, VmRunner};
-use ariadne::{sources, Color, ColorGenerator, Label, Report, ReportKind, Source};
-use chumsky::error::Rich;
-use chumsky::prelude::Input;
-use chumsky::{ParseResult, Parser};
-use std::fmt::Display;
-use std::{env, fs, process};
+use ariadne::sources;
+use clap::{Parser, Subcommand};
+use miniscript::compile;
+use miniscript::diagnostics::Diagnostic;
+use miniscript::errors::MsError;
+use miniscript::format::format;
+use miniscript::lint::lint;
+use miniscript::vm::cfg::Cfg;
+use miniscript::vm::chunk::{pretty_print, Chunk};
+use miniscript::vm::profiler::ProfilingRunner;
+use miniscript::vm::{BudgetRunner, DefaultRunner, Vm, VmRunner};
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::{fs, process};
 
-pub mod ast;
-pub mod errors;
-pub mod parsing;
-#[cfg(test)]
-pub mod tests;
-pub mod value;
-pub mod vm;
+/// Runs, checks and formats miniscript files.
+///
+/// Exits with the code of the first error, as returned by `MsError::code`. Most shells keep only
+/// the lowest 8 bits of it, so `--json` should be used to get the full code.
+#[derive(Parser, Debug)]
+#[command(about, long_about = None)]
+struct Args {
+    /// Print diagnostics and script output as a single JSON document
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a script or a compiled bytecode file
+    Run {
+        file: PathBuf,
+        /// Maximum number of instructions to execute
+        #[arg(long)]
+        budget: Option<usize>,
+    },
+    /// Compile a script without running it
+    Check { file: PathBuf },
+    /// Print the compiled bytecode
+    Disasm { file: PathBuf },
+    /// Compile a script into a bytecode file
+    Compile {
+        file: PathBuf,
+        /// Output file, defaults to the script path with `msc` extension
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Start an interactive session
+    Repl,
+    /// Format scripts in place
+    Fmt {
+        files: Vec<PathBuf>,
+        /// Only report files that are not formatted, without changing them
+        #[arg(long)]
+        check: bool,
+    },
+    /// Run all `*_test.ms` files in the directory and its subdirectories
+    Test {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Maximum number of instructions to execute in each test
+        #[arg(long)]
+        budget: Option<usize>,
+    },
+    /// Report suspicious code
+    Lint { file: PathBuf },
+    /// Run a script, printing time spent on each line and writing folded stacks next to it
+    Profile { file: PathBuf },
+    /// Print the control flow graph in Graphviz DOT format
+    Cfg { file: PathBuf },
+}
 
-fn format_errors<T: Display>(src_id: &str, errors: Vec<Rich<T>>) -> Vec<MsError> {
-    errors
-        .into_iter()
-        .map(CompileError::from_compilation)
-        .map(|err| MsError {
-            error_type: err.into(),
-            src_id: src_id.to_string(),
-        })
-        .collect()
+/// Collects diagnostics, which are printed right away, or as JSON once the command is done
+struct Output {
+    json: bool,
+    diagnostics: Vec<Diagnostic>,
+    /// Lines printed by scripts, only captured in JSON mode
+    printed: Vec<String>,
+    exit_code: i32,
 }
 
-pub fn compile(src_id: &str, src: &str) -> Result<Chunk, Vec<MsError>> {
-    let lexer = lexer();
-    let (tokens, errors) = lexer.parse(src).into_output_errors();
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    diagnostics: &'a [Diagnostic],
+    output: &'a [String],
+}
 
-    if !errors.is_empty() {
-        return Err(format_errors(src_id, errors));
+impl Output {
+    fn error(&mut self, err: &MsError, src: Option<&str>, chunk: Option<&Chunk>, vm: Option<&Vm>) {
+        if self.exit_code == 0 {
+            // Internal errors have code 0, which would read as a success
+            self.exit_code = i32::from(err.code()).max(1);
+        }
+        if self.json {
+            self.diagnostics
+                .push(Diagnostic::from_error(err, src, chunk, vm));
+            return;
+        }
+        match src {
+            Some(src) => err
+                .report(chunk, vm)
+                .eprint(sources([(err.src_id.clone(), src.to_string())]))
+                .expect("Failed to print error message"),
+            // Compiled bytecode has no source code to point at
+            None => eprintln!("[{}] Error: {err}", err.code()),
+        }
     }
 
-    let tokens = tokens.expect("Tokens output is none, but no errors were emitted either");
-    let stripped = tokens
-        .into_iter()
-        .filter(|token| !matches!(token.0, Token::Comment(_)))
-        .collect::<Vec<_>>();
-    let spanned = stripped.spanned((src.len()..src.len()).into());
+    fn errors(&mut self, errors: &[MsError], src: &str) {
+        for err in errors {
+            self.error(err, Some(src), None, None);
+        }
+    }
 
-    let ast_parser = ast_parser();
+    /// Reports a failure that doesn't come from the script itself
+    fn fail(&mut self, message: impl AsRef<str>) {
+        eprintln!("{}", message.as_ref());
+        if self.exit_code == 0 {
+            self.exit_code = 1;
+        }
+    }
 
-    let (ast, errors) = ast_parser.parse(spanned).into_output_errors();
+    fn finish(self) -> ! {
+        if self.json {
+            let output = JsonOutput {
+                diagnostics: &self.diagnostics,
+                output: &self.printed,
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&output).expect("Failed to serialize diagnostics")
+            );
+        }
+        process::exit(self.exit_code)
+    }
+}
 
-    if !errors.is_empty() {
-        return Err(format_errors(src_id, errors));
+fn main() {
+    let args = Args::parse();
+    let mut out = Output {
+        json: args.json,
+        diagnostics: vec![],
+        printed: vec![],
+        exit_code: 0,
+    };
+    match args.command {
+        Command::Run { file, budget } => run_file(&file, budget, &mut out),
+        Command::Check { file } => with_chunk(&file, &mut out, |_, _, _| {}),
+        Command::Disasm { file } => with_chunk(&file, &mut out, |chunk, src, _| {
+            println!("{}", pretty_print(chunk, src))
+        }),
+        Command::Compile { file, output } => {
+            let output = output.unwrap_or_else(|| file.with_extension("msc"));
+            with_chunk(&file, &mut out, |chunk, _, out| {
+                if let Err(err) = fs::write(&output, chunk.to_bytes()) {
+                    out.fail(format!("Failed to write {}: {err}", output.display()));
+                }
+            })
+        }
+        Command::Repl => repl(&mut out),
+        Command::Fmt { files, check } => {
+            for file in files {
+                format_file(&file, check, &mut out);
+            }
+        }
+        Command::Test { dir, budget } => run_tests(&dir, budget, &mut out),
+        Command::Lint { file } => lint_file(&file, &mut out),
+        Command::Profile { file } => with_chunk(&file, &mut out, |chunk, src, out| {
+            let mut profiler = ProfilingRunner::default();
+            if run_chunk(chunk, Some(src), &mut profiler, out) {
+                println!("{}", profiler.annotated_source(chunk, src));
+                let folded = format!("{}.folded", file.display());
+                match fs::write(&folded, profiler.folded_stacks(chunk, src)) {
+                    Ok(()) => println!("Folded stacks are written to {folded}"),
+                    Err(err) => out.fail(format!("Failed to write {folded}: {err}")),
+                }
+            }
+        }),
+        Command::Cfg { file } => with_chunk(&file, &mut out, |chunk, src, _| {
+            print!("{}", Cfg::new(chunk).to_dot(chunk, src))
+        }),
     }
+    out.finish()
+}
 
-    let ast = ast.expect("AST output is none, but no errors were emitted either");
+fn read_source(file: &Path, out: &mut Output) -> Option<String> {
+    fs::read_to_string(file)
+        .map_err(|err| out.fail(format!("Failed to read {}: {err}", file.display())))
+        .ok()
+}
 
-    let ast = AST::from_body(ast, src_id.to_string()).map_err(|err| vec![err])?;
+/// Compiles the script and passes it to `action`, or reports compilation errors
+fn with_chunk(file: &Path, out: &mut Output, action: impl FnOnce(&Chunk, &str, &mut Output)) {
+    let Some(src) = read_source(file, out) else {
+        return;
+    };
+    match compile(&file.display().to_string(), &src) {
+        Ok(chunk) => action(&chunk, &src, out),
+        Err(errors) => out.errors(&errors, &src),
+    }
+}
 
-    let chunk = compile_chunk(ast);
+fn run_file(file: &Path, budget: Option<usize>, out: &mut Output) {
+    let bytes = match fs::read(file) {
+        Ok(bytes) => bytes,
+        Err(err) => return out.fail(format!("Failed to read {}: {err}", file.display())),
+    };
+    if Chunk::is_bytecode(&bytes) {
+        match Chunk::from_bytes(&bytes) {
+            Ok(chunk) => {
+                run_with_budget(&chunk, None, budget, out);
+            }
+            Err(err) => out.fail(format!("Failed to load {}: {err}", file.display())),
+        }
+        return;
+    }
+    let Ok(src) = String::from_utf8(bytes) else {
+        return out.fail(format!(
+            "{} is neither a script nor bytecode",
+            file.display()
+        ));
+    };
+    match compile(&file.display().to_string(), &src) {
+        Ok(chunk) => {
+            run_with_budget(&chunk, Some(&src), budget, out);
+        }
+        Err(errors) => out.errors(&errors, &src),
+    }
+}
 
-    Ok(chunk)
+fn run_with_budget(
+    chunk: &Chunk,
+    src: Option<&str>,
+    budget: Option<usize>,
+    out: &mut Output,
+) -> bool {
+    match budget {
+        Some(budget) => run_chunk(chunk, src, &mut BudgetRunner::new(budget), out),
+        None => run_chunk(chunk, src, &mut DefaultRunner, out),
+    }
 }
 
-fn main() {
-    let filename = env::args().nth(1).expect("Expected file argument");
+/// Runs the chunk in a new VM, returning whether it finished without errors
+fn run_chunk(
+    chunk: &Chunk,
+    src: Option<&str>,
+    runner: &mut impl VmRunner,
+    out: &mut Output,
+) -> bool {
+    let mut vm = Vm::new(chunk);
+    if out.json {
+        vm.capture_output();
+    }
+    let result = runner.run(chunk, &mut vm);
+    out.printed.extend(vm.output.take().unwrap_or_default());
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            out.error(&err, src, Some(chunk), Some(&vm));
+            false
+        }
+    }
+}
 
-    println!("{filename}");
+fn lint_file(file: &Path, out: &mut Output) {
+    let Some(src) = read_source(file, out) else {
+        return;
+    };
+    let src_id = file.display().to_string();
+    let warnings = match lint(&src_id, &src) {
+        Ok(warnings) => warnings,
+        Err(errors) => return out.errors(&errors, &src),
+    };
+    for warning in &warnings {
+        if out.json {
+            out.diagnostics
+                .push(Diagnostic::from_warning(warning, &src_id, &src));
+        } else {
+            warning
+                .report(&src_id)
+                .eprint(sources([(src_id.clone(), src.clone())]))
+                .expect("Failed to print warning message");
+        }
+    }
+}
 
-    // println!("cwd: {:?}", env::current_dir());
+fn format_file(file: &Path, check: bool, out: &mut Output) {
+    let Some(src) = read_source(file, out) else {
+        return;
+    };
+    let formatted = match format(&file.display().to_string(), &src) {
+        Ok(formatted) => formatted,
+        Err(errors) => return out.errors(&errors, &src),
+    };
+    if formatted == src {
+        return;
+    }
+    if check {
+        out.fail(format!("{} is not formatted", file.display()));
+    } else if let Err(err) = fs::write(file, formatted) {
+        out.fail(format!("Failed to write {}: {err}", file.display()));
+    }
+}
 
-    let src = fs::read_to_string(&filename).expect("Failed to read file");
+fn run_tests(dir: &Path, budget: Option<usize>, out: &mut Output) {
+    let mut files = vec![];
+    if let Err(err) = find_tests(dir, &mut files) {
+        return out.fail(format!("Failed to read {}: {err}", dir.display()));
+    }
+    files.sort();
 
-    let chunk = compile(&filename, &src).unwrap_or_else(|errors| {
-        for err in errors {
-            err.report(None, None)
-                .print(sources([(filename.clone(), src.clone())]))
-                .expect("Failed to print error message");
+    let mut failed = 0;
+    for file in &files {
+        let passed = match read_source(file, out) {
+            Some(src) => match compile(&file.display().to_string(), &src) {
+                Ok(chunk) => run_with_budget(&chunk, Some(&src), budget, out),
+                Err(errors) => {
+                    out.errors(&errors, &src);
+                    false
+                }
+            },
+            None => false,
+        };
+        if !passed {
+            failed += 1;
+        }
+        if !out.json {
+            let status = if passed { "ok" } else { "FAILED" };
+            println!("test {} ... {status}", file.display());
         }
-        process::exit(1);
-    });
+    }
+    if !out.json {
+        println!(
+            "test result: {} passed; {failed} failed",
+            files.len() - failed
+        );
+    }
+}
 
-    println!("{}", pretty_print(&chunk, &src));
-    let mut vm = Vm {
-        cursor: 0,
-        stack: vec![Value::Null; chunk.stack_size()],
-    };
-    DefaultRunner.run(&chunk, &mut vm).unwrap_or_else(|err| {
-        err.report(Some(&chunk), Some(&vm))
-            .print(sources([(filename.clone(), src.clone())]))
-            .expect("Failed to print error message");
-        process::exit(1);
-    });
-
-    // println!("{}", result.unwrap()[0].0)
+fn find_tests(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_tests(&path, files)?;
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with("_test.ms"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Reads statements from stdin and runs them, until the input is closed.
+///
+/// Top level variables live in registers of a single chunk, so every input re-runs the whole
+/// session with it appended, and only the new output is printed. Inputs that fail are discarded.
+fn repl(out: &mut Output) {
+    const SRC_ID: &str = "<repl>";
+    let mut session = String::new();
+    let mut input = String::new();
+    let mut printed = 0;
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { ". " });
+        io::stdout().flush().expect("Failed to flush stdout");
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        input += &line;
+        input.push('\n');
+
+        let src = format!("{session}{input}");
+        let chunk = match compile(SRC_ID, &src) {
+            Ok(chunk) => chunk,
+            // Errors at the end of input mean that a block isn't closed yet
+            Err(errors)
+                if errors.iter().any(|err| {
+                    err.span(None, None)
+                        .is_some_and(|span| span.start >= src.len())
+                }) =>
+            {
+                continue
+            }
+            Err(errors) => {
+                out.errors(&errors, &src);
+                input.clear();
+                continue;
+            }
+        };
+        input.clear();
+
+        let mut vm = Vm::new(&chunk);
+        vm.capture_output();
+        let result = DefaultRunner.run(&chunk, &mut vm);
+        let output = vm.output.take().unwrap_or_default();
+        for line in output.iter().skip(printed) {
+            println!("{line}");
+        }
+        match result {
+            Ok(()) => {
+                session = src;
+                printed = output.len();
+            }
+            Err(err) => out.error(&err, Some(&src), Some(&chunk), Some(&vm)),
+        }
+    }
+    // Errors are part of an interactive session, and don't fail it
+    out.exit_code = 0;
 }
@@ -551,11 +551,35 @@ pub fn ast_parser<'tokens, 'src: 'tokens>() -> impl Parser<
                 (Statement::For(ident, condition, body), span)
             });
 
+        // Statement must span until the end of line, so trailing garbage is reported as its error
+        let statement_end = eol!().ignored().or(end()).rewind();
+
+        // Malformed statement is skipped until the end of line, and parsing resumes from the next
+        // one. Lines starting with `end` or `else` are left for the enclosing block to handle
+        let statement_error = any()
+            .filter(|token: &Token| {
+                !matches!(
+                    token,
+                    Token::EOL
+                        | Token::Semicolon
+                        | Token::Keyword(Keyword::End)
+                        | Token::Keyword(Keyword::Else)
+                )
+            })
+            .then(
+                any()
+                    .filter(|token: &Token| !matches!(token, Token::EOL | Token::Semicolon))
+                    .repeated(),
+            )
+            .map_with_span(|_, span| (Statement::Error, span));
+
         let multi_eol = eol!().repeated().at_least(1);
         return single_line
             .or(multiline_if)
             .or(while_statement)
             .or(for_statement)
+            .then_ignore(statement_end)
+            .recover_with(via_parser(statement_error))
             .separated_by(multi_eol.clone())
             .collect()
             .delimited_by(multi_eol.clone().or_not(), multi_eol.clone().or_not());
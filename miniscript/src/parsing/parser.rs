@@ -274,16 +274,22 @@ pub fn lexer<'src>(
         .or(parentheses)
         .or(others);
 
+    // Unknown characters are reported and skipped, so the rest of the file is still tokenized
+    let unknown = any().validate(|c: char, span, emitter| {
+        emitter.emit(Rich::custom(span, format!("unexpected character `{c}`")));
+    });
+
     token
-        .map_with_span(|tok, span| (tok, span))
+        .map_with_span(|tok, span| Some((tok, span)))
+        .or(unknown.to(None))
         .padded_by(
             any()
                 .filter(|c: &char| c.is_whitespace() && *c != '\n')
                 .ignored()
                 .repeated(),
         )
-        // .recover_with(skip_then_retry_until(any().ignored(), end()))
         .repeated()
-        .collect()
+        .collect::<Vec<_>>()
+        .map(|tokens| tokens.into_iter().flatten().collect())
         .then_ignore(end())
 }
@@ -0,0 +1,91 @@
+use lazy_static::lazy_static;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::RwLock;
+
+lazy_static! {
+    static ref INTERNER: RwLock<Interner> = RwLock::new(Interner::default());
+}
+
+#[derive(Default)]
+struct Interner {
+    symbols: FxHashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+/// String interned in the pool shared by all chunks and VMs of the process.
+///
+/// Symbols are compared and hashed by their ID. Interned strings are never freed, so only names
+/// and literals from the source code should be interned, not strings built by scripts.
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn intern(string: &str) -> Self {
+        if let Some(symbol) = Self::lookup(string) {
+            return symbol;
+        }
+        let mut interner = INTERNER.write().expect("Interner lock is poisoned");
+        // Another thread could intern the same string between the locks
+        if let Some(symbol) = interner.symbols.get(string) {
+            return *symbol;
+        }
+        let string: &'static str = Box::leak(string.into());
+        let symbol = Symbol(interner.strings.len() as u32);
+        interner.strings.push(string);
+        interner.symbols.insert(string, symbol);
+        symbol
+    }
+
+    /// Symbol of the string, if it was interned before
+    pub fn lookup(string: &str) -> Option<Self> {
+        INTERNER
+            .read()
+            .expect("Interner lock is poisoned")
+            .symbols
+            .get(string)
+            .copied()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.read().expect("Interner lock is poisoned").strings[self.0 as usize]
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Symbol::intern(value)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(value: String) -> Self {
+        Symbol::intern(&value)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Symbol({:?})", self.as_str())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// IDs depend on the order of interning, so symbols are serialized as their strings
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Symbol::intern(&String::deserialize(deserializer)?))
+    }
+}
@@ -1,8 +1,49 @@
-use crate::compile;
-use crate::vm::chunk::pretty_print;
+use crate::ast::{Expr, Span, Statement, AST};
+use crate::bindings::{FromValue, ScriptMethods, ToValue};
+use crate::diagnostics::Diagnostic;
+use crate::engine::Engine;
+use crate::errors::{runtime_error_table, InternalError, MsError, MsErrorType, RuntimeError};
+use crate::format::format;
+use crate::interpreter::interpret;
+use crate::intrinsics::json::{parse_json, to_json};
+use crate::intrinsics::sandboxed::Capabilities;
+use crate::intrinsics::{Intrinsic, IntrinsicParam};
+use crate::lint::lint;
+use crate::symbol::Symbol;
+use crate::value::{ConstantKey, Value, ISA_KEY};
+use crate::vm::cfg::{Cfg, Target};
+use crate::vm::chunk::{compile_chunk, pretty_print, BytecodeLoadError, Chunk};
+use crate::vm::profiler::ProfilingRunner;
+use crate::vm::{
+    BudgetRunner, DefaultRunner, ResumableRunner, RunState, Vm, VmRunner, MAX_CALL_DEPTH,
+};
+use crate::{compile, parse};
+use crate::{script_methods, ScriptMap};
 use ariadne::{sources, Report};
+use proptest::prelude::*;
+use std::cell::RefCell;
 use std::io::BufWriter;
 use std::ops::Range;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Structural equality where nulls are equal to each other, unlike in scripts
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Null, Value::Null) => true,
+        (Value::List(a), Value::List(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same(a, b))
+        }
+        (Value::Map(a), Value::Map(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, value)| b.get(key).is_some_and(|other| same(value, other)))
+        }
+        _ => a == b,
+    }
+}
 
 fn report_to_string(report: Report<(String, Range<usize>)>, code: &str) -> String {
     let mut buf = BufWriter::new(Vec::new());
@@ -25,6 +66,43 @@ fn review_code(code: &str) -> String {
         })
 }
 
+fn run_code(code: &str) -> String {
+    run_code_with(code, |_| {})
+}
+
+fn run_code_with(code: &str, setup: impl FnOnce(&mut Vm)) -> String {
+    let chunk = match compile("<eval>", code) {
+        Ok(chunk) => chunk,
+        Err(err) => {
+            return err
+                .into_iter()
+                .map(|err| report_to_string(err.report(None, None), code))
+                .collect::<Vec<String>>()
+                .join("\n\n")
+        }
+    };
+    let mut vm = Vm::new(&chunk);
+    setup(&mut vm);
+    match DefaultRunner.run(&chunk, &mut vm) {
+        Ok(()) => "OK".to_string(),
+        Err(err) => report_to_string(err.report(Some(&chunk), Some(&vm)), code),
+    }
+}
+
+fn lint_code(code: &str) -> String {
+    let reports = match lint("<eval>", code) {
+        Ok(warnings) => warnings
+            .iter()
+            .map(|warning| report_to_string(warning.report("<eval>"), code))
+            .collect::<Vec<String>>(),
+        Err(err) => err
+            .into_iter()
+            .map(|err| report_to_string(err.report(None, None), code))
+            .collect(),
+    };
+    reports.join("\n\n")
+}
+
 macro_rules! review {
     ($src:expr) => {
         let result = review_code($src);
@@ -37,6 +115,16 @@ fn test_binary() {
     review!("1 + 2");
 }
 
+#[test]
+fn test_register_reuse() {
+    review!("a = 1\nb = a + 1\nc = b * 2\nd = c - a\nprint d\ne = 5\nprint e");
+}
+
+#[test]
+fn test_else_if_liveness() {
+    review!("x = 1\nif a then\ny = x\nelse if x then\nprint 2\nend if");
+}
+
 #[test]
 fn test_print() {
     review!("print 1 + 2");
@@ -56,3 +144,786 @@ fn test_chained_comparison() {
 fn test_fail() {
     review!("if a print 5");
 }
+
+#[test]
+fn test_undefined_identifier() {
+    insta::assert_display_snapshot!(run_code("x = 1\nprint x + y"));
+}
+
+#[test]
+fn test_runtime_error_table() {
+    insta::assert_display_snapshot!(runtime_error_table());
+}
+
+#[test]
+fn test_json_round_trip() {
+    let json = serde_json::json!({
+        "name": "robot",
+        "speed": 1.5,
+        "lives": 3,
+        "enabled": null,
+        "path": [[0, 1], [2, 3]],
+    });
+    let value: Value = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(value.type_name(), "map");
+    assert_eq!(serde_json::to_value(&value).unwrap(), json);
+}
+
+#[test]
+fn test_json_display() {
+    let value: Value = serde_json::from_str(r#"{"a": [1, "two", null], "b": {"c": 0.5}}"#).unwrap();
+    assert_eq!(
+        value.to_string(),
+        r#"{"a": [1, "two", null], "b": {"c": 0.5}}"#
+    );
+}
+
+#[test]
+fn test_self_containing_values() {
+    let list = Value::new_list(vec![1.into()]);
+    let map = Value::new_map(Default::default());
+    map.set_index("list".into(), list.clone()).unwrap();
+    let Value::List(items) = &list else {
+        unreachable!()
+    };
+    items.borrow_mut().push(map.clone());
+    items.borrow_mut().push(list.clone());
+
+    assert_eq!(list.to_string(), r#"[1, {"list": [...]}, [...]]"#);
+    assert_eq!(map.to_string(), r#"{"list": [1, {...}, [...]]}"#);
+    let err = serde_json::to_string(&list).unwrap_err();
+    assert_eq!(err.to_string(), "list contains itself");
+    assert_eq!(list, list.clone());
+    let other = Value::new_list(vec![1.into(), map.clone(), list.clone()]);
+    assert_eq!(list, other);
+
+    // Mutable values can't be keys, since mutating them would change their hash
+    let err = map.set_index(list.clone(), 1.into()).unwrap_err();
+    assert!(matches!(
+        err,
+        RuntimeError::TypeMismatch { got: "list", .. }
+    ));
+    assert!(map.get_index(&map).is_err());
+    assert_ne!(Value::Null, Value::Null);
+    // Null and NaN are unequal to themselves, so they couldn't be found once inserted
+    let err = map.set_index(Value::Null, 1.into()).unwrap_err();
+    assert!(matches!(
+        err,
+        RuntimeError::TypeMismatch { got: "null", .. }
+    ));
+    let err = map.set_index(f64::NAN.into(), 1.into()).unwrap_err();
+    assert!(matches!(err, RuntimeError::TypeMismatch { got: "NaN", .. }));
+    assert!(map.get_index(&Value::Null).is_err());
+}
+
+#[derive(Debug, Clone, PartialEq, ScriptMap)]
+struct Robot {
+    name: String,
+    #[script(rename = "pos")]
+    position: Vec<f64>,
+    #[script(skip)]
+    moves: usize,
+}
+
+#[script_methods]
+impl Robot {
+    fn distance(&self) -> f64 {
+        self.position.iter().map(|x| x * x).sum::<f64>().sqrt()
+    }
+
+    fn step(&mut self, dx: f64, dy: f64) {
+        self.position[0] += dx;
+        self.position[1] += dy;
+        self.moves += 1;
+    }
+
+    #[script(rename = "check")]
+    fn check_name(&self, expected: String) -> Result<bool, RuntimeError> {
+        if expected.contains(char::is_whitespace) {
+            return Err(RuntimeError::Message(
+                "Names can't contain spaces".to_string(),
+            ));
+        }
+        Ok(self.name == expected)
+    }
+
+    #[script(skip)]
+    #[allow(dead_code)]
+    fn hidden(&self) {}
+}
+
+fn robot() -> Robot {
+    Robot {
+        name: "R2".to_string(),
+        position: vec![3., 4.],
+        moves: 0,
+    }
+}
+
+#[test]
+fn test_derive_round_trip() {
+    let value = robot().to_value();
+    assert_eq!(value.to_string(), r#"{"name": "R2", "pos": [3, 4]}"#);
+    assert_eq!(Robot::from_value(&value).unwrap(), robot());
+    assert!(Robot::from_value(&Value::from(1)).is_err());
+}
+
+#[test]
+fn test_numeric_conversions() {
+    let out_of_range = |err: RuntimeError| matches!(err, RuntimeError::NumberOutOfRange { .. });
+    assert!(out_of_range(u8::from_value(&(-1).into()).unwrap_err()));
+    assert!(out_of_range(u8::from_value(&300.into()).unwrap_err()));
+    assert!(out_of_range(u8::from_value(&f64::NAN.into()).unwrap_err()));
+    assert!(out_of_range(i32::from_value(&0.5.into()).unwrap_err()));
+    assert!(out_of_range(
+        u64::from_value(&2f64.powi(64).into()).unwrap_err()
+    ));
+    assert!(out_of_range(f32::from_value(&f64::MAX.into()).unwrap_err()));
+    assert!(matches!(
+        u8::from_value(&"1".into()).unwrap_err(),
+        RuntimeError::TypeMismatch { .. }
+    ));
+
+    assert_eq!(u8::from_value(&255.into()).unwrap(), 255);
+    assert_eq!(i64::from_value(&(-2f64.powi(63)).into()).unwrap(), i64::MIN);
+    assert_eq!(f32::from_value(&0.1.into()).unwrap(), 0.1);
+    assert!(f64::from_value(&f64::NAN.into()).unwrap().is_nan());
+}
+
+#[test]
+fn test_isa_cycle() {
+    let a = Value::new_map(Default::default());
+    let b = Value::new_map(Default::default());
+    a.set_index(ISA_KEY.into(), b.clone()).unwrap();
+    b.set_index(ISA_KEY.into(), a.clone()).unwrap();
+    b.set_index("x".into(), 1.into()).unwrap();
+    assert_eq!(a.get_index(&"x".into()).unwrap(), Value::from(1));
+    assert!(matches!(
+        a.get_index(&"y".into()).unwrap_err(),
+        RuntimeError::KeyNotFound(_)
+    ));
+}
+
+#[test]
+fn test_derive_methods() {
+    let names = Robot::script_methods()
+        .iter()
+        .map(|method| method.name().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["distance", "step", "check"]);
+
+    let value = robot().to_script_object(&Robot::script_class());
+    let result = run_code_with(
+        "robot.step 1, 2\nrobot.pos[1] = robot.pos[1] + robot.distance\nrobot.check \"R2\"",
+        |vm| vm.set_global("robot", value.clone()),
+    );
+    assert_eq!(result, "OK");
+    let moved = Robot::from_value(&value).unwrap();
+    assert_eq!(moved.position, vec![4., 6. + 52f64.sqrt()]);
+}
+
+#[test]
+fn test_method_errors() {
+    let value = robot().to_script_object(&Robot::script_class());
+    insta::assert_display_snapshot!(run_code_with("robot.check \"R 2\"", |vm| vm
+        .set_global("robot", value.clone()),));
+}
+
+#[test]
+fn test_too_many_arguments() {
+    let value = robot().to_script_object(&Robot::script_class());
+    insta::assert_display_snapshot!(run_code_with("robot.step 1, 2, 3", |vm| vm
+        .set_global("robot", value.clone())));
+}
+
+fn run_with_output(vm: &mut Vm, code: &str) -> String {
+    let chunk = compile("<eval>", code).unwrap();
+    DefaultRunner.run(&chunk, vm).unwrap();
+    vm.get_global("out").unwrap().to_string()
+}
+
+#[test]
+fn test_rnd_replay() {
+    let code = "out[0] = rnd\nout[1] = rnd(42)\nout[2] = rnd";
+    let chunk = compile("<eval>", code).unwrap();
+    let mut vm = Vm::new(&chunk);
+    vm.set_global("out", Value::new_list(vec![Value::Null; 3]));
+    let snapshot = vm.snapshot();
+
+    let recorded = run_with_output(&mut vm, code);
+    vm.restore(&snapshot);
+    assert_eq!(
+        vm.get_global("out").unwrap().to_string(),
+        "[null, null, null]"
+    );
+    assert_eq!(run_with_output(&mut vm, code), recorded);
+
+    // Seeding via `rnd(seed)` makes the following numbers independent of the prior state
+    vm.restore(&snapshot);
+    vm.seed_rng(7);
+    let reseeded = run_with_output(&mut vm, code);
+    assert_ne!(reseeded, recorded);
+    let tail = |out: &str| out.split(", ").skip(1).collect::<Vec<_>>().join(", ");
+    assert_eq!(tail(&reseeded), tail(&recorded));
+}
+
+#[test]
+fn test_snapshot_preserves_sharing() {
+    let chunk = compile("<eval>", "a[0] = 5").unwrap();
+    let list = Value::new_list(vec![Value::from(1)]);
+    let mut vm = Vm::new(&chunk);
+    vm.set_global("a", list.clone());
+    vm.set_global("b", list.clone());
+    let snapshot = vm.snapshot();
+    vm.restore(&snapshot);
+
+    DefaultRunner.run(&chunk, &mut vm).unwrap();
+    assert_eq!(vm.get_global("b").unwrap().to_string(), "[5]");
+    assert_eq!(list.to_string(), "[1]");
+}
+
+#[test]
+fn test_multiple_parse_errors() {
+    review!("x = (1 + )\nprint 2\ny = 3 4\nprint x");
+}
+
+#[test]
+fn test_lexer_and_parser_errors() {
+    review!("x = 1 $ 2\nwhile x\n  print 5 +\nend while\nprint ~");
+}
+
+#[test]
+fn test_error_nodes_compile() {
+    let span = Span::from(0..4);
+    let body = vec![
+        (Statement::Expression((Expr::Error, span)), span),
+        (Statement::Error, span),
+    ];
+    let chunk = compile_chunk(AST::from_body_unchecked(body, "<eval>".to_string()));
+    let mut vm = Vm::new(&chunk);
+    let err = DefaultRunner.run(&chunk, &mut vm).unwrap_err();
+    assert_eq!(err.to_string(), "Code with syntax errors was executed");
+}
+
+/// Runs the code, returning printed lines, the outcome and the final state of global `g`
+fn run_engine(run: impl FnOnce(&mut Vm) -> Result<(), MsError>) -> String {
+    let mut vm = Vm::default();
+    vm.capture_output();
+    vm.set_global("g", Value::new_list(vec![1.into(), 2.into(), 3.into()]));
+    let result = run(&mut vm);
+    let output = vm.output.take().unwrap_or_default().join("\n");
+    let result = result.map_or_else(|err| err.to_string(), |_| "OK".to_string());
+    format!("{output}\n=> {result}\ng = {}", vm.get_global("g").unwrap())
+}
+
+fn run_both(code: &str) -> (String, String) {
+    let compiled = run_engine(|vm| {
+        let chunk = compile("<eval>", code).unwrap();
+        vm.stack = vec![Value::Null; chunk.stack_size()];
+        DefaultRunner.run(&chunk, vm)
+    });
+    let interpreted = run_engine(|vm| interpret(&parse("<eval>", code).unwrap(), vm));
+    (compiled, interpreted)
+}
+
+fn leaf_expr() -> impl Strategy<Value = String> {
+    prop_oneof![
+        (0..10u8).prop_map(|num| num.to_string()),
+        Just("0.5".to_string()),
+        Just("\"s\"".to_string()),
+        Just("true".to_string()),
+        Just("false".to_string()),
+        Just("null".to_string()),
+        "[abc]",
+        Just("rnd".to_string()),
+        Just("g[1]".to_string()),
+        Just("zz".to_string()),
+    ]
+}
+
+fn expr() -> impl Strategy<Value = String> {
+    leaf_expr().prop_recursive(3, 16, 3, |inner| {
+        let op = prop::sample::select(vec![
+            "+", "-", "*", "/", "^", "and", "or", "==", "!=", "<", ">", "<=", ">=",
+        ]);
+        prop_oneof![
+            (inner.clone(), op, inner.clone())
+                .prop_map(|(lhs, op, rhs)| format!("({lhs} {op} {rhs})")),
+            (inner.clone(), inner.clone(), inner.clone())
+                .prop_map(|(a, b, c)| format!("({a} < {b} <= {c})")),
+            inner.clone().prop_map(|index| format!("g[{index}]")),
+        ]
+    })
+}
+
+/// Generates a block of statements. Loops are bounded by a counter that is unique for the
+/// nesting `depth` and is never assigned by the generated code
+fn block(depth: usize) -> BoxedStrategy<String> {
+    let simple = prop_oneof![
+        ("[abc]", expr()).prop_map(|(var, value)| format!("{var} = {value}")),
+        expr().prop_map(|value| format!("print {value}")),
+        (expr(), expr()).prop_map(|(index, value)| format!("g[{index}] = {value}")),
+        (expr(), expr())
+            .prop_map(|(condition, value)| format!("if {condition} then print {value}")),
+    ];
+    let statement = if depth == 0 {
+        simple.boxed()
+    } else {
+        let nested = block(depth - 1);
+        prop_oneof![
+            3 => simple,
+            1 => (
+                expr(),
+                nested.clone(),
+                prop::collection::vec((expr(), nested.clone()), 0..3),
+                prop::option::of(nested.clone()),
+            )
+                .prop_map(|(condition, body, else_ifs, else_body)| {
+                    let mut code = format!("if {condition} then\n{body}");
+                    for (condition, body) in else_ifs {
+                        code += &format!("\nelse if {condition} then\n{body}");
+                    }
+                    if let Some(else_body) = else_body {
+                        code += &format!("\nelse\n{else_body}");
+                    }
+                    code + "\nend if"
+                }),
+            1 => (0..4, nested).prop_map(move |(count, body)| {
+                format!("w{depth} = 0\nwhile w{depth} < {count}\n{body}\nw{depth} = w{depth} + 1\nend while")
+            }),
+        ]
+        .boxed()
+    };
+    prop::collection::vec(statement, 1..5)
+        .prop_map(|statements| statements.join("\n"))
+        .boxed()
+}
+
+fn program() -> impl Strategy<Value = String> {
+    // Locals are defined upfront, so reading them never depends on the branches taken
+    block(2).prop_map(|body| format!("a = 1\nb = \"b\"\nc = null\n{body}"))
+}
+
+proptest! {
+    // Miscompilations usually need a specific combination of branches, which default amount of
+    // cases rarely hits
+    #![proptest_config(ProptestConfig::with_cases(1024))]
+
+    #[test]
+    fn test_engines_agree(code in program()) {
+        let (compiled, interpreted) = run_both(&code);
+        prop_assert_eq!(compiled, interpreted, "Engines disagree on:\n{}", code);
+    }
+}
+
+#[test]
+fn test_interpreter_output() {
+    let (compiled, interpreted) =
+        run_both("x = 2\nwhile x < 5\nprint x\nx = x + 1\nend while\ng[0] = x\nprint zz");
+    assert_eq!(compiled, interpreted);
+    insta::assert_display_snapshot!(interpreted);
+}
+
+#[test]
+fn test_lint_warnings() {
+    let warnings = lint_code(
+        "x = 1\nx = 2\nunused = x\n3 + 4\nrnd = 5\nprint rnd\nf = function(a=0)\n  if a == a then\n    return 1\n    print a\n  end if\nend function\nprint f(1)",
+    );
+    insta::assert_display_snapshot!(warnings);
+}
+
+#[test]
+fn test_lint_suppression() {
+    let warnings = lint_code(
+        "// lint-allow: unused_variable\nx = 1\ny = 2 // lint-allow: 3000\nz = 3\n1 // lint-allow: no_effect, self_comparison",
+    );
+    insta::assert_display_snapshot!(warnings);
+}
+
+#[test]
+fn test_profiler_coverage() {
+    let code =
+        "x = 0\nwhile x < 3\n  x = x + 1\nend while\nif x > 5 then\n  print x\nend if\ny = rnd";
+    let chunk = compile("<eval>", code).unwrap();
+    let mut vm = Vm::new(&chunk);
+    let mut profiler = ProfilingRunner::default();
+    profiler.run(&chunk, &mut vm).unwrap();
+
+    assert_eq!(profiler.function_stats()["rnd"].hits, 1);
+    assert_eq!(profiler.coverage(&chunk, code), (5, 6));
+    let stacks = profiler.folded_stacks(&chunk, code);
+    assert!(stacks
+        .lines()
+        .any(|stack| stack.starts_with("<eval>;line 8;rnd ")));
+    insta::assert_display_snapshot!(profiler.coverage_listing(&chunk, code));
+}
+
+#[test]
+fn test_cfg_export() {
+    let code = "x = 1\nif x > 0 and x < 5 then\n  print x\nelse\n  print \"no\"\nend if";
+    let chunk = compile("<eval>", code).unwrap();
+    let cfg = Cfg::new(&chunk);
+
+    // Every jump lands at the start of a block
+    for block in cfg.blocks() {
+        for edge in &block.successors {
+            if let Target::Block(target) = edge.target {
+                assert_eq!(cfg.block_of(cfg.blocks()[target].start), Some(target));
+                assert!(cfg
+                    .predecessors(target)
+                    .contains(&cfg.block_of(block.start).unwrap()));
+            }
+        }
+    }
+    insta::assert_display_snapshot!(cfg.to_dot(&chunk, code));
+}
+
+fn op_codes(code: &str) -> Vec<String> {
+    let chunk = compile("<eval>", code).unwrap();
+    chunk.code().iter().map(|op| op.pretty_print()).collect()
+}
+
+#[test]
+fn test_format() {
+    let code = "\n\nx=1// start\nwhile x<10\nif x>5 and x!=7 then\n    print x*2\nelse if x==3 then\nprint(x)\nend if\n\n\n\nx=x+1\nend while\nprint  rnd (3)\n\n";
+    let formatted = format("<eval>", code).unwrap();
+    assert_eq!(format("<eval>", &formatted).unwrap(), formatted);
+    assert_eq!(op_codes(&formatted), op_codes(code));
+    insta::assert_display_snapshot!(formatted);
+}
+
+#[test]
+fn test_bytecode_round_trip() {
+    let code = "x = 0\nwhile x < 3\n  x = x + 1\n  print x\nend while\nprint \"done\"";
+    let chunk = compile("<eval>", code).unwrap();
+    let bytes = chunk.to_bytes();
+    assert!(Chunk::is_bytecode(&bytes));
+    assert!(!Chunk::is_bytecode(code.as_bytes()));
+
+    let loaded = Chunk::from_bytes(&bytes).unwrap();
+    assert_eq!(pretty_print(&loaded, code), pretty_print(&chunk, code));
+    let output = |chunk: &Chunk| {
+        let mut vm = Vm::new(chunk);
+        vm.capture_output();
+        DefaultRunner.run(chunk, &mut vm).unwrap();
+        vm.output.unwrap()
+    };
+    assert_eq!(output(&loaded), output(&chunk));
+    assert!(matches!(
+        Chunk::from_bytes(&bytes[..bytes.len() / 2]),
+        Err(BytecodeLoadError::Malformed(_))
+    ));
+
+    // Source id claiming to be longer than the whole file
+    let mut huge = bytes[..5].to_vec();
+    huge.extend(u64::MAX.to_le_bytes());
+    assert!(matches!(
+        Chunk::from_bytes(&huge),
+        Err(BytecodeLoadError::Malformed(_))
+    ));
+    // Stack size is the last field, registers of instructions don't fit into an empty stack
+    let mut no_stack = bytes.clone();
+    let len = no_stack.len();
+    no_stack[len - 8..].fill(0);
+    let err = Chunk::from_bytes(&no_stack).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Malformed bytecode: Instruction 0 uses register $0, but the stack size is 0"
+    );
+}
+
+#[test]
+fn test_budget_exceeded() {
+    let chunk = compile("<eval>", "x = 0\nwhile true\n  x = x + 1\nend while").unwrap();
+    let mut vm = Vm::new(&chunk);
+    let mut runner = BudgetRunner::new(100);
+    let err = runner.run(&chunk, &mut vm).unwrap_err();
+    assert_eq!(err.code(), 1009);
+    assert_eq!(runner.executed(), 100);
+}
+
+#[test]
+fn test_diagnostics_json() {
+    let code = "x = 1\ny = (x +";
+    let errors = compile("<eval>", code).unwrap_err();
+    let diagnostics = errors
+        .iter()
+        .map(|err| Diagnostic::from_error(err, Some(code), None, None))
+        .collect::<Vec<_>>();
+    insta::assert_display_snapshot!(serde_json::to_string_pretty(&diagnostics).unwrap());
+}
+
+fn sandboxed_vm(chunk: &Chunk, capabilities: Capabilities, root: &std::path::Path) -> Vm {
+    let mut vm = Vm::new(chunk);
+    vm.capture_output();
+    vm.enable(capabilities);
+    vm.set_file_root(root);
+    vm
+}
+
+#[test]
+fn test_sandboxed_files() {
+    let root = std::env::temp_dir().join(format!("miniscript_sandbox_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let run = |code: &str, capabilities: Capabilities| {
+        let chunk = compile("<eval>", code).unwrap();
+        let mut vm = sandboxed_vm(&chunk, capabilities, &root);
+        DefaultRunner
+            .run(&chunk, &mut vm)
+            .map(|()| vm.output.unwrap())
+            .map_err(|err| err.code())
+    };
+    let all = Capabilities::FILE_READ | Capabilities::FILE_WRITE;
+
+    assert_eq!(
+        run(
+            "file.write \"a.txt\", \"hello\"\nprint file.read(\"a.txt\")",
+            all
+        ),
+        Ok(vec!["hello".to_string()])
+    );
+    assert_eq!(
+        std::fs::read_to_string(root.join("a.txt")).unwrap(),
+        "hello"
+    );
+    // Paths can't escape the root
+    assert_eq!(run("print file.read(\"../a.txt\")", all), Err(1010));
+    assert_eq!(run("print file.read(\"/etc/hosts\")", all), Err(1010));
+    assert_eq!(run("print file.read(\"missing.txt\")", all), Err(1011));
+    // Modules are only defined when enabled
+    assert_eq!(
+        run("print file.read(\"a.txt\")", Capabilities::empty()),
+        Err(1005)
+    );
+    assert_eq!(run("print time", Capabilities::FILE_READ), Err(1005));
+    assert!(run("file.write \"a.txt\", \"x\"", Capabilities::FILE_READ).is_err());
+    assert_eq!(
+        std::fs::read_to_string(root.join("a.txt")).unwrap(),
+        "hello"
+    );
+    // Dangling links can't be written through to create files outside of the root
+    #[cfg(unix)]
+    {
+        let outside = root.with_extension("outside");
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        assert_eq!(run("file.write \"link\", \"x\"", all), Err(1010));
+        assert!(!outside.exists());
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_wait_suspends() {
+    let chunk = compile("<eval>", "print 1\nwait 2\nprint 2\nwait\nprint 3").unwrap();
+    let mut vm = Vm::new(&chunk);
+    vm.capture_output();
+    vm.enable(Capabilities::TIME);
+
+    let mut runner = ResumableRunner;
+    let mut states = vec![];
+    loop {
+        let state = runner.resume(&chunk, &mut vm).unwrap();
+        states.push((state, vm.output.as_ref().unwrap().len()));
+        if state == RunState::Finished {
+            break;
+        }
+    }
+    assert_eq!(
+        states,
+        [
+            (RunState::Suspended(Duration::from_secs(2)), 1),
+            (RunState::Suspended(Duration::from_secs(1)), 2),
+            (RunState::Finished, 3)
+        ]
+    );
+
+    // Other runners don't suspend
+    let mut vm = Vm::new(&chunk);
+    vm.capture_output();
+    vm.enable(Capabilities::TIME);
+    DefaultRunner.run(&chunk, &mut vm).unwrap();
+    assert_eq!(vm.output.unwrap().len(), 3);
+}
+
+#[test]
+fn test_string_interning() {
+    let first = compile("<first>", "out[0] = interned_name\nout[1] = \"literal\"").unwrap();
+    let second = compile("<second>", "out[0] = \"interned_name\"").unwrap();
+    assert_eq!(
+        Symbol::lookup("interned_name"),
+        Some(Symbol::intern("interned_name"))
+    );
+    assert_eq!(Symbol::intern("literal").as_str(), "literal");
+    assert_eq!(Symbol::lookup("never_interned_name"), None);
+
+    // Constants of both chunks share one string in the VM
+    let mut vm = Vm::new(&first);
+    vm.set_global("interned_name", 1);
+    vm.set_global("out", Value::new_list(vec![Value::Null; 2]));
+    DefaultRunner.run(&first, &mut vm).unwrap();
+    let out = vm.get_global("out").unwrap().clone();
+    vm.set_global("out", Value::new_list(vec![Value::Null]));
+    vm.cursor = 0;
+    DefaultRunner.run(&second, &mut vm).unwrap();
+    let key = vm.get_global("out").unwrap().get_index(&0.into()).unwrap();
+    let (Value::String(a), Value::String(b)) =
+        (&key, &vm.symbol_value(Symbol::intern("interned_name")))
+    else {
+        panic!("Expected strings, got {key:?}");
+    };
+    assert!(Rc::ptr_eq(a, b));
+    assert_eq!(out.to_string(), "[1, \"literal\"]");
+}
+
+#[test]
+fn test_constant_keys() {
+    let key = ConstantKey::new("name".into());
+    let map = Value::new_map(Default::default());
+    // Keys built at runtime find values written by constants, and the other way around
+    map.set_field(&key, 1.into()).unwrap();
+    let built = Value::from("na".to_string() + "me");
+    assert_eq!(map.get_index(&built).unwrap(), Value::from(1));
+    map.set_index(built, 2.into()).unwrap();
+    assert_eq!(map.get_field(&key).unwrap(), Value::from(2));
+    assert_eq!(map.to_string(), "{\"name\": 2}");
+
+    let code = "m.a = 1\nm[\"b\"] = m[\"a\"] + 1\nout[0] = m.b\nout[1] = child.a";
+    let chunk = compile("<eval>", code).unwrap();
+    let mut vm = Vm::new(&chunk);
+    let parent = Value::new_map(Default::default());
+    let child = Value::new_map(Default::default());
+    child.set_index(ISA_KEY.into(), parent.clone()).unwrap();
+    vm.set_global("m", parent);
+    vm.set_global("child", child);
+    vm.set_global("out", Value::new_list(vec![Value::Null; 2]));
+    DefaultRunner.run(&chunk, &mut vm).unwrap();
+    assert_eq!(vm.get_global("out").unwrap().to_string(), "[2, 1]");
+}
+
+#[test]
+fn test_json_intrinsics() {
+    let code = "out[0] = toJSON(data)\nout[1] = toJSON(data, 1)\nout[2] = parseJSON(out[0])\nout[3] = toJSON(parseJSON(\"[1, true, null]\"))";
+    let chunk = compile("<eval>", code).unwrap();
+    let data =
+        parse_json(r#"{"name": "robot", "pos": [1, 2.5], "tags": {}, "parent": null}"#).unwrap();
+    let mut vm = Vm::new(&chunk);
+    vm.set_global("data", data.clone());
+    vm.set_global("out", Value::new_list(vec![Value::Null; 4]));
+    DefaultRunner.run(&chunk, &mut vm).unwrap();
+
+    let out = vm.get_global("out").unwrap();
+    let item = |i: i32| out.get_index(&i.into()).unwrap();
+    assert!(same(&item(2), &data), "{} != {}", item(2), data);
+    assert_eq!(item(3).to_string(), "[1,1,null]");
+    insta::assert_display_snapshot!(format!("{}\n{}", item(0), item(1)));
+
+    // Shared values are fine, but cycles and functions are reported with their path
+    let list = Value::new_list(vec![Value::Null, data.clone(), data]);
+    assert!(to_json(&list, false).is_ok());
+    let Value::List(items) = &list else {
+        unreachable!()
+    };
+    items.borrow_mut()[1]
+        .set_index("self".into(), list.clone())
+        .unwrap();
+    let errors = [
+        to_json(&list, false).unwrap_err(),
+        to_json(vm.get_global("toJSON").unwrap(), false).unwrap_err(),
+        to_json(
+            &Value::new_list(vec![1.into(), f64::INFINITY.into()]),
+            false,
+        )
+        .unwrap_err(),
+        to_json(&f64::NAN.into(), false).unwrap_err(),
+        parse_json(r#"{"a": [1, 2, }"#).unwrap_err(),
+        parse_json("[1] 2").unwrap_err(),
+    ];
+    insta::assert_display_snapshot!(errors
+        .iter()
+        .map(|err| format!("[{}] {err}", err.code()))
+        .collect::<Vec<_>>()
+        .join("\n"));
+}
+
+#[test]
+fn test_engine() {
+    let mut engine = Engine::new();
+    let printed = Rc::new(RefCell::new(vec![]));
+    let sink = printed.clone();
+    engine.set_output(move |line| sink.borrow_mut().push(line.to_string()));
+    engine.set_loader(|name: &str| match name {
+        "answer" => Some("print \"loading\"\n40 + 2".to_string()),
+        "cycle" => Some("import(\"cycle\")".to_string()),
+        "broken" => Some("x = (".to_string()),
+        "random" => Some("define \"seen\", speed\nrnd".to_string()),
+        // Every module imports a new one, so they never run into a cycle
+        name => name
+            .strip_prefix("deep")
+            .and_then(|depth| depth.parse::<usize>().ok())
+            .map(|depth| format!("import(\"deep{}\")", depth + 1)),
+    });
+    engine.register_intrinsic(Intrinsic::new(
+        "double",
+        vec![IntrinsicParam::new("x")],
+        |_, args| Ok(Value::from(args[0].as_f64() * 2.)),
+    ));
+    engine.register_intrinsic(Intrinsic::new(
+        "define",
+        vec![IntrinsicParam::new("name"), IntrinsicParam::new("value")],
+        |vm, args| {
+            vm.set_global(String::from_value(&args[0])?.as_str(), args[1].clone());
+            Ok(Value::Null)
+        },
+    ));
+
+    assert_eq!(engine.eval("1 + 2").unwrap(), Value::from(3));
+    assert!(same(&engine.eval("x = 1").unwrap(), &Value::Null));
+    // Constructs the compiler doesn't support yet are errors instead of panics
+    for code in ["x = [1, 2]", "-x"] {
+        let err = engine.eval(code).unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            MsErrorType::Internal(InternalError::NotImplemented)
+        ));
+    }
+    engine.set_global("speed", 5);
+    assert_eq!(
+        engine.eval("x = speed\ndouble(x)").unwrap(),
+        Value::from(10)
+    );
+    let double = engine.get_global("double").unwrap().clone();
+    assert_eq!(
+        engine.call(&double, vec![Value::from(4)]).unwrap(),
+        Value::from(8)
+    );
+
+    // Modules run once, and their output goes to the same sink
+    assert_eq!(engine.eval("import(\"answer\")").unwrap(), Value::from(42));
+    assert_eq!(
+        engine.eval("import(\"answer\") + 1").unwrap(),
+        Value::from(43)
+    );
+    assert_eq!(*printed.borrow(), ["loading"]);
+
+    // Modules run in the importing VM, so its globals and random generator are shared
+    engine.vm_mut().seed_rng(1);
+    let first = engine.eval("rnd").unwrap();
+    let imported = engine.eval("import(\"random\")").unwrap();
+    assert_eq!(engine.get_global("seen"), Some(&Value::from(5)));
+    engine.vm_mut().seed_rng(1);
+    assert_eq!(engine.eval("rnd").unwrap(), first);
+    assert_eq!(engine.eval("rnd").unwrap(), imported);
+    for module in ["cycle", "broken", "missing"] {
+        let err = engine.eval(&format!("import(\"{module}\")")).unwrap_err();
+        assert_eq!(err.code(), 1013, "{err}");
+    }
+    // Modules that import new modules forever stop at the call depth limit
+    let err = engine.eval("import(\"deep0\")").unwrap_err();
+    let overflow = RuntimeError::StackOverflow(MAX_CALL_DEPTH).to_string();
+    assert!(err.to_string().contains(&overflow), "{err}");
+
+    let code = "y = 1\nprint undefined_name";
+    let err = engine.eval(code).unwrap_err();
+    insta::assert_display_snapshot!(report_to_string(engine.report(&err), code));
+}
@@ -1,12 +1,77 @@
+use crate::errors::RuntimeError;
+use crate::intrinsics::Intrinsic;
 use auto_ops::impl_op_ex;
+use indexmap::{Equivalent, IndexMap};
+use rustc_hash::FxHasher;
+use std::cell::RefCell;
 use std::fmt::Display;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
 use std::ops::Neg;
+use std::rc::Rc;
+
+mod serialization;
+
+pub type ValueMap = IndexMap<Value, Value, BuildHasherDefault<FxHasher>>;
 
 #[non_exhaustive]
 #[derive(Debug, Clone)]
 pub enum Value {
     Null,
     Number(f64),
+    String(Rc<str>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<ValueMap>>),
+    Function(Rc<Intrinsic>),
+}
+
+/// Map key that points to the parent map in MiniScript's prototype chains
+pub const ISA_KEY: &str = "__isa";
+
+thread_local! {
+    static ISA: ConstantKey = ConstantKey::new(ISA_KEY.into());
+}
+
+/// String map key with its hash computed once, so that maps are searched for names and literals
+/// of the code without hashing their text on every lookup.
+#[derive(Debug, Clone)]
+pub struct ConstantKey {
+    value: Value,
+    hash: u64,
+}
+
+impl ConstantKey {
+    pub fn new(string: Rc<str>) -> Self {
+        let hash = string_hash(&string);
+        Self {
+            value: Value::String(string),
+            hash,
+        }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+}
+
+/// Hashes the same way as `Value::String` with the same text
+impl Hash for ConstantKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.value).hash(state);
+        state.write_u64(self.hash);
+    }
+}
+
+impl Equivalent<Value> for ConstantKey {
+    fn equivalent(&self, key: &Value) -> bool {
+        // Keys written by the same constant share its allocation, so they compare by pointer
+        self.value == *key
+    }
+}
+
+fn string_hash(str: &str) -> u64 {
+    let mut hasher = FxHasher::default();
+    str.hash(&mut hasher);
+    hasher.finish()
 }
 
 macro_rules! numeric_as {
@@ -47,6 +112,61 @@ fn numeric_op<A: Fn(&f64, &f64) -> T, T: Into<Value>>(a: &Value, b: &Value, op:
     }
 }
 
+/// Resolves a possibly negative sequence index into a position within `len` items
+fn sequence_index(index: &Value, len: usize) -> Result<usize, RuntimeError> {
+    let Value::Number(num) = index else {
+        return Err(RuntimeError::TypeMismatch {
+            expected: "number",
+            got: index.type_name(),
+        });
+    };
+    let index = *num as i64;
+    let resolved = if index < 0 { index + len as i64 } else { index };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(RuntimeError::IndexOutOfRange { index, len });
+    }
+    Ok(resolved as usize)
+}
+
+/// Checks that the value can be used as a map key. Lists and maps are mutable, and changing
+/// them after insertion would leave them at the place of their old hash, while null and NaN
+/// are unequal to themselves, so they could never be found again
+fn check_key(key: &Value) -> Result<(), RuntimeError> {
+    let got = match key {
+        Value::Null | Value::List(_) | Value::Map(_) => key.type_name(),
+        Value::Number(num) if num.is_nan() => "NaN",
+        _ => return Ok(()),
+    };
+    Err(RuntimeError::TypeMismatch {
+        expected: "number, string or function key",
+        got,
+    })
+}
+
+/// Finds the value of the key in the map or its `__isa` parents
+fn map_get<Q: Hash + Equivalent<Value> + ?Sized>(
+    map: &Rc<RefCell<ValueMap>>,
+    key: &Q,
+) -> Option<Value> {
+    let mut map = map.clone();
+    // Maps of the chain so far, a chain that loops back ends like a missing parent
+    let mut visited = vec![];
+    loop {
+        visited.push(Rc::as_ptr(&map));
+        let parent = {
+            let borrowed = map.borrow();
+            if let Some(value) = borrowed.get(key) {
+                return Some(value.clone());
+            }
+            match ISA.with(|isa| borrowed.get(isa).cloned()) {
+                Some(Value::Map(parent)) if !visited.contains(&Rc::as_ptr(&parent)) => parent,
+                _ => return None,
+            }
+        };
+        map = parent;
+    }
+}
+
 fn abs_clamp_01(mut num: f64) -> f64 {
     if num < 0. {
         num = -num;
@@ -58,6 +178,42 @@ fn abs_clamp_01(mut num: f64) -> f64 {
 }
 
 impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Function(_) => "function",
+        }
+    }
+
+    pub fn new_list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn new_map(items: ValueMap) -> Value {
+        Value::Map(Rc::new(RefCell::new(items)))
+    }
+
+    /// Address of the storage shared by copies of a list or a map, used to detect values that
+    /// contain themselves
+    pub(crate) fn container_ptr(&self) -> Option<*const ()> {
+        match self {
+            Value::List(list) => Some(Rc::as_ptr(list) as *const ()),
+            Value::Map(map) => Some(Rc::as_ptr(map) as *const ()),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(str) => Some(str),
+            _ => None,
+        }
+    }
+
     pub fn as_f64(&self) -> f64 {
         match self {
             Value::Number(val) => *val,
@@ -91,6 +247,81 @@ impl Value {
         match self {
             Value::Null => false,
             Value::Number(num) => *num > 0.,
+            Value::String(str) => !str.is_empty(),
+            Value::List(list) => !list.borrow().is_empty(),
+            Value::Map(map) => !map.borrow().is_empty(),
+            Value::Function(_) => true,
+        }
+    }
+
+    /// Reads an element of a list or a string, or a value of a map, following the `__isa` chain
+    pub fn get_index(&self, index: &Value) -> Result<Value, RuntimeError> {
+        match self {
+            Value::List(list) => {
+                let list = list.borrow();
+                Ok(list[sequence_index(index, list.len())?].clone())
+            }
+            Value::String(str) => {
+                let chars = str.chars().collect::<Vec<_>>();
+                let char = chars[sequence_index(index, chars.len())?];
+                Ok(Value::from(char.to_string()))
+            }
+            Value::Map(map) => {
+                check_key(index)?;
+                map_get(map, index).ok_or_else(|| RuntimeError::KeyNotFound(index.to_string()))
+            }
+            val => Err(RuntimeError::TypeMismatch {
+                expected: "list, string or map",
+                got: val.type_name(),
+            }),
+        }
+    }
+
+    /// Reads a value of a map by a constant key, following the `__isa` chain
+    pub fn get_field(&self, key: &ConstantKey) -> Result<Value, RuntimeError> {
+        match self {
+            Value::Map(map) => {
+                map_get(map, key).ok_or_else(|| RuntimeError::KeyNotFound(key.value().to_string()))
+            }
+            _ => self.get_index(key.value()),
+        }
+    }
+
+    /// Writes a value of a map by a constant key
+    pub fn set_field(&self, key: &ConstantKey, value: Value) -> Result<(), RuntimeError> {
+        match self {
+            Value::Map(map) => {
+                let mut map = map.borrow_mut();
+                match map.get_mut(key) {
+                    Some(slot) => *slot = value,
+                    None => {
+                        map.insert(key.value().clone(), value);
+                    }
+                }
+                Ok(())
+            }
+            _ => self.set_index(key.value().clone(), value),
+        }
+    }
+
+    /// Writes an element of a list, or a value of a map
+    pub fn set_index(&self, index: Value, value: Value) -> Result<(), RuntimeError> {
+        match self {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let index = sequence_index(&index, list.len())?;
+                list[index] = value;
+                Ok(())
+            }
+            Value::Map(map) => {
+                check_key(&index)?;
+                map.borrow_mut().insert(index, value);
+                Ok(())
+            }
+            val => Err(RuntimeError::TypeMismatch {
+                expected: "list or map",
+                got: val.type_name(),
+            }),
         }
     }
 
@@ -146,6 +377,104 @@ impl Value {
     }
 }
 
+impl Value {
+    /// Formats the value the way it appears inside of a collection, with strings quoted
+    fn fmt_nested(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        ancestors: &mut Vec<*const ()>,
+    ) -> std::fmt::Result {
+        match self {
+            Value::String(str) => write!(f, "\"{str}\""),
+            Value::List(_) | Value::Map(_) => self.fmt_collection(f, ancestors),
+            val => write!(f, "{val}"),
+        }
+    }
+
+    /// Formats a list or a map. Collections containing themselves are written as `[...]` or
+    /// `{...}` where the cycle closes
+    fn fmt_collection(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        ancestors: &mut Vec<*const ()>,
+    ) -> std::fmt::Result {
+        let pointer = self.container_ptr().expect("Value is not a collection");
+        if ancestors.contains(&pointer) {
+            return match self {
+                Value::List(_) => write!(f, "[...]"),
+                _ => write!(f, "{{...}}"),
+            };
+        }
+        ancestors.push(pointer);
+        match self {
+            Value::List(list) => {
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f, ancestors)?;
+                }
+                write!(f, "]")?;
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_nested(f, ancestors)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f, ancestors)?;
+                }
+                write!(f, "}}")?;
+            }
+            _ => unreachable!(),
+        }
+        ancestors.pop();
+        Ok(())
+    }
+
+    /// Compares values, treating a pair of collections that is already being compared further up
+    /// as equal, so values containing themselves don't recurse forever
+    fn eq_guarded(&self, other: &Value, ancestors: &mut Vec<(*const (), *const ())>) -> bool {
+        let (Some(a), Some(b)) = (self.container_ptr(), other.container_ptr()) else {
+            return match (self, other) {
+                (Value::Null, Value::Null) => false,
+                (Value::Number(a), Value::Number(b)) => a == b,
+                // Strings of the same constant share their allocation
+                (Value::String(a), Value::String(b)) => Rc::ptr_eq(a, b) || a == b,
+                (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+                _ => false,
+            };
+        };
+        if a == b || ancestors.contains(&(a, b)) {
+            return true;
+        }
+        ancestors.push((a, b));
+        let equal = match (self, other) {
+            (Value::List(a), Value::List(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(a, b)| a.eq_guarded(b, ancestors))
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len()
+                    && a.iter().all(|(key, value)| {
+                        b.get(key)
+                            .is_some_and(|other| value.eq_guarded(other, ancestors))
+                    })
+            }
+            _ => false,
+        };
+        ancestors.pop();
+        equal
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -153,22 +482,46 @@ impl Display for Value {
             Value::Number(val) => {
                 write!(f, "{val}")
             }
+            Value::String(str) => write!(f, "{str}"),
+            Value::List(_) | Value::Map(_) => self.fmt_collection(f, &mut vec![]),
+            Value::Function(func) => {
+                let params = func
+                    .params()
+                    .iter()
+                    .map(|param| param.name.as_str())
+                    .collect::<Vec<_>>();
+                write!(f, "FUNCTION({})", params.join(", "))
+            }
         }
     }
 }
 
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Value::Null, Value::Null) => false,
-            (Value::Number(a), Value::Number(b)) => a == b,
-            _ => false,
-        }
+        self.eq_guarded(other, &mut vec![])
     }
 }
 
 impl Eq for Value {}
 
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Null => {}
+            // Positive and negative zeroes are equal, so they must have the same hash
+            Value::Number(num) => (if *num == 0. { 0. } else { *num }).to_bits().hash(state),
+            // Text is hashed separately, so `ConstantKey` can keep its hash
+            Value::String(str) => state.write_u64(string_hash(str)),
+            // Lists and maps can't be map keys, so only their size is hashed, which is also
+            // independent of map entry order and doesn't recurse into values containing themselves
+            Value::List(list) => list.borrow().len().hash(state),
+            Value::Map(map) => map.borrow().len().hash(state),
+            Value::Function(func) => Rc::as_ptr(func).hash(state),
+        }
+    }
+}
+
 impl_op_ex!(+|a: &Value, b: &Value| -> Value { numeric_op(a, b, |a, b| a + b) });
 
 impl_op_ex!(-|a: &Value, b: &Value| -> Value { numeric_op(a, b, |a, b| a - b) });
@@ -184,10 +537,35 @@ impl Neg for Value {
         match self {
             Value::Null => Value::Number(-0.),
             Value::Number(num) => Value::Number(-num),
+            _ => Value::Null,
         }
     }
 }
 
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value.into())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::new_list(value)
+    }
+}
+
+impl From<ValueMap> for Value {
+    fn from(value: ValueMap) -> Self {
+        Value::new_map(value)
+    }
+}
+
 impl From<bool> for Value {
     fn from(value: bool) -> Self {
         Self::from(value as usize)
@@ -203,6 +581,7 @@ numeric_from!(u32);
 numeric_from!(u64);
 numeric_from!(u128);
 numeric_from!(usize);
+numeric_from!(isize);
 
 numeric_from!(i8);
 numeric_from!(i16);
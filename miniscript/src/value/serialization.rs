@@ -0,0 +1,164 @@
+use crate::value::{Value, ValueMap};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Error, SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+use std::fmt::Formatter;
+
+/// Largest integer that is exactly representable by f64
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.;
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Guarded {
+            value: self,
+            ancestors: &RefCell::new(vec![]),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Value with lists and maps it's nested in, so values containing themselves fail to serialize
+/// instead of overflowing the stack
+struct Guarded<'a> {
+    value: &'a Value,
+    ancestors: &'a RefCell<Vec<*const ()>>,
+}
+
+impl Guarded<'_> {
+    fn nested<'a>(&'a self, value: &'a Value) -> Guarded<'a> {
+        Guarded {
+            value,
+            ancestors: self.ancestors,
+        }
+    }
+}
+
+impl Serialize for Guarded<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(pointer) = self.value.container_ptr() {
+            if self.ancestors.borrow().contains(&pointer) {
+                return Err(S::Error::custom(format!(
+                    "{} contains itself",
+                    self.value.type_name()
+                )));
+            }
+            self.ancestors.borrow_mut().push(pointer);
+        }
+        let result = match self.value {
+            Value::Null => serializer.serialize_unit(),
+            Value::Number(num) => {
+                // Whole numbers are emitted as integers, so `1` doesn't turn into `1.0`
+                if num.fract() == 0. && num.abs() <= MAX_SAFE_INTEGER {
+                    serializer.serialize_i64(*num as i64)
+                } else {
+                    serializer.serialize_f64(*num)
+                }
+            }
+            Value::String(str) => serializer.serialize_str(str),
+            Value::List(list) => {
+                let list = list.borrow();
+                let mut seq = serializer.serialize_seq(Some(list.len()))?;
+                for item in list.iter() {
+                    seq.serialize_element(&self.nested(item))?;
+                }
+                seq.end()
+            }
+            Value::Map(map) => {
+                let map = map.borrow();
+                let mut ser = serializer.serialize_map(Some(map.len()))?;
+                for (key, value) in map.iter() {
+                    // Most formats only support string keys, so other keys are stringified
+                    match key {
+                        Value::String(str) => ser.serialize_key(&**str)?,
+                        key => ser.serialize_key(&key.to_string())?,
+                    }
+                    ser.serialize_value(&self.nested(value))?;
+                }
+                ser.end()
+            }
+            Value::Function(func) => Err(S::Error::custom(format!(
+                "function `{}` can not be serialized",
+                func.name()
+            ))),
+        };
+        if self.value.container_ptr().is_some() {
+            self.ancestors.borrow_mut().pop();
+        }
+        result
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a null, boolean, number, string, sequence or map")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::new_list(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut items = ValueMap::default();
+        while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+            if let Value::List(_) | Value::Map(_) = key {
+                return Err(de::Error::custom(format!(
+                    "{} can not be a map key",
+                    key.type_name()
+                )));
+            }
+            items.insert(key, value);
+        }
+        Ok(Value::new_map(items))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
@@ -1,19 +1,144 @@
+use crate::errors::RuntimeError;
+use crate::intrinsics::sandboxed::{register_modules, Capabilities};
+use crate::intrinsics::{standard_intrinsics, Intrinsic};
+use crate::symbol::Symbol;
+use crate::value::ConstantKey;
 use crate::vm::chunk::Chunk;
 use crate::vm::register::StackIndex;
 use crate::{errors::MsError, value::Value};
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use rustc_hash::FxHashMap;
 use std::ops::{Index, IndexMut};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 pub mod op_code;
 
+pub mod cfg;
 pub mod chunk;
+pub mod profiler;
 pub mod register;
+pub mod snapshot;
 
+/// Seed of the random number generator of new VMs, so runs are reproducible by default
+pub const DEFAULT_SEED: u64 = 0;
+
+/// Calls that can be nested in each other. Intrinsics like `import` run scripts that call
+/// intrinsics again on the host stack, so the limit keeps it from overflowing
+pub const MAX_CALL_DEPTH: usize = 64;
+
+#[derive(Debug)]
 pub struct Vm {
     pub cursor: usize,
     pub stack: Vec<Value>,
+    pub globals: FxHashMap<Symbol, Value>,
+    /// Values of constant strings, so all their uses share one allocation, and compare equal by
+    /// pointer
+    pub strings: FxHashMap<Symbol, ConstantKey>,
+    /// Generator behind `rnd`, kept in the VM so it's captured by snapshots
+    pub rng: Pcg32,
+    /// Lines printed by the script. When `None`, they are written to stdout instead
+    pub output: Option<Vec<String>>,
+    /// Optional intrinsic modules enabled by the host
+    pub capabilities: Capabilities,
+    /// Directory that file intrinsics are restricted to
+    pub file_root: Option<PathBuf>,
+    /// Moment that `time` is measured from
+    pub started: Instant,
+    /// Duration requested by `wait`, until a resumable runner suspends the script
+    pub(crate) suspension: Option<Duration>,
+    /// Calls that are currently running, up to `MAX_CALL_DEPTH`
+    pub(crate) call_depth: usize,
+    /// Value returned by the script, taken by the host once it finishes
+    pub returned: Option<Value>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        let mut vm = Self {
+            cursor: 0,
+            stack: vec![],
+            globals: Default::default(),
+            strings: Default::default(),
+            rng: Pcg32::seed_from_u64(DEFAULT_SEED),
+            output: None,
+            capabilities: Capabilities::empty(),
+            file_root: None,
+            started: Instant::now(),
+            suspension: None,
+            call_depth: 0,
+            returned: None,
+        };
+        for intrinsic in standard_intrinsics() {
+            vm.register_intrinsic(intrinsic);
+        }
+        vm
+    }
 }
 
 impl Vm {
+    pub fn new(chunk: &Chunk) -> Self {
+        Self {
+            stack: vec![Value::Null; chunk.stack_size()],
+            ..Default::default()
+        }
+    }
+
+    /// Makes printed lines to be collected into `output` instead of stdout
+    pub fn capture_output(&mut self) {
+        self.output = Some(vec![]);
+    }
+
+    /// Enables optional intrinsic modules, defining their globals
+    pub fn enable(&mut self, capabilities: Capabilities) {
+        self.capabilities |= capabilities;
+        register_modules(self);
+    }
+
+    /// Sets the directory that scripts can access files in. Files are only accessible when it's
+    /// set, and the file capabilities are enabled
+    pub fn set_file_root(&mut self, root: impl Into<PathBuf>) {
+        self.file_root = Some(root.into());
+    }
+
+    pub fn print(&mut self, value: &Value) {
+        let line = value.to_string();
+        match &mut self.output {
+            Some(output) => output.push(line),
+            None => println!("{line}"),
+        }
+    }
+
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Pcg32::seed_from_u64(seed);
+    }
+
+    pub fn set_global(&mut self, name: impl Into<Symbol>, value: impl Into<Value>) {
+        self.globals.insert(name.into(), value.into());
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        // Names that were never interned can't be defined
+        self.globals.get(&Symbol::lookup(name)?)
+    }
+
+    pub fn register_intrinsic(&mut self, intrinsic: Intrinsic) {
+        self.set_global(Symbol::intern(intrinsic.name()), intrinsic);
+    }
+
+    /// String value of the symbol, shared with its other uses in this VM
+    pub fn symbol_value(&mut self, symbol: Symbol) -> Value {
+        self.symbol_key(symbol).value().clone()
+    }
+
+    /// Map key of the symbol, which is hashed only once per VM
+    pub fn symbol_key(&mut self, symbol: Symbol) -> &ConstantKey {
+        self.strings
+            .entry(symbol)
+            .or_insert_with(|| ConstantKey::new(symbol.as_str().into()))
+    }
+
     #[inline(always)]
     pub fn stack_offset(&self) -> usize {
         0
@@ -45,13 +170,89 @@ pub struct DefaultRunner;
 impl VmRunner for DefaultRunner {
     fn run(&mut self, chunk: &Chunk, vm: &mut Vm) -> Result<(), MsError> {
         while vm.cursor < chunk.code().len() {
-            let op_code = &chunk.code()[vm.cursor];
-            op_code.step(vm).map_err(|err| MsError {
-                src_id: chunk.get_src_id().to_string(),
-                error_type: err,
-            })?;
+            step(chunk, vm)?;
         }
 
         Ok(())
     }
 }
+
+/// State of the script when `ResumableRunner` returns control to the host
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RunState {
+    Finished,
+    /// Script called `wait`, and should be resumed once the duration passes
+    Suspended(Duration),
+}
+
+/// Runner that returns control to the host when the script calls `wait`, instead of blocking the
+/// thread. Other runners don't suspend, so `wait` returns immediately with them
+#[derive(Debug, Default)]
+pub struct ResumableRunner;
+
+impl ResumableRunner {
+    /// Runs the script until it finishes or waits. Calling it again continues after the `wait`
+    pub fn resume(&mut self, chunk: &Chunk, vm: &mut Vm) -> Result<RunState, MsError> {
+        // Requests left by other runners are stale
+        vm.suspension = None;
+        while vm.cursor < chunk.code().len() {
+            step(chunk, vm)?;
+            if let Some(duration) = vm.suspension.take() {
+                return Ok(RunState::Suspended(duration));
+            }
+        }
+
+        Ok(RunState::Finished)
+    }
+}
+
+/// Runner that stops the script with an error after executing the given number of instructions
+pub struct BudgetRunner {
+    budget: usize,
+    executed: usize,
+}
+
+impl BudgetRunner {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            executed: 0,
+        }
+    }
+
+    /// Number of instructions executed so far
+    pub fn executed(&self) -> usize {
+        self.executed
+    }
+}
+
+impl VmRunner for BudgetRunner {
+    fn run(&mut self, chunk: &Chunk, vm: &mut Vm) -> Result<(), MsError> {
+        while vm.cursor < chunk.code().len() {
+            if self.executed >= self.budget {
+                return Err(MsError {
+                    src_id: chunk.get_src_id().to_string(),
+                    error_type: RuntimeError::BudgetExceeded(self.budget).into(),
+                });
+            }
+            self.executed += 1;
+            step(chunk, vm)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Executes a single instruction at the cursor
+pub(crate) fn step(chunk: &Chunk, vm: &mut Vm) -> Result<(), MsError> {
+    let cursor = vm.cursor;
+    let op_code = &chunk.code()[cursor];
+    op_code.step(chunk, vm).map_err(|err| {
+        // Point the cursor back at the failing instruction for error reporting
+        vm.cursor = cursor;
+        MsError {
+            src_id: chunk.get_src_id().to_string(),
+            error_type: err,
+        }
+    })
+}
@@ -0,0 +1,206 @@
+use crate::vm::chunk::Chunk;
+use crate::vm::op_code::OpCode;
+use std::collections::BTreeSet;
+
+/// Control flow graph of a chunk, with instructions split into basic blocks
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    blocks: Vec<BasicBlock>,
+}
+
+/// Sequence of instructions that is always executed from start to end
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    /// Index of the first instruction
+    pub start: usize,
+    /// Index after the last instruction
+    pub end: usize,
+    pub successors: Vec<Edge>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Edge {
+    pub target: Target,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Target {
+    /// Index of the block
+    Block(usize),
+    /// End of the chunk
+    Exit,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction
+    Fallthrough,
+    /// Unconditional jump
+    Jump,
+    /// Conditional jump, when the condition holds
+    Taken,
+    /// Conditional jump, when the condition doesn't hold
+    NotTaken,
+}
+
+impl Cfg {
+    pub fn new(chunk: &Chunk) -> Self {
+        let code = chunk.code();
+
+        // Blocks start at the beginning, at jump targets, and after each jump or terminator
+        let mut leaders = BTreeSet::from([0]);
+        for (i, op_code) in code.iter().enumerate() {
+            if let Some(target) = jump_target(op_code) {
+                leaders.insert(target);
+            }
+            if jump_target(op_code).is_some() || is_terminator(op_code) {
+                leaders.insert(i + 1);
+            }
+        }
+        let leaders = leaders
+            .into_iter()
+            .filter(|leader| *leader < code.len())
+            .collect::<Vec<_>>();
+
+        let block_at = |index: usize| match leaders.binary_search(&index) {
+            Ok(block) => Target::Block(block),
+            Err(_) => Target::Exit,
+        };
+        let blocks = leaders
+            .iter()
+            .enumerate()
+            .map(|(block, &start)| {
+                let end = leaders.get(block + 1).copied().unwrap_or(code.len());
+                let last = &code[end - 1];
+                let mut successors = vec![];
+                match last {
+                    OpCode::Jump(target) => successors.push(Edge {
+                        target: block_at(*target),
+                        kind: EdgeKind::Jump,
+                    }),
+                    OpCode::Return(_) => successors.push(Edge {
+                        target: Target::Exit,
+                        kind: EdgeKind::Jump,
+                    }),
+                    // Errors stop the execution, so the block has no successors
+                    op_code if is_terminator(op_code) => {}
+                    op_code => {
+                        if let Some(target) = jump_target(op_code) {
+                            successors.push(Edge {
+                                target: block_at(target),
+                                kind: EdgeKind::Taken,
+                            });
+                        }
+                        successors.push(Edge {
+                            target: block_at(end),
+                            kind: if jump_target(op_code).is_some() {
+                                EdgeKind::NotTaken
+                            } else {
+                                EdgeKind::Fallthrough
+                            },
+                        });
+                    }
+                }
+                BasicBlock {
+                    start,
+                    end,
+                    successors,
+                }
+            })
+            .collect();
+
+        Self { blocks }
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// Index of the block containing the instruction
+    pub fn block_of(&self, index: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| (block.start..block.end).contains(&index))
+    }
+
+    /// Indices of blocks that have an edge leading to the block
+    pub fn predecessors(&self, block: usize) -> Vec<usize> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, pred)| {
+                pred.successors
+                    .iter()
+                    .any(|edge| edge.target == Target::Block(block))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Exports the graph in Graphviz DOT format, listing instructions of each block along with
+    /// the source code they were compiled from
+    pub fn to_dot(&self, chunk: &Chunk, source: &str) -> String {
+        let mut dot = format!(
+            "digraph \"{}\" {{\n    node [shape=box, fontname=\"monospace\"];\n",
+            escape(chunk.get_src_id())
+        );
+        for (i, block) in self.blocks.iter().enumerate() {
+            let label = (block.start..block.end)
+                .map(|index| {
+                    let snippet = source[chunk.spans()[index].into_range()]
+                        .lines()
+                        .next()
+                        .unwrap_or("")
+                        .trim();
+                    format!(
+                        "{index}: {}  |  {}\\l",
+                        escape(&chunk.code()[index].pretty_print()),
+                        escape(snippet)
+                    )
+                })
+                .collect::<String>();
+            dot += &format!("    b{i} [label=\"{label}\"];\n");
+        }
+        dot += "    exit [shape=oval];\n";
+        for (i, block) in self.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                let target = match edge.target {
+                    Target::Block(target) => format!("b{target}"),
+                    Target::Exit => "exit".to_string(),
+                };
+                let attributes = match edge.kind {
+                    EdgeKind::Fallthrough | EdgeKind::Jump => "",
+                    EdgeKind::Taken => match &chunk.code()[block.end - 1] {
+                        OpCode::JumpIfFalse(_, _) => " [label=\"false\"]",
+                        OpCode::JumpIfAbsOneOrGreater(_, _) => " [label=\">= 1\"]",
+                        _ => " [label=\"true\"]",
+                    },
+                    EdgeKind::NotTaken => " [style=dashed]",
+                };
+                dot += &format!("    b{i} -> {target}{attributes};\n");
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+pub(crate) fn jump_target(op_code: &OpCode) -> Option<usize> {
+    match op_code {
+        OpCode::JumpIfFalse(_, target)
+        | OpCode::JumpIfTrue(_, target)
+        | OpCode::JumpIfAbsOneOrGreater(_, target)
+        | OpCode::Jump(target) => Some(*target),
+        _ => None,
+    }
+}
+
+/// Whether the instruction never passes execution to the next one
+fn is_terminator(op_code: &OpCode) -> bool {
+    matches!(op_code, OpCode::Return(_) | OpCode::Error(_))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -1,13 +1,22 @@
 use crate::ast::{
-    ast_err, BinaryOp, Body, Comparison, Expr, Path, Span, Spanned, Statement, UnaryOp, Value, AST,
+    BinaryOp, Body, Comparison, Expr, Path, Span, Spanned, Statement, UnaryOp, Value, AST,
 };
+use crate::symbol::Symbol;
+use crate::vm::cfg::jump_target;
 use crate::vm::op_code::{BytecodeError, OpCode};
 use crate::vm::register::StackIndex;
+use bincode::Options;
 use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Reverse;
 use std::collections::BinaryHeap;
+use thiserror::Error;
 
-#[derive(Debug, Copy, Clone)]
+/// Magic bytes at the start of serialized chunks, followed by the format version
+const BYTECODE_MAGIC: &[u8; 4] = b"MSBC";
+const BYTECODE_VERSION: u8 = 2;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct ConstantIndex(usize);
 
 impl ConstantIndex {
@@ -16,12 +25,17 @@ impl ConstantIndex {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
     src_id: String,
     code: Vec<OpCode>,
+    #[serde(
+        serialize_with = "serialize_spans",
+        deserialize_with = "deserialize_spans"
+    )]
     spans: Vec<Span>,
-    strings: Vec<String>,
+    /// String literals and names used by the code, which are interned in the shared pool
+    constants: Vec<Symbol>,
     stack_size: usize,
 }
 
@@ -30,8 +44,8 @@ impl Chunk {
         &self.src_id
     }
 
-    pub fn get_constant(&self, index: &ConstantIndex) -> &str {
-        &self.strings[index.0]
+    pub fn get_constant(&self, index: &ConstantIndex) -> Symbol {
+        self.constants[index.0]
     }
 
     pub fn code(&self) -> &Vec<OpCode> {
@@ -45,6 +59,120 @@ impl Chunk {
     pub fn stack_size(&self) -> usize {
         self.stack_size
     }
+
+    /// Whether the bytes start with the header of serialized bytecode
+    pub fn is_bytecode(bytes: &[u8]) -> bool {
+        bytes.starts_with(BYTECODE_MAGIC)
+    }
+
+    /// Serializes the chunk into bytecode, which can be loaded without the source code
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = BYTECODE_MAGIC.to_vec();
+        bytes.push(BYTECODE_VERSION);
+        bytecode_options()
+            .serialize_into(&mut bytes, self)
+            .expect("Failed to serialize chunk");
+        bytes
+    }
+
+    /// Loads a chunk from bytecode, checking that its instructions stay within the stack, the
+    /// constants and the code, so a corrupted file can't make the VM panic
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytecodeLoadError> {
+        let Some(rest) = bytes.strip_prefix(BYTECODE_MAGIC) else {
+            return Err(BytecodeLoadError::InvalidHeader);
+        };
+        match rest.split_first() {
+            Some((&BYTECODE_VERSION, data)) => {
+                // Lengths in the data can't exceed the data itself
+                let chunk: Chunk = bytecode_options()
+                    .with_limit(data.len() as u64)
+                    .deserialize(data)?;
+                chunk.verify()?;
+                Ok(chunk)
+            }
+            Some((version, _)) => Err(BytecodeLoadError::UnsupportedVersion(*version)),
+            None => Err(BytecodeLoadError::InvalidHeader),
+        }
+    }
+
+    fn verify(&self) -> Result<(), BytecodeLoadError> {
+        let malformed = |message: String| Err(BytecodeLoadError::Malformed(message));
+        if self.spans.len() != self.code.len() {
+            return malformed(format!(
+                "{} spans for {} instructions",
+                self.spans.len(),
+                self.code.len()
+            ));
+        }
+        for (i, op_code) in self.code.iter().enumerate() {
+            if let Some(register) = op_code
+                .registers()
+                .into_iter()
+                .find(|register| register.0 >= self.stack_size)
+            {
+                return malformed(format!(
+                    "Instruction {i} uses register ${register}, but the stack size is {}",
+                    self.stack_size
+                ));
+            }
+            if let OpCode::SetString(_, index)
+            | OpCode::ReadField { field: index, .. }
+            | OpCode::WriteField { field: index, .. } = op_code
+            {
+                if index.0 >= self.constants.len() {
+                    return malformed(format!(
+                        "Instruction {i} uses constant {}, but there are {} constants",
+                        index.0,
+                        self.constants.len()
+                    ));
+                }
+            }
+            if let Some(target) = jump_target(op_code) {
+                if target > self.code.len() {
+                    return malformed(format!(
+                        "Instruction {i} jumps to {target}, but there are {} instructions",
+                        self.code.len()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encoding of `bincode::serialize`, which options have to be repeated to set a size limit
+fn bytecode_options() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+#[derive(Debug, Error)]
+pub enum BytecodeLoadError {
+    #[error("File is not a miniscript bytecode")]
+    InvalidHeader,
+    #[error("Bytecode version {} is not supported", .0)]
+    UnsupportedVersion(u8),
+    #[error("Malformed bytecode: {}", .0)]
+    Malformed(String),
+}
+
+impl From<bincode::Error> for BytecodeLoadError {
+    fn from(err: bincode::Error) -> Self {
+        BytecodeLoadError::Malformed(err.to_string())
+    }
+}
+
+fn serialize_spans<S: Serializer>(spans: &[Span], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(spans.iter().map(|span| (span.start, span.end)))
+}
+
+fn deserialize_spans<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Span>, D::Error> {
+    let spans = Vec::<(usize, usize)>::deserialize(deserializer)?;
+    Ok(spans
+        .into_iter()
+        .map(|(start, end)| Span::from(start..end))
+        .collect())
 }
 
 pub fn pretty_print(chunk: &Chunk, source: &str) -> String {
@@ -74,6 +202,7 @@ pub fn pretty_print(chunk: &Chunk, source: &str) -> String {
 pub fn compile_chunk<'src>(ast: AST<'src>) -> Chunk {
     let (body, src_id) = ast.into_body_src();
     let mut ctx = FunctionCompilationContext::<'src>::new();
+    ctx.dead_after = analyze_liveness(&body);
     compile_body(&body, &mut ctx);
     ctx.emit(OpCode::Return(None), Span::from(0..0));
     ctx.chunk.stack_size = ctx.next_register;
@@ -81,6 +210,31 @@ pub fn compile_chunk<'src>(ast: AST<'src>) -> Chunk {
     ctx.chunk
 }
 
+/// Compiles the chunk so it returns the value of its last statement, if that's an expression.
+///
+/// Used for evaluating snippets, where `1 + 2` is expected to produce `3` rather than be skipped
+/// for having no side effects.
+pub fn compile_chunk_with_result<'src>(ast: AST<'src>) -> Chunk {
+    let (body, src_id) = ast.into_body_src();
+    let mut ctx = FunctionCompilationContext::<'src>::new();
+    ctx.dead_after = analyze_liveness(&body);
+    match body.split_last() {
+        Some(((Statement::Expression(expr), _), rest)) => {
+            compile_body(rest, &mut ctx);
+            ctx.next_statement();
+            let register = compile_expressions(expr, None, &mut ctx, false);
+            ctx.emit(OpCode::Return(Some(register)), expr.1);
+        }
+        _ => {
+            compile_body(&body, &mut ctx);
+            ctx.emit(OpCode::Return(None), Span::from(0..0));
+        }
+    }
+    ctx.chunk.stack_size = ctx.next_register;
+    ctx.chunk.src_id = src_id;
+    ctx.chunk
+}
+
 struct FunctionCompilationContext<'src> {
     use_locals_map: bool,
     declared_variables: FxHashMap<&'src str, VariableInfo>,
@@ -88,6 +242,12 @@ struct FunctionCompilationContext<'src> {
     free_registers: BinaryHeap<Reverse<StackIndex>>,
     patches: FxHashSet<usize>,
     assignment_spans: Vec<usize>,
+    /// Number of statements compiled so far, used as id of the next statement
+    statement_count: usize,
+    /// Locals that are not used after the statement with this id, so their registers are reused
+    dead_after: FxHashMap<usize, Vec<&'src str>>,
+    /// Positions of symbols in the constants of the chunk
+    constant_indices: FxHashMap<Symbol, ConstantIndex>,
     chunk: Chunk,
 }
 
@@ -100,11 +260,14 @@ impl<'src> FunctionCompilationContext<'src> {
             free_registers: Default::default(),
             patches: Default::default(),
             assignment_spans: vec![],
+            statement_count: 0,
+            dead_after: Default::default(),
+            constant_indices: Default::default(),
             chunk: Chunk {
                 src_id: "".to_string(),
                 code: vec![],
                 spans: vec![],
-                strings: vec![],
+                constants: vec![],
                 stack_size: 0,
             },
         }
@@ -134,6 +297,19 @@ impl<'src> FunctionCompilationContext<'src> {
         StackIndex(next)
     }
 
+    /// Allocates `count` consecutive registers at the top of the stack, bypassing free registers
+    fn get_register_block(&mut self, count: usize) -> StackIndex {
+        let start = self.next_register;
+        self.next_register += count;
+        StackIndex(start)
+    }
+
+    fn release_register_block(&mut self, start: StackIndex, count: usize) {
+        for i in 0..count {
+            self.release_register(start.next(i));
+        }
+    }
+
     fn release_register(&mut self, register: StackIndex) {
         self.free_registers.push(Reverse(register));
     }
@@ -236,11 +412,12 @@ impl<'src> FunctionCompilationContext<'src> {
     }
 
     fn get_or_create_constant_index(&mut self, item: &str) -> ConstantIndex {
-        if let Some(index) = self.chunk.strings.iter().position(|x| x == item) {
-            return ConstantIndex(index);
-        }
-        self.chunk.strings.push(item.to_owned());
-        ConstantIndex(self.chunk.strings.len() - 1)
+        let symbol = Symbol::intern(item);
+        let constants = &mut self.chunk.constants;
+        *self.constant_indices.entry(symbol).or_insert_with(|| {
+            constants.push(symbol);
+            ConstantIndex(constants.len() - 1)
+        })
     }
 
     fn check_for_trash(&self) {
@@ -267,6 +444,20 @@ impl<'src> FunctionCompilationContext<'src> {
         }
     }
 
+    fn next_statement(&mut self) -> usize {
+        self.statement_count += 1;
+        self.statement_count - 1
+    }
+
+    /// Releases registers of locals whose live range ends at the statement
+    fn release_dead_locals(&mut self, statement: usize) {
+        for ident in self.dead_after.remove(&statement).unwrap_or_default() {
+            if let Some(var) = self.declared_variables.remove(ident) {
+                self.release_register(var.register);
+            }
+        }
+    }
+
     fn ops_length(&self) -> usize {
         self.chunk.code.len()
     }
@@ -292,33 +483,126 @@ impl<'src> FunctionCompilationContext<'src> {
 
 struct ReservedOpSpace(usize);
 
-fn compile_body<'src>(body: &Body<'src>, ctx: &mut FunctionCompilationContext<'src>) {
+fn compile_body<'src>(
+    body: &[Spanned<Statement<'src>>],
+    ctx: &mut FunctionCompilationContext<'src>,
+) {
     for (statement, span) in body {
+        let id = ctx.next_statement();
         match statement {
             Statement::Expression(expr) => {
                 // Expressions statements without side effects are ignored
-                if !can_have_side_effects(&expr.0, ctx) {
-                    continue;
+                if can_have_side_effects(&expr.0, ctx.use_locals_map) {
+                    let reg = compile_expressions(expr, None, ctx, false);
+                    ctx.release_if_unused(reg);
                 }
-
-                let reg = compile_expressions(expr, None, ctx, false);
-                ctx.release_register(reg);
             }
             Statement::Assignment(lhs, rhs) => compile_assignment(lhs, rhs, span, ctx),
             Statement::If(chain, else_body) => {
                 compile_if(chain, else_body, span, ctx);
             }
             Statement::While(condition, body) => compile_while(condition, body, span, ctx),
-            Statement::For(_, _, _) => todo!(),
-            Statement::Break => todo!(),
-            Statement::Continue => todo!(),
-            Statement::Return(_) => todo!(),
-            Statement::Error => todo!(),
+            Statement::For(_, _, _)
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Return(_) => ctx.emit(OpCode::Error(BytecodeError::NotImplemented), *span),
+            Statement::Error => ctx.emit(OpCode::Error(BytecodeError::SyntaxError), *span),
         }
+        ctx.release_dead_locals(id);
         ctx.check_for_trash();
     }
 }
 
+/// Finds the statement after which each variable is no longer used.
+///
+/// Statements are numbered in the order they are compiled. A variable lives from its first to
+/// its last mention, and mentions inside a loop keep it alive until the end of the loop, since
+/// the value is carried to the next iteration.
+fn analyze_liveness<'src>(body: &Body<'src>) -> FxHashMap<usize, Vec<&'src str>> {
+    let mut analysis = LivenessAnalysis::default();
+    analysis.body(body);
+
+    let mut dead_after = FxHashMap::<usize, Vec<&'src str>>::default();
+    for (ident, mentions) in analysis.mentions {
+        let mut end = *mentions.iter().max().expect("Variable is never mentioned");
+        for (start, loop_end) in &analysis.loops {
+            if mentions.iter().any(|id| (start..=loop_end).contains(&id)) {
+                end = end.max(*loop_end);
+            }
+        }
+        dead_after.entry(end).or_default().push(ident);
+    }
+    dead_after
+}
+
+#[derive(Default)]
+struct LivenessAnalysis<'src> {
+    statement_count: usize,
+    /// Ids of statements mentioning each variable
+    mentions: FxHashMap<&'src str, Vec<usize>>,
+    /// First and last statement ids of each loop
+    loops: Vec<(usize, usize)>,
+}
+
+impl<'src> LivenessAnalysis<'src> {
+    fn body(&mut self, body: &Body<'src>) {
+        for (statement, _) in body {
+            let id = self.statement_count;
+            self.statement_count += 1;
+            match statement {
+                Statement::Expression((expr, _)) => self.expr(expr, id),
+                Statement::Assignment((lhs, _), (rhs, _)) => {
+                    self.expr(lhs, id);
+                    self.expr(rhs, id);
+                }
+                Statement::If(chain, else_body) => {
+                    let mut else_if_conditions = vec![];
+                    for (i, (((condition, _), _), body)) in chain.iter().enumerate() {
+                        if i == 0 {
+                            self.expr(condition, id);
+                        } else {
+                            else_if_conditions.push(condition);
+                        }
+                        self.body(body);
+                    }
+                    if let Some(body) = else_body {
+                        self.body(body);
+                    }
+                    // Else-if conditions are compiled after the preceding branches, so they keep
+                    // their variables alive until the end of the whole chain
+                    for condition in else_if_conditions {
+                        self.expr(condition, self.statement_count - 1);
+                    }
+                }
+                Statement::While(((condition, _), _), body) => {
+                    self.expr(condition, id);
+                    self.body(body);
+                    self.loops.push((id, self.statement_count - 1));
+                }
+                Statement::For((ident, _), (iterable, _), body) => {
+                    self.mentions.entry(ident).or_default().push(id);
+                    self.expr(iterable, id);
+                    self.body(body);
+                    self.loops.push((id, self.statement_count - 1));
+                }
+                Statement::Return(Some((expr, _))) => self.expr(expr, id),
+                Statement::Return(None)
+                | Statement::Break
+                | Statement::Continue
+                | Statement::Error => {}
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr<'src>, id: usize) {
+        for expr in expr_iter(expr) {
+            if let Expr::Path(Path::AnyScope(ident)) = expr {
+                self.mentions.entry(ident).or_default().push(id);
+            }
+        }
+    }
+}
+
 fn compile_assignment<'src>(
     lhs: &Spanned<Expr<'src>>,
     rhs: &Spanned<Expr<'src>>,
@@ -347,11 +631,25 @@ fn compile_assignment<'src>(
                 ctx.set_can_be_function(ident, can_evaluate_to_function(&rhs.0, ctx));
             }
         },
-        Expr::Index(_, _) => {
-            todo!();
+        Expr::Index(target, name) => {
+            let target = compile_expressions(target, None, ctx, false);
+            let field = ctx.get_or_create_constant_index(name);
+            let value = compile_expressions(rhs, None, ctx, false);
+            ctx.emit(
+                OpCode::WriteField {
+                    target,
+                    field,
+                    value,
+                },
+                *span,
+            );
+            ctx.release_if_unused(target);
+            ctx.release_if_unused(value);
         }
-        Expr::ExprIndex(_, _) => {
-            todo!();
+        Expr::ExprIndex(target, index) => {
+            let target = compile_expressions(target, None, ctx, false);
+            let index = compile_expressions(index, None, ctx, false);
+            compile_index_assignment(target, index, rhs, span, ctx);
         }
         _ => {
             unreachable!("Invalid assignment target");
@@ -359,6 +657,27 @@ fn compile_assignment<'src>(
     };
 }
 
+fn compile_index_assignment<'src>(
+    target: StackIndex,
+    index: StackIndex,
+    rhs: &Spanned<Expr<'src>>,
+    span: &Span,
+    ctx: &mut FunctionCompilationContext<'src>,
+) {
+    let value = compile_expressions(rhs, None, ctx, false);
+    ctx.emit(
+        OpCode::WriteIndex {
+            target,
+            index,
+            value,
+        },
+        *span,
+    );
+    ctx.release_if_unused(target);
+    ctx.release_if_unused(index);
+    ctx.release_if_unused(value);
+}
+
 fn compile_if<'src>(
     ifs: &[(Spanned<Spanned<Expr<'src>>>, Body<'src>)],
     else_body: &Option<Body<'src>>,
@@ -435,18 +754,30 @@ fn compile_expressions<'src>(
             register
         }
         Expr::Path(path) => compile_path(path, *span, register, ctx, suppress_call),
-        Expr::List(_) => todo!(),
-        Expr::Map(_) => todo!(),
-        Expr::FunctionDefinition(_, _) => todo!(),
+        Expr::List(_) | Expr::Map(_) | Expr::FunctionDefinition(_, _) | Expr::Unary(_, _) => {
+            let register = ctx.actualize(register);
+            ctx.emit(OpCode::Error(BytecodeError::NotImplemented), *span);
+            register
+        }
         Expr::Comparison(lhs, comparisons) => {
             compile_comparison_chain(lhs, comparisons, *span, register, ctx)
         }
         Expr::Binary(lhs, op, rhs) => compile_binary_op(lhs, op, rhs, *span, register, ctx),
-        Expr::Unary(_, _) => todo!(),
         Expr::Call(expr, arguments) => compile_function_call(expr, arguments, *span, register, ctx),
-        Expr::ExprIndex(_, _) => todo!(),
-        Expr::Index(_, _) => todo!(),
-        Expr::Error => ast_err!(),
+        Expr::ExprIndex(target, index) => compile_index(target, index, *span, register, ctx),
+        Expr::Index(target, name) => {
+            if suppress_call {
+                compile_dot_index(target, name, *span, register, ctx)
+            } else {
+                // Reading a function from a map calls it as a method
+                compile_method_call(target, name, &[], *span, register, ctx)
+            }
+        }
+        Expr::Error => {
+            let register = ctx.actualize(register);
+            ctx.emit(OpCode::Error(BytecodeError::SyntaxError), *span);
+            register
+        }
     };
     if let Some(register) = register {
         assert_eq!(register, new_register, "Register mismatch");
@@ -483,14 +814,14 @@ fn compile_path<'src>(
                     // Local variable does not exist
                     (None, _) => {
                         let register = ctx.actualize(register);
-                        ctx.emit(OpCode::ReadVariable(register, (*ident).to_owned()), span);
+                        ctx.emit(OpCode::ReadVariable(register, Symbol::intern(ident)), span);
                         register
                     }
                 }
             } else {
                 let register = ctx.actualize(register);
                 let input = ctx.local_register(ident).unwrap_or_else(|| {
-                    ctx.emit(OpCode::ReadVariable(register, (*ident).to_owned()), span);
+                    ctx.emit(OpCode::ReadVariable(register, Symbol::intern(ident)), span);
                     register
                 });
                 // Calls found value with no arguments
@@ -507,6 +838,57 @@ fn compile_path<'src>(
     }
 }
 
+fn compile_index<'src>(
+    target: &Spanned<Expr<'src>>,
+    index: &Spanned<Expr<'src>>,
+    span: Span,
+    register: Option<StackIndex>,
+    ctx: &mut FunctionCompilationContext<'src>,
+) -> StackIndex {
+    let (output, released) = ctx.actualize_and_release_if_unused(register);
+    let target = compile_expressions(target, None, ctx, false);
+    let index = compile_expressions(index, None, ctx, false);
+    ctx.emit(
+        OpCode::ReadIndex {
+            output,
+            target,
+            index,
+        },
+        span,
+    );
+    ctx.release_if_unused(target);
+    ctx.release_if_unused(index);
+    if released {
+        ctx.take_back_register(output);
+    }
+    output
+}
+
+fn compile_dot_index<'src>(
+    target: &Spanned<Expr<'src>>,
+    name: &str,
+    span: Span,
+    register: Option<StackIndex>,
+    ctx: &mut FunctionCompilationContext<'src>,
+) -> StackIndex {
+    let (output, released) = ctx.actualize_and_release_if_unused(register);
+    let target = compile_expressions(target, None, ctx, false);
+    let field = ctx.get_or_create_constant_index(name);
+    ctx.emit(
+        OpCode::ReadField {
+            output,
+            target,
+            field,
+        },
+        span,
+    );
+    ctx.release_if_unused(target);
+    if released {
+        ctx.take_back_register(output);
+    }
+    output
+}
+
 fn compile_comparison_chain<'src>(
     lhs: &Spanned<Expr<'src>>,
     chain: &Vec<(Comparison, Spanned<Expr<'src>>)>,
@@ -517,11 +899,12 @@ fn compile_comparison_chain<'src>(
     let (register, released) = ctx.actualize_and_release_if_unused(register);
     if chain.len() == 1 {
         let (comparison, rhs) = &chain[0];
-        let lhs_register = if ctx.use_locals_map && can_have_side_effects(&rhs.0, ctx) {
-            Some(ctx.get_register())
-        } else {
-            None
-        };
+        let lhs_register =
+            if ctx.use_locals_map && can_have_side_effects(&rhs.0, ctx.use_locals_map) {
+                Some(ctx.get_register())
+            } else {
+                None
+            };
         let lhs_register = compile_expressions(lhs, lhs_register, ctx, false);
         let rhs_register = compile_expressions(rhs, None, ctx, false);
         ctx.emit(
@@ -536,12 +919,15 @@ fn compile_comparison_chain<'src>(
         let accumulator = ctx.get_register();
         ctx.emit(OpCode::SetNumber(accumulator, 1.), 0..0);
 
-        let lhs_register =
-            if ctx.use_locals_map && chain.iter().any(|e| can_have_side_effects(&e.1 .0, ctx)) {
-                Some(ctx.get_register())
-            } else {
-                None
-            };
+        let lhs_register = if ctx.use_locals_map
+            && chain
+                .iter()
+                .any(|e| can_have_side_effects(&e.1 .0, ctx.use_locals_map))
+        {
+            Some(ctx.get_register())
+        } else {
+            None
+        };
 
         // On each iteration, we compare lhs and rhs, and then transform rhs into the next lhs
         let mut lhs_register = compile_expressions(lhs, lhs_register, ctx, false);
@@ -550,14 +936,13 @@ fn compile_comparison_chain<'src>(
             let rhs_register = if ctx.use_locals_map
                 && chain[i + 1..]
                     .iter()
-                    .any(|e| can_have_side_effects(&e.1 .0, ctx))
+                    .any(|e| can_have_side_effects(&e.1 .0, ctx.use_locals_map))
             {
                 Some(ctx.get_register())
             } else {
                 None
             };
             let rhs_register = compile_expressions(rhs, rhs_register, ctx, false);
-            ctx.release_if_unused(lhs_register);
             let tmp_register = ctx.get_register();
             ctx.emit(
                 comparison_op(*comparison, lhs_register, rhs_register, tmp_register),
@@ -576,6 +961,8 @@ fn compile_comparison_chain<'src>(
             lhs_register = rhs_register;
             previous = rhs;
         }
+        // Right side of the last comparison is not used as the left side of anything
+        ctx.release_if_unused(lhs_register);
         ctx.release_if_unused(accumulator);
 
         if register != accumulator {
@@ -621,7 +1008,7 @@ fn compile_binary_op<'src>(
     // If the right side can have side effects, we create a new register for lhs
     // Note that when locals map is not used, lhs variable can't be changed by side effects
     // of rhs
-    let lhs_reg = if ctx.use_locals_map && can_have_side_effects(&rhs.0, ctx) {
+    let lhs_reg = if ctx.use_locals_map && can_have_side_effects(&rhs.0, ctx.use_locals_map) {
         Some(ctx.get_register())
     } else {
         None
@@ -658,7 +1045,7 @@ fn compile_binary_logic<'src>(
     // If the right side can have side effects, we create a new register for lhs
     // Note that when locals map is not used, lhs variable can't be changed by side effects
     // of rhs
-    let lhs_register = if ctx.use_locals_map && can_have_side_effects(&rhs.0, ctx) {
+    let lhs_register = if ctx.use_locals_map && can_have_side_effects(&rhs.0, ctx.use_locals_map) {
         Some(ctx.get_register())
     } else {
         None
@@ -740,41 +1127,115 @@ fn compile_function_call<'src>(
         );
         return register;
     }
-    todo!("function calls");
+
+    if let Expr::Index(target, name) = &callee.0 {
+        return compile_method_call(target, name, args, span, register, ctx);
+    }
+
+    // Function and its arguments are placed in consecutive registers
+    let block_size = args.len() + 1;
+    let function = ctx.get_register_block(block_size);
+    let _ = compile_expressions(callee, Some(function), ctx, true);
+    for (i, arg) in args.iter().enumerate() {
+        let _ = compile_expressions(arg, Some(function.next(i + 1)), ctx, false);
+    }
+    ctx.release_register_block(function, block_size);
+    let output = ctx.actualize(register);
+    ctx.emit(
+        OpCode::Call {
+            function,
+            output,
+            argument_count: argument_count(args),
+        },
+        span,
+    );
+    output
+}
+
+fn compile_method_call<'src>(
+    target: &Spanned<Expr<'src>>,
+    name: &str,
+    args: &[Spanned<Expr<'src>>],
+    span: Span,
+    register: Option<StackIndex>,
+    ctx: &mut FunctionCompilationContext<'src>,
+) -> StackIndex {
+    // Method, the object and arguments are placed in consecutive registers
+    let block_size = args.len() + 2;
+    let function = ctx.get_register_block(block_size);
+    let this = compile_expressions(target, Some(function.next(1)), ctx, false);
+
+    let field = ctx.get_or_create_constant_index(name);
+    ctx.emit(
+        OpCode::ReadField {
+            output: function,
+            target: this,
+            field,
+        },
+        span,
+    );
+
+    for (i, arg) in args.iter().enumerate() {
+        let _ = compile_expressions(arg, Some(function.next(i + 2)), ctx, false);
+    }
+    ctx.release_register_block(function, block_size);
+    let output = ctx.actualize(register);
+    ctx.emit(
+        OpCode::MethodCall {
+            function,
+            output,
+            argument_count: argument_count(args),
+        },
+        span,
+    );
+    output
 }
 
-fn can_have_side_effects(expr: &Expr, ctx: &FunctionCompilationContext) -> bool {
+fn argument_count(args: &[Spanned<Expr>]) -> u8 {
+    args.len()
+        .try_into()
+        .unwrap_or_else(|_| unimplemented!("Functions only support up to 255 arguments"))
+}
+
+/// Whether evaluating the expression can have observable effects besides producing a value
+pub(crate) fn can_have_side_effects(expr: &Expr, use_locals_map: bool) -> bool {
     match expr {
         // Constant values never have side effects
         Expr::Value(_) => false,
         // Variable access can have side effects when locals are a map
         Expr::Path(_) => true,
         // Lists only have side effects if one of the elements also have side effects
-        Expr::List(items) => items.iter().any(|x| can_have_side_effects(&x.0, ctx)),
-        // Same for maps, but also checking keys
-        Expr::Map(items) => items
+        Expr::List(items) => items
             .iter()
-            .any(|x| can_have_side_effects(&x.0 .0, ctx) || can_have_side_effects(&x.1 .0, ctx)),
+            .any(|x| can_have_side_effects(&x.0, use_locals_map)),
+        // Same for maps, but also checking keys
+        Expr::Map(items) => items.iter().any(|x| {
+            can_have_side_effects(&x.0 .0, use_locals_map)
+                || can_have_side_effects(&x.1 .0, use_locals_map)
+        }),
         // Function definitions never have side effects
         Expr::FunctionDefinition(_, _) => false,
         // All operations have side effects if one of the operands have side effects
         Expr::Comparison(a, chain) => {
-            can_have_side_effects(&(**a).0, ctx)
-                || chain.iter().any(|x| can_have_side_effects(&x.1 .0, ctx))
+            can_have_side_effects(&(**a).0, use_locals_map)
+                || chain
+                    .iter()
+                    .any(|x| can_have_side_effects(&x.1 .0, use_locals_map))
         }
         Expr::Binary(lhs, _, rhs) => {
-            can_have_side_effects(&(**lhs).0, ctx) || can_have_side_effects(&(**rhs).0, ctx)
+            can_have_side_effects(&(**lhs).0, use_locals_map)
+                || can_have_side_effects(&(**rhs).0, use_locals_map)
         }
         Expr::Unary(op, expr) => {
             if op != &UnaryOp::AddressOf {
-                can_have_side_effects(&(**expr).0, ctx)
+                can_have_side_effects(&(**expr).0, use_locals_map)
             } else {
                 match &(**expr).0 {
                     // @ operator suppresses possible function calls, but does not help when locals
                     // has custom indexer logic
-                    Expr::Path(_) => ctx.use_locals_map,
-                    Expr::Index(expr, _) => can_have_side_effects(&(**expr).0, ctx),
-                    expr => can_have_side_effects(expr, ctx),
+                    Expr::Path(_) => use_locals_map,
+                    Expr::Index(expr, _) => can_have_side_effects(&(**expr).0, use_locals_map),
+                    expr => can_have_side_effects(expr, use_locals_map),
                 }
             }
         }
@@ -783,7 +1244,8 @@ fn can_have_side_effects(expr: &Expr, ctx: &FunctionCompilationContext) -> bool
         // Indexing can have side effects
         Expr::ExprIndex(_, _) => true,
         Expr::Index(_, _) => true,
-        Expr::Error => ast_err!(),
+        // Erroneous code is kept, so running it fails instead of being silently skipped
+        Expr::Error => true,
     }
 }
 
@@ -802,7 +1264,7 @@ fn can_evaluate_to_function(expr: &Expr, ctx: &FunctionCompilationContext) -> bo
         Expr::Call(_, _) => true,
         Expr::ExprIndex(_, _) => true,
         Expr::Index(_, _) => true,
-        Expr::Error => ast_err!(),
+        Expr::Error => false,
     }
 }
 
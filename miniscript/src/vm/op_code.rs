@@ -1,16 +1,18 @@
 use crate::errors::{InternalError, MsError, MsErrorType, RuntimeError};
+use crate::symbol::Symbol;
 use crate::value::Value;
-use crate::vm::chunk::ConstantIndex;
+use crate::vm::chunk::{Chunk, ConstantIndex};
+use serde::{Deserialize, Serialize};
 use std::fmt::format;
 use std::result;
 use strum_macros::EnumMessage;
 
-use super::{register::StackIndex, Vm};
+use super::{register::StackIndex, Vm, MAX_CALL_DEPTH};
 
 ///
 /// As a rule of thumb, first argument is the "target" of a bytecode operation
 #[non_exhaustive]
-#[derive(EnumMessage, Debug, Clone)]
+#[derive(EnumMessage, Debug, Clone, Serialize, Deserialize)]
 pub enum OpCode {
     #[strum(message = "Returns a value at a register (0)")]
     Return(Option<StackIndex>),
@@ -25,7 +27,7 @@ pub enum OpCode {
     #[strum(
         message = "Attempts to find a value identified by (1) in all visible contexts and write it to index (0)"
     )]
-    ReadVariable(StackIndex, String),
+    ReadVariable(StackIndex, Symbol),
 
     // Function calls
     #[strum(message = "Calls a function with 0 arguments")]
@@ -39,12 +41,50 @@ pub enum OpCode {
         output: StackIndex,
         arg: StackIndex,
     },
-    #[strum(message = "Calls a function with many argumetns")]
+    #[strum(
+        message = "Calls a function with many argumetns, placed in registers following the (function)"
+    )]
     Call {
         function: StackIndex,
         output: StackIndex,
         argument_count: u8,
     },
+    #[strum(
+        message = "Calls a method with the object in a register following the (function), and arguments after it"
+    )]
+    MethodCall {
+        function: StackIndex,
+        output: StackIndex,
+        argument_count: u8,
+    },
+
+    // Indexing
+    #[strum(message = "Reads an element at (index) of (target) and writes it to (output)")]
+    ReadIndex {
+        output: StackIndex,
+        target: StackIndex,
+        index: StackIndex,
+    },
+    #[strum(message = "Writes (value) to an element at (index) of (target)")]
+    WriteIndex {
+        target: StackIndex,
+        index: StackIndex,
+        value: StackIndex,
+    },
+    #[strum(
+        message = "Reads a value of (target) at a constant string (field) and writes it to (output)"
+    )]
+    ReadField {
+        output: StackIndex,
+        target: StackIndex,
+        field: ConstantIndex,
+    },
+    #[strum(message = "Writes (value) to (target) at a constant string (field)")]
+    WriteField {
+        target: StackIndex,
+        field: ConstantIndex,
+        value: StackIndex,
+    },
 
     // Binary operators
     #[strum(message = "Adds values at (lhs) and (rhs) and writes result to (output)")]
@@ -166,15 +206,107 @@ fn simple_op<T: Fn(&Value, &Value) -> Value>(
     Ok(())
 }
 
+/// Calls a value with given arguments. Non-function values evaluate to themselves when called
+/// without arguments
+pub(crate) fn call_value(
+    vm: &mut Vm,
+    function: Value,
+    args: Vec<Value>,
+) -> Result<Value, MsErrorType> {
+    match function {
+        Value::Function(func) => {
+            if vm.call_depth >= MAX_CALL_DEPTH {
+                return Err(RuntimeError::StackOverflow(MAX_CALL_DEPTH).into());
+            }
+            vm.call_depth += 1;
+            let result = func.invoke(vm, args);
+            vm.call_depth -= 1;
+            Ok(result?)
+        }
+        value if args.is_empty() => Ok(value),
+        value => Err(RuntimeError::NotCallable(value.type_name()).into()),
+    }
+}
+
+fn collect_arguments(vm: &Vm, first: StackIndex, count: u8) -> Vec<Value> {
+    (0..count as usize)
+        .map(|i| vm[&first.next(i)].clone())
+        .collect()
+}
+
 impl OpCode {
-    pub fn step(&self, vm: &mut Vm) -> Result<(), MsErrorType> {
+    /// Registers the instruction reads or writes. Arguments of calls are placed in a block after
+    /// the function, which is represented by its first and last registers
+    pub(crate) fn registers(&self) -> Vec<StackIndex> {
+        match self {
+            OpCode::Return(value) => value.iter().copied().collect(),
+            OpCode::SetNumber(to, _)
+            | OpCode::SetNull(to)
+            | OpCode::SetString(to, _)
+            | OpCode::ReadVariable(to, _) => vec![*to],
+            OpCode::Call0 { function, output } => vec![*function, *output],
+            OpCode::Call1 {
+                function,
+                output,
+                arg,
+            } => vec![*function, *output, *arg],
+            OpCode::Call {
+                function,
+                output,
+                argument_count,
+            } => vec![*function, *output, function.next(*argument_count as usize)],
+            OpCode::MethodCall {
+                function,
+                output,
+                argument_count,
+            } => vec![
+                *function,
+                *output,
+                function.next(*argument_count as usize + 1),
+            ],
+            OpCode::ReadIndex {
+                output,
+                target,
+                index,
+            } => vec![*output, *target, *index],
+            OpCode::WriteIndex {
+                target,
+                index,
+                value,
+            } => vec![*target, *index, *value],
+            OpCode::ReadField { output, target, .. } => vec![*output, *target],
+            OpCode::WriteField { target, value, .. } => vec![*target, *value],
+            OpCode::Add { output, lhs, rhs }
+            | OpCode::Subtract { output, lhs, rhs }
+            | OpCode::Multiply { output, lhs, rhs }
+            | OpCode::Divide { output, lhs, rhs }
+            | OpCode::Pow { output, lhs, rhs }
+            | OpCode::FuzzyOr { output, lhs, rhs }
+            | OpCode::FuzzyAnd { output, lhs, rhs }
+            | OpCode::And { output, lhs, rhs }
+            | OpCode::Equals { output, lhs, rhs }
+            | OpCode::NotEquals { output, lhs, rhs }
+            | OpCode::GreaterThan { output, lhs, rhs }
+            | OpCode::LessThan { output, lhs, rhs }
+            | OpCode::GreaterOrEquals { output, lhs, rhs }
+            | OpCode::LessOrEquals { output, lhs, rhs } => vec![*output, *lhs, *rhs],
+            OpCode::JumpIfFalse(condition, _)
+            | OpCode::JumpIfTrue(condition, _)
+            | OpCode::JumpIfAbsOneOrGreater(condition, _) => vec![*condition],
+            OpCode::Jump(_) | OpCode::Error(_) => vec![],
+            OpCode::Copy { source, output } => vec![*source, *output],
+            OpCode::Print { value, output } => vec![*value, *output],
+        }
+    }
+
+    pub fn step(&self, chunk: &Chunk, vm: &mut Vm) -> Result<(), MsErrorType> {
         vm.cursor += 1;
         match self {
             OpCode::Return(value) => {
-                if value.is_none() {
-                    return Ok(());
-                }
-                Err(InternalError::NotImplemented.into())
+                // There are no call frames yet, so returning always ends the script
+                vm.returned = value.map(|value| vm[&value].clone());
+                vm.cursor = chunk.code().len();
+                Ok(())
             }
             OpCode::SetNumber(to, number) => {
                 vm[to] = Value::Number(*number);
@@ -184,17 +316,92 @@ impl OpCode {
                 vm[to] = Value::Null;
                 Ok(())
             }
-            OpCode::SetString(_, _) => todo!(),
-            OpCode::ReadVariable(_, _) => todo!(),
-            OpCode::Call0 { output, function } => {
-                let function = &vm[function];
-                match function {
-                    Value::Null | Value::Number(_) => vm[output] = function.clone(),
+            OpCode::SetString(to, idx) => {
+                vm[to] = vm.symbol_value(chunk.get_constant(idx));
+                Ok(())
+            }
+            OpCode::ReadVariable(to, ident) => match vm.globals.get(ident) {
+                Some(value) => {
+                    vm[to] = value.clone();
+                    Ok(())
                 }
+                None => Err(RuntimeError::UndefinedIdentifier(ident.to_string()).into()),
+            },
+            OpCode::Call0 { output, function } => {
+                let function = vm[function].clone();
+                vm[output] = call_value(vm, function, vec![])?;
+                Ok(())
+            }
+            OpCode::Call1 {
+                function,
+                output,
+                arg,
+            } => {
+                let function = vm[function].clone();
+                let args = vec![vm[arg].clone()];
+                vm[output] = call_value(vm, function, args)?;
+                Ok(())
+            }
+            OpCode::Call {
+                function,
+                output,
+                argument_count,
+            } => {
+                let args = collect_arguments(vm, function.next(1), *argument_count);
+                let function = vm[function].clone();
+                vm[output] = call_value(vm, function, args)?;
+                Ok(())
+            }
+            OpCode::MethodCall {
+                function,
+                output,
+                argument_count,
+            } => {
+                let callee = vm[function].clone();
+                let is_method = matches!(&callee, Value::Function(func) if func.is_method());
+                // Object is only passed to functions that accept `self`
+                let args = if is_method {
+                    collect_arguments(vm, function.next(1), *argument_count + 1)
+                } else {
+                    collect_arguments(vm, function.next(2), *argument_count)
+                };
+                vm[output] = call_value(vm, callee, args)?;
+                Ok(())
+            }
+            OpCode::ReadIndex {
+                output,
+                target,
+                index,
+            } => {
+                vm[output] = vm[target].get_index(&vm[index])?;
+                Ok(())
+            }
+            OpCode::WriteIndex {
+                target,
+                index,
+                value,
+            } => {
+                vm[target].set_index(vm[index].clone(), vm[value].clone())?;
+                Ok(())
+            }
+            OpCode::ReadField {
+                output,
+                target,
+                field,
+            } => {
+                let key = vm.symbol_key(chunk.get_constant(field)).clone();
+                vm[output] = vm[target].get_field(&key)?;
+                Ok(())
+            }
+            OpCode::WriteField {
+                target,
+                field,
+                value,
+            } => {
+                let key = vm.symbol_key(chunk.get_constant(field)).clone();
+                vm[target].set_field(&key, vm[value].clone())?;
                 Ok(())
             }
-            OpCode::Call1 { .. } => todo!(),
-            OpCode::Call { .. } => todo!(),
             OpCode::Add { lhs, rhs, output } => simple_op(vm, lhs, rhs, output, |a, b| a + b),
             OpCode::Subtract { lhs, rhs, output } => simple_op(vm, lhs, rhs, output, |a, b| a - b),
             OpCode::Multiply { lhs, rhs, output } => simple_op(vm, lhs, rhs, output, |a, b| a * b),
@@ -252,8 +459,8 @@ impl OpCode {
                 Ok(())
             }
             OpCode::Print { output, value } => {
-                let value = &vm[value];
-                println!("{:?}", value);
+                let value = vm[value].clone();
+                vm.print(&value);
                 vm[output] = Value::Null;
                 Ok(())
             }
@@ -276,8 +483,44 @@ impl OpCode {
                 output,
                 function,
                 arg,
-            } => format!("{output} = ${function}( ${arg} )"),
-            OpCode::Call { .. } => todo!(),
+            } => format!("${output} = ${function}( ${arg} )"),
+            OpCode::Call {
+                output,
+                function,
+                argument_count,
+            } => format!(
+                "${output} = ${function}({})",
+                format_arguments(function.next(1), *argument_count)
+            ),
+            OpCode::MethodCall {
+                output,
+                function,
+                argument_count,
+            } => format!(
+                "${output} = ${}.${function}({})",
+                function.next(1),
+                format_arguments(function.next(2), *argument_count)
+            ),
+            OpCode::ReadIndex {
+                output,
+                target,
+                index,
+            } => format!("${output} = ${target}[${index}]"),
+            OpCode::WriteIndex {
+                target,
+                index,
+                value,
+            } => format!("${target}[${index}] = ${value}"),
+            OpCode::ReadField {
+                output,
+                target,
+                field,
+            } => format!("${output} = ${target}.\"{}\"", field.raw()),
+            OpCode::WriteField {
+                target,
+                field,
+                value,
+            } => format!("${target}.\"{}\" = ${value}", field.raw()),
             OpCode::Add { output, lhs, rhs } => format!("${output} = ${lhs} + ${rhs}"),
             OpCode::Subtract { output, lhs, rhs } => format!("${output} = ${lhs} - ${rhs}"),
             OpCode::Multiply { output, lhs, rhs } => format!("${output} = ${lhs} * ${rhs}"),
@@ -323,19 +566,32 @@ impl OpCode {
     }
 }
 
-#[derive(Debug, Clone)]
+fn format_arguments(first: StackIndex, count: u8) -> String {
+    (0..count as usize)
+        .map(|i| format!("${}", first.next(i)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BytecodeError {
     Message(String),
     Register(StackIndex),
     UnpatchedOpCode,
+    /// Placeholder for code that failed to parse
+    SyntaxError,
+    /// Placeholder for code that the compiler doesn't support yet
+    NotImplemented,
 }
 
 impl BytecodeError {
     fn error(&self, id: usize, vm: &Vm) -> MsErrorType {
         match self {
-            BytecodeError::Message(msg) => todo!(),
+            BytecodeError::Message(msg) => RuntimeError::Message(msg.clone()).into(),
             BytecodeError::Register(idx) => RuntimeError::Custom(vm[idx].clone()).into(),
             BytecodeError::UnpatchedOpCode => InternalError::UnpatchedOpCode(id).into(),
+            BytecodeError::SyntaxError => InternalError::SyntaxError.into(),
+            BytecodeError::NotImplemented => InternalError::NotImplemented.into(),
         }
     }
 
@@ -344,6 +600,8 @@ impl BytecodeError {
             BytecodeError::Message(msg) => format!("throw \"{msg}\""),
             BytecodeError::Register(target) => format!("throw ${target}"),
             BytecodeError::UnpatchedOpCode => format!("FATAL! unpatched OpCode"),
+            BytecodeError::SyntaxError => "FATAL! syntax error".to_string(),
+            BytecodeError::NotImplemented => "FATAL! not implemented".to_string(),
         }
     }
 }
@@ -0,0 +1,204 @@
+use crate::errors::MsError;
+use crate::value::Value;
+use crate::vm::chunk::Chunk;
+use crate::vm::op_code::OpCode;
+use crate::vm::{step, Vm, VmRunner};
+use rustc_hash::FxHashMap;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Number of executions and total time spent
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Stats {
+    pub hits: u64,
+    pub time: Duration,
+}
+
+impl Stats {
+    fn record(&mut self, time: Duration) {
+        self.hits += 1;
+        self.time += time;
+    }
+}
+
+/// Stats of all instructions starting at a single source line
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LineStats {
+    /// One-based line number
+    pub line: usize,
+    /// Number of times the line was reached, counted by its most executed instruction
+    pub hits: u64,
+    /// Time spent on the line, including functions called from it
+    pub time: Duration,
+}
+
+/// Runner that executes the chunk like `DefaultRunner`, while measuring each instruction.
+///
+/// Measurements accumulate across runs, so a whole suite of scripts sharing the chunk can be
+/// profiled together.
+#[derive(Debug, Default)]
+pub struct ProfilingRunner {
+    /// Stats of each instruction, by its index in the chunk
+    ops: Vec<Stats>,
+    /// Time spent inside of the named functions, by index of the calling instruction
+    calls: FxHashMap<(usize, String), Stats>,
+}
+
+impl VmRunner for ProfilingRunner {
+    fn run(&mut self, chunk: &Chunk, vm: &mut Vm) -> Result<(), MsError> {
+        self.ops.resize(chunk.code().len(), Stats::default());
+        while vm.cursor < chunk.code().len() {
+            let cursor = vm.cursor;
+            let callee = called_function(&chunk.code()[cursor], vm);
+            let start = Instant::now();
+            let result = step(chunk, vm);
+            let time = start.elapsed();
+
+            self.ops[cursor].hits += 1;
+            match callee {
+                Some(name) => self.calls.entry((cursor, name)).or_default().record(time),
+                None => self.ops[cursor].time += time,
+            }
+            result?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ProfilingRunner {
+    /// Stats of each instruction, by its index in the chunk
+    pub fn op_stats(&self) -> &[Stats] {
+        &self.ops
+    }
+
+    /// Stats of the functions called from the script, by name
+    pub fn function_stats(&self) -> BTreeMap<&str, Stats> {
+        let mut result = BTreeMap::<&str, Stats>::new();
+        for ((_, name), stats) in &self.calls {
+            let entry = result.entry(name).or_default();
+            entry.hits += stats.hits;
+            entry.time += stats.time;
+        }
+        result
+    }
+
+    /// Stats of every source line containing code, in order of lines
+    pub fn line_stats(&self, chunk: &Chunk, src: &str) -> Vec<LineStats> {
+        let mut lines = BTreeMap::<usize, LineStats>::new();
+        for (i, span) in chunk.spans().iter().enumerate() {
+            // Instructions without a span are generated by the compiler, like implicit return
+            if span.start == span.end {
+                continue;
+            }
+            let line = line_of(src, span.start);
+            let stats = self.ops.get(i).copied().unwrap_or_default();
+            let entry = lines.entry(line).or_insert(LineStats {
+                line,
+                hits: 0,
+                time: Duration::ZERO,
+            });
+            entry.hits = entry.hits.max(stats.hits);
+            entry.time += stats.time + self.call_time(i);
+        }
+        lines.into_values().collect()
+    }
+
+    /// Lines that were reached at least once, and lines containing code in total
+    pub fn coverage(&self, chunk: &Chunk, src: &str) -> (usize, usize) {
+        let lines = self.line_stats(chunk, src);
+        let covered = lines.iter().filter(|line| line.hits > 0).count();
+        (covered, lines.len())
+    }
+
+    /// Source code with hit counts and time spent, prefixed to each line
+    pub fn annotated_source(&self, chunk: &Chunk, src: &str) -> String {
+        self.annotate(chunk, src, |stats| {
+            format!("{:>8} {:>12}", stats.hits, format!("{:.1?}", stats.time))
+        })
+    }
+
+    /// Source code with hit counts prefixed to each line, marking lines that were never reached
+    /// with `#####`
+    pub fn coverage_listing(&self, chunk: &Chunk, src: &str) -> String {
+        self.annotate(chunk, src, |stats| match stats.hits {
+            0 => format!("{:>8}", "#####"),
+            hits => format!("{hits:>8}"),
+        })
+    }
+
+    fn annotate(
+        &self,
+        chunk: &Chunk,
+        src: &str,
+        format_stats: impl Fn(&LineStats) -> String,
+    ) -> String {
+        let stats = self
+            .line_stats(chunk, src)
+            .into_iter()
+            .map(|stats| (stats.line, format_stats(&stats)))
+            .collect::<FxHashMap<_, _>>();
+        let width = stats.values().map(|prefix| prefix.len()).max().unwrap_or(0);
+        src.lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let prefix = stats.get(&(i + 1)).map(String::as_str).unwrap_or("");
+                format!("{prefix:>width$} | {line}")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Stacks in the folded format of `inferno` and `flamegraph.pl`, weighted by nanoseconds.
+    ///
+    /// Each stack starts with the source id, followed by the line, and the called function if
+    /// the time was spent inside one.
+    pub fn folded_stacks(&self, chunk: &Chunk, src: &str) -> String {
+        let src_id = chunk.get_src_id();
+        let mut stacks = BTreeMap::<String, u128>::new();
+        for (i, (stats, span)) in self.ops.iter().zip(chunk.spans()).enumerate() {
+            let frame = if span.start == span.end {
+                src_id.to_string()
+            } else {
+                format!("{src_id};line {}", line_of(src, span.start))
+            };
+            if !stats.time.is_zero() {
+                *stacks.entry(frame.clone()).or_default() += stats.time.as_nanos();
+            }
+            for ((_, name), call) in self.calls.iter().filter(|((op, _), _)| *op == i) {
+                *stacks.entry(format!("{frame};{name}")).or_default() += call.time.as_nanos();
+            }
+        }
+        stacks
+            .into_iter()
+            .map(|(stack, weight)| format!("{stack} {weight}\n"))
+            .collect()
+    }
+
+    fn call_time(&self, op: usize) -> Duration {
+        self.calls
+            .iter()
+            .filter(|((index, _), _)| *index == op)
+            .map(|(_, stats)| stats.time)
+            .sum()
+    }
+}
+
+/// Name of the function that the instruction is about to call, if any
+fn called_function(op_code: &OpCode, vm: &Vm) -> Option<String> {
+    let function = match op_code {
+        OpCode::Call0 { function, .. }
+        | OpCode::Call1 { function, .. }
+        | OpCode::Call { function, .. }
+        | OpCode::MethodCall { function, .. } => function,
+        _ => return None,
+    };
+    match &vm[function] {
+        Value::Function(func) => Some(func.name().to_string()),
+        _ => None,
+    }
+}
+
+fn line_of(src: &str, offset: usize) -> usize {
+    src[..offset].matches('\n').count() + 1
+}
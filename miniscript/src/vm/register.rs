@@ -1,16 +1,22 @@
 use crate::value::Value;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 use std::ops::{Index, IndexMut, Sub};
 
 type Stack = Vec<Value>;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct StackIndex(pub(crate) usize);
 
 impl StackIndex {
     pub fn with_offset(&self, offset: usize) -> Register {
         return Register(self.0 + offset);
     }
+
+    /// Index of a register that is `amount` slots after this one
+    pub fn next(&self, amount: usize) -> StackIndex {
+        StackIndex(self.0 + amount)
+    }
 }
 
 impl Display for StackIndex {
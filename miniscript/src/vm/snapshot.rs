@@ -0,0 +1,92 @@
+use crate::value::{Value, ValueMap};
+use crate::vm::Vm;
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Detached copy of the full VM state, used to replay a script run from a known point.
+///
+/// Lists and maps are deep copied, so further changes to the VM don't affect the snapshot.
+/// Values shared between several places keep being shared after restoring.
+#[derive(Debug)]
+pub struct VmSnapshot {
+    vm: Vm,
+}
+
+impl Vm {
+    pub fn snapshot(&self) -> VmSnapshot {
+        VmSnapshot { vm: copy_vm(self) }
+    }
+
+    /// Restores the VM to the state of the snapshot.
+    ///
+    /// Host values that referred to lists or maps of this VM are not updated, and should be read
+    /// again via globals
+    pub fn restore(&mut self, snapshot: &VmSnapshot) {
+        *self = copy_vm(&snapshot.vm);
+    }
+}
+
+fn copy_vm(vm: &Vm) -> Vm {
+    let mut copier = HeapCopier::default();
+    Vm {
+        cursor: vm.cursor,
+        stack: vm.stack.iter().map(|value| copier.copy(value)).collect(),
+        globals: vm
+            .globals
+            .iter()
+            .map(|(name, value)| (*name, copier.copy(value)))
+            .collect(),
+        // Strings are immutable, so they can be shared
+        strings: vm.strings.clone(),
+        rng: vm.rng.clone(),
+        output: vm.output.clone(),
+        capabilities: vm.capabilities,
+        file_root: vm.file_root.clone(),
+        started: vm.started,
+        suspension: vm.suspension,
+        call_depth: vm.call_depth,
+        returned: vm.returned.as_ref().map(|value| copier.copy(value)),
+    }
+}
+
+/// Deep copies values, mapping each original list or map to exactly one copy
+#[derive(Default)]
+struct HeapCopier {
+    lists: FxHashMap<*const RefCell<Vec<Value>>, Rc<RefCell<Vec<Value>>>>,
+    maps: FxHashMap<*const RefCell<ValueMap>, Rc<RefCell<ValueMap>>>,
+}
+
+impl HeapCopier {
+    fn copy(&mut self, value: &Value) -> Value {
+        match value {
+            Value::List(list) => {
+                if let Some(copy) = self.lists.get(&Rc::as_ptr(list)) {
+                    return Value::List(copy.clone());
+                }
+                // Copy is registered before its items, so self-referencing lists terminate
+                let copy = Rc::new(RefCell::new(Vec::new()));
+                self.lists.insert(Rc::as_ptr(list), copy.clone());
+                let items = list.borrow().iter().map(|item| self.copy(item)).collect();
+                *copy.borrow_mut() = items;
+                Value::List(copy)
+            }
+            Value::Map(map) => {
+                if let Some(copy) = self.maps.get(&Rc::as_ptr(map)) {
+                    return Value::Map(copy.clone());
+                }
+                let copy = Rc::new(RefCell::new(ValueMap::default()));
+                self.maps.insert(Rc::as_ptr(map), copy.clone());
+                let items = map
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| (self.copy(key), self.copy(value)))
+                    .collect();
+                *copy.borrow_mut() = items;
+                Value::Map(copy)
+            }
+            // Strings are immutable and intrinsics are stateless, so they can be shared
+            value => value.clone(),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Fields, FnArg, ImplItem, ImplItemFn, ItemImpl,
+    LitStr, Pat, ReturnType, Type,
+};
+
+/// Options provided via `#[script(...)]` attribute
+#[derive(Default)]
+struct ScriptAttributes {
+    rename: Option<String>,
+    skip: bool,
+}
+
+fn parse_attributes(attrs: &[Attribute]) -> syn::Result<ScriptAttributes> {
+    let mut result = ScriptAttributes::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("script")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                result.skip = true;
+                Ok(())
+            } else if meta.path.is_ident("rename") {
+                result.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported script attribute"))
+            }
+        })?;
+    }
+    Ok(result)
+}
+
+/// Derives `ToValue` and `FromValue`, converting a struct with named fields to and from a
+/// script map.
+///
+/// Fields can be renamed with `#[script(rename = "name")]`, or excluded with `#[script(skip)]`,
+/// in which case they are filled with `Default::default()` when converting from a map.
+#[proc_macro_derive(ScriptMap, attributes(script))]
+pub fn derive_script_map(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive_script_map_impl(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn derive_script_map_impl(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.span(),
+            "ScriptMap can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new(
+            input.span(),
+            "ScriptMap can only be derived for structs with named fields",
+        ));
+    };
+
+    let mut writes = Vec::new();
+    let mut reads = Vec::new();
+    for field in &fields.named {
+        let ident = field.ident.as_ref().expect("Named field has no name");
+        let attributes = parse_attributes(&field.attrs)?;
+        if attributes.skip {
+            reads.push(quote! { #ident: ::std::default::Default::default() });
+            continue;
+        }
+        let name = attributes.rename.unwrap_or_else(|| ident.to_string());
+        writes.push(quote! {
+            map.insert(
+                ::miniscript::value::Value::from(#name),
+                ::miniscript::bindings::ToValue::to_value(&self.#ident),
+            );
+        });
+        reads.push(quote! { #ident: ::miniscript::bindings::get_field(value, #name)? });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::miniscript::bindings::ToValue for #name #ty_generics #where_clause {
+            fn to_value(&self) -> ::miniscript::value::Value {
+                let mut map = ::miniscript::value::ValueMap::default();
+                #(#writes)*
+                ::miniscript::value::Value::new_map(map)
+            }
+        }
+
+        impl #impl_generics ::miniscript::bindings::FromValue for #name #ty_generics #where_clause {
+            fn from_value(
+                value: &::miniscript::value::Value,
+            ) -> ::std::result::Result<Self, ::miniscript::errors::RuntimeError> {
+                ::std::result::Result::Ok(Self {
+                    #(#reads),*
+                })
+            }
+        }
+    })
+}
+
+/// Implements `ScriptMethods` for the type of an `impl` block, exposing its functions as
+/// intrinsics.
+///
+/// Methods receive the object via the `self` parameter. The object is converted with
+/// `FromValue`, and changes done by `&mut self` methods are written back into the map. Functions
+/// returning `Result` propagate their errors to the script. Functions can be renamed with
+/// `#[script(rename = "name")]`, or hidden from scripts with `#[script(skip)]`.
+#[proc_macro_attribute]
+pub fn script_methods(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemImpl);
+    script_methods_impl(item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn script_methods_impl(mut item: ItemImpl) -> syn::Result<TokenStream2> {
+    let self_ty = item.self_ty.clone();
+    let mut intrinsics = Vec::new();
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(func) = impl_item else {
+            continue;
+        };
+        let attributes = parse_attributes(&func.attrs)?;
+        func.attrs.retain(|attr| !attr.path().is_ident("script"));
+        if attributes.skip {
+            continue;
+        }
+        intrinsics.push(method_intrinsic(&self_ty, func, attributes.rename)?);
+    }
+
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::miniscript::bindings::ScriptMethods for #self_ty #where_clause {
+            fn script_methods() -> ::std::vec::Vec<::miniscript::intrinsics::Intrinsic> {
+                ::std::vec![#(#intrinsics),*]
+            }
+        }
+    })
+}
+
+fn method_intrinsic(
+    self_ty: &Type,
+    func: &ImplItemFn,
+    rename: Option<String>,
+) -> syn::Result<TokenStream2> {
+    let ident = &func.sig.ident;
+    let name = rename.unwrap_or_else(|| ident.to_string());
+
+    let mut params = Vec::new();
+    let mut conversions = Vec::new();
+    let mut arguments = Vec::new();
+    // Whether the function has `self` receiver, and if it's a mutable reference
+    let mut receiver = None;
+    for (i, input) in func.sig.inputs.iter().enumerate() {
+        match input {
+            FnArg::Receiver(recv) => {
+                params.push(quote! { ::miniscript::intrinsics::IntrinsicParam::new("self") });
+                receiver = Some(recv.reference.is_some() && recv.mutability.is_some());
+            }
+            FnArg::Typed(arg) => {
+                let param_name = match &*arg.pat {
+                    Pat::Ident(pat) => pat.ident.to_string(),
+                    _ => format!("arg{i}"),
+                };
+                if matches!(&*arg.ty, Type::Reference(_)) {
+                    return Err(syn::Error::new(
+                        arg.ty.span(),
+                        "script methods only support owned argument types",
+                    ));
+                }
+                let ty = &arg.ty;
+                let var = format_ident!("arg{}", i);
+                params.push(quote! { ::miniscript::intrinsics::IntrinsicParam::new(#param_name) });
+                conversions.push(quote! {
+                    let #var = <#ty as ::miniscript::bindings::FromValue>::from_value(&args[#i])?;
+                });
+                arguments.push(var);
+            }
+        }
+    }
+
+    let call = match receiver {
+        Some(_) => quote! { this.#ident(#(#arguments),*) },
+        None => quote! { <#self_ty>::#ident(#(#arguments),*) },
+    };
+    let this = match receiver {
+        Some(_) => quote! {
+            #[allow(unused_mut)]
+            let mut this = <#self_ty as ::miniscript::bindings::FromValue>::from_value(&args[0])?;
+        },
+        None => quote! {},
+    };
+    let write_back = match receiver {
+        // Methods taking `&mut self` can mutate the object, so it's copied back into the map
+        Some(true) => quote! {
+            ::miniscript::bindings::write_back(
+                &args[0],
+                ::miniscript::bindings::ToValue::to_value(&this),
+            )?;
+        },
+        _ => quote! {},
+    };
+    let result = if returns_result(&func.sig.output) {
+        quote! { let result = #call?; }
+    } else {
+        quote! { let result = #call; }
+    };
+
+    Ok(quote! {
+        ::miniscript::intrinsics::Intrinsic::new(
+            #name,
+            ::std::vec![#(#params),*],
+            |_vm, args| {
+                #this
+                #(#conversions)*
+                #result
+                #write_back
+                ::std::result::Result::Ok(::miniscript::bindings::ToValue::to_value(&result))
+            },
+        )
+    })
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let Type::Path(path) = &**ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Result")
+}
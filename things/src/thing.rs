@@ -1,25 +1,56 @@
 ﻿use std::any::{type_name, TypeId};
-use std::fmt::{Debug, Display};
+use std::fmt::{Debug, Display, Formatter};
 
-use anyhow::{Error, Result};
+use anyhow::Result;
 use as_any::{AsAny, Downcast};
 use serde_json::Value;
 use thiserror::Error;
 
+use crate::thing::assignment::Requirement;
 use crate::thing::primitives::PrimitiveRefinement;
+use crate::thing::validation::{validate, ValidationIssue};
 
+pub mod array;
+pub mod assignment;
 pub mod bounds;
+pub mod json_schema;
 pub mod multiple_of;
+pub mod normalize;
+pub mod object;
+pub mod one_of;
+pub mod pattern;
 pub mod primitives;
+pub mod registry;
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategy;
+pub mod union;
+pub mod validation;
 
 #[cfg(test)]
 pub mod tests_utils;
 
 pub trait Refinement: Debug + Display + AsAny {
-    fn apply(&self, value: &mut Value, warnings: &mut Vec<anyhow::Error>) -> Result<()>;
+    /// Refines the value, pushing warnings of coerced values. Errors that aren't located
+    /// [`ValidationIssue`]s already are reported as issues of this refinement with the value it
+    /// got, so they are returned before changing it. Fields and items of a rejected value may
+    /// have been refined already
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> Result<()>;
     fn strict(&self) -> Box<dyn Refinement>;
     fn clone(&self) -> Box<dyn Refinement>;
     fn is_subset_of(&self, other: &dyn Refinement) -> Relation;
+    /// Relation of a whole thing to this refinement, for refinements like unions that can't be
+    /// related to refinements of the thing one at a time. Unrelated things are then checked
+    /// with [`Refinement::is_subset_of`]
+    fn is_superset_of(&self, _thing: &Thing) -> Relation {
+        Relation::Unrelated
+    }
+    /// Single refinement that has the values of both refinements in common, used by
+    /// [`Thing::normalize`]
+    fn intersect(&self, _other: &dyn Refinement) -> Intersection {
+        Intersection::Unrelated
+    }
+    /// Tagged representation of the refinement, see [`registry::serialize_tagged`]
+    fn serialize(&self) -> Result<Value>;
 }
 
 #[derive(Debug)]
@@ -32,6 +63,16 @@ pub enum Relation {
     Conflict(anyhow::Error),
 }
 
+#[derive(Debug)]
+pub enum Intersection {
+    /// Refinements can't be merged and are kept as they are
+    Unrelated,
+    /// Refinement that is satisfied exactly by values satisfying both refinements
+    Merged(Box<dyn Refinement>),
+    /// No value satisfies both refinements
+    Empty,
+}
+
 #[derive(Error, Debug)]
 #[error("Refinement `{}` is not satisfied", .0)]
 pub struct MissingRefinementError(String);
@@ -46,6 +87,21 @@ impl Clone for Thing {
     }
 }
 
+impl Display for Thing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "Any");
+        }
+        for (i, refinement) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " & ")?;
+            }
+            write!(f, "{}", refinement)?;
+        }
+        Ok(())
+    }
+}
+
 impl<T: Refinement> From<Vec<T>> for Thing {
     fn from(values: Vec<T>) -> Self {
         let items = values
@@ -73,39 +129,30 @@ impl<R: Refinement> FromIterator<R> for Thing {
 }
 
 impl Thing {
-    fn try_assign_to(&self, other: &Thing) -> Result<()> {
-        if let Some(err) = other
-            .0
-            .iter()
-            .filter_map(|other| {
-                // For each refinement in 'other', iterate over the refinements in 'self'.
-                // Use filter_map to transform the iterator, keeping only the refinements
-                // with a relationship to the current refinement in 'other'.
-                if let Some(rel) = self
-                    .0
-                    .iter()
-                    .filter_map(|refinement| {
-                        match refinement.is_subset_of(other.as_ref()) {
-                            // Skip unrelated refinements, only keeping successes or errors.
-                            Relation::Unrelated => None,
-                            rel => Some(rel),
-                        }
-                    })
-                    .next()
-                {
-                    return if let Relation::Conflict(err) = rel {
-                        Some(err)
-                    } else {
-                        None
-                    };
-                }
-                // If there's no relationship found for the current refinement in 'other',
-                // return a MissingRefinementError.
-                Some(MissingRefinementError(other.to_string()).into())
-            })
-            .next()
-        {
-            return Err(err);
+    /// Applies refinements in order like [`ThingLike::apply`], for refinements that refine
+    /// nested values with things and locate the issues with [`ValidationIssue::at`]
+    pub fn refine(
+        &self,
+        value: &mut Value,
+        warnings: &mut Vec<ValidationIssue>,
+    ) -> Result<(), Box<ValidationIssue>> {
+        validate(
+            self.0.iter().map(|refinement| &**refinement),
+            value,
+            warnings,
+        )
+    }
+
+    /// Checks that values of this thing are always values of the other thing, returning the
+    /// first conflicting or missing refinement otherwise. See [`Thing::assignment_report`]
+    /// for all of them
+    pub fn try_assign_to(&self, other: &Thing) -> Result<()> {
+        for refinement in &other.0 {
+            match self.requirement(&**refinement) {
+                Requirement::Matched(_) => {}
+                Requirement::Conflict(err) => return Err(err),
+                Requirement::Missing(err) => return Err(err.into()),
+            }
         }
         Ok(())
     }
@@ -113,7 +160,9 @@ impl Thing {
 
 pub trait ThingLike {
     fn get_refinement<T: 'static>(&self) -> Option<&T>;
-    fn apply(&self, value: &mut Value) -> Result<Vec<anyhow::Error>>;
+    /// Refines the value, returning warnings of coerced values or the error of the rejected
+    /// one, located by their paths
+    fn apply(&self, value: &mut Value) -> Result<Vec<ValidationIssue>, Box<ValidationIssue>>;
 }
 
 impl<R: Refinement> ThingLike for R {
@@ -121,9 +170,9 @@ impl<R: Refinement> ThingLike for R {
         return self.downcast_ref();
     }
 
-    fn apply(&self, value: &mut Value) -> Result<Vec<Error>> {
-        let mut warnings = Vec::new();
-        self.apply(value, &mut warnings)?;
+    fn apply(&self, value: &mut Value) -> Result<Vec<ValidationIssue>, Box<ValidationIssue>> {
+        let mut warnings = vec![];
+        validate([self as &dyn Refinement], value, &mut warnings)?;
         Ok(warnings)
     }
 }
@@ -135,11 +184,9 @@ impl ThingLike for Thing {
         })
     }
 
-    fn apply(&self, value: &mut Value) -> Result<Vec<Error>> {
-        let mut warnings = Vec::new();
-        for refinement in &self.0 {
-            refinement.apply(value, &mut warnings)?;
-        }
+    fn apply(&self, value: &mut Value) -> Result<Vec<ValidationIssue>, Box<ValidationIssue>> {
+        let mut warnings = vec![];
+        self.refine(value, &mut warnings)?;
         Ok(warnings)
     }
 }
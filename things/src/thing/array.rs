@@ -0,0 +1,412 @@
+use std::fmt::{Display, Formatter};
+
+use as_any::Downcast;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::thing::object::at_path;
+use crate::thing::primitives::inapplicable;
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Refinement, Relation, Thing};
+
+/// Arrays where every item is refined by the same thing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArrayOfRefinement {
+    pub items: Thing,
+}
+
+/// Arrays of the fixed length, where each item is refined by the thing at its position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TupleRefinement {
+    pub items: Vec<Thing>,
+}
+
+/// Arrays without equal items, where the coercion removes repeated ones
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct UniqueItemsRefinement {
+    #[serde(default)]
+    pub coerce: bool,
+}
+
+/// Applies the thing to the item, locating its errors and warnings at the index
+fn apply_item(
+    thing: &Thing,
+    index: usize,
+    item: &mut Value,
+    warnings: &mut Vec<ValidationIssue>,
+) -> anyhow::Result<()> {
+    let index = index.to_string();
+    let mut item_warnings = vec![];
+    thing
+        .refine(item, &mut item_warnings)
+        .map_err(|issue| issue.at(&index))?;
+    warnings.extend(item_warnings.into_iter().map(|warn| warn.at(&index)));
+    Ok(())
+}
+
+impl Display for ArrayOfRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ArrayOf({})", self.items)
+    }
+}
+
+impl Refinement for ArrayOfRefinement {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        let Value::Array(items) = value else {
+            return Err(inapplicable(self, value));
+        };
+        for (i, item) in items.iter_mut().enumerate() {
+            apply_item(&self.items, i, item, warnings)?;
+        }
+        Ok(())
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        if let Some(other) = other.downcast_ref::<ArrayOfRefinement>() {
+            return match self.items.try_assign_to(&other.items) {
+                Ok(()) => Relation::Subset,
+                Err(err) => Relation::Conflict(err),
+            };
+        }
+        if other.downcast_ref::<TupleRefinement>().is_some() {
+            return Relation::Conflict(ArrayRefinementError::UnknownLength.into());
+        }
+        Relation::Unrelated
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for ArrayOfRefinement {
+    const TAG: &'static str = "ArrayOf";
+}
+
+impl Display for TupleRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let items: Vec<_> = self.items.iter().map(Thing::to_string).collect();
+        write!(f, "Tuple({})", items.join(", "))
+    }
+}
+
+impl Refinement for TupleRefinement {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        let Value::Array(items) = value else {
+            return Err(inapplicable(self, value));
+        };
+        if items.len() != self.items.len() {
+            return Err(ArrayRefinementError::Length {
+                expected: self.items.len(),
+                got: items.len(),
+            }
+            .into());
+        }
+        for (i, (item, thing)) in items.iter_mut().zip(&self.items).enumerate() {
+            apply_item(thing, i, item, warnings)?;
+        }
+        Ok(())
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        // Pairs of own items and the things that they have to be assignable to
+        let pairs: Vec<(&Thing, &Thing)> =
+            if let Some(other) = other.downcast_ref::<TupleRefinement>() {
+                if self.items.len() != other.items.len() {
+                    return Relation::Conflict(
+                        ArrayRefinementError::Length {
+                            expected: other.items.len(),
+                            got: self.items.len(),
+                        }
+                        .into(),
+                    );
+                }
+                self.items.iter().zip(&other.items).collect()
+            } else if let Some(other) = other.downcast_ref::<ArrayOfRefinement>() {
+                self.items.iter().map(|item| (item, &other.items)).collect()
+            } else {
+                return Relation::Unrelated;
+            };
+
+        for (i, (item, other_item)) in pairs.into_iter().enumerate() {
+            if let Err(err) = item.try_assign_to(other_item) {
+                return Relation::Conflict(at_path(&i.to_string(), err));
+            }
+        }
+        Relation::Subset
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for TupleRefinement {
+    const TAG: &'static str = "Tuple";
+}
+
+impl Display for UniqueItemsRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UniqueItems")
+    }
+}
+
+impl Refinement for UniqueItemsRefinement {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        let Value::Array(items) = value else {
+            return Err(inapplicable(self, value));
+        };
+        // Unique items with their indices in the original array
+        let mut unique: Vec<(usize, Value)> = Vec::with_capacity(items.len());
+        for (i, item) in items.iter().enumerate() {
+            let Some(&(first, _)) = unique.iter().find(|(_, seen)| seen == item) else {
+                unique.push((i, item.clone()));
+                continue;
+            };
+            let err = ArrayRefinementError::Repeated(first).into();
+            if !self.coerce {
+                let issue = ValidationIssue::error(self, Some(item.clone()), err);
+                return Err(issue.at(&i.to_string()).into());
+            }
+            let issue = ValidationIssue::warning(self, item.clone(), None, err);
+            warnings.push(issue.at(&i.to_string()));
+        }
+        if unique.len() != items.len() {
+            *items = unique.into_iter().map(|(_, item)| item).collect();
+        }
+        Ok(())
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        if self.coerce {
+            Box::new(*self)
+        } else {
+            let mut copy = *self;
+            copy.coerce = true;
+            Box::new(copy)
+        }
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        // Own items are unique after coercion too, so any pair of them is related
+        if other.downcast_ref::<UniqueItemsRefinement>().is_some() {
+            Relation::Subset
+        } else {
+            Relation::Unrelated
+        }
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for UniqueItemsRefinement {
+    const TAG: &'static str = "UniqueItems";
+}
+
+#[derive(Error, Debug)]
+pub enum ArrayRefinementError {
+    #[error("Got {got} items where {expected} items were expected")]
+    Length { expected: usize, got: usize },
+    #[error("Array of any length is not a tuple")]
+    UnknownLength,
+    #[error("Item repeats the one at index {0}")]
+    Repeated(usize),
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json::json;
+
+    use crate::thing::array::{
+        ArrayOfRefinement, ArrayRefinementError, TupleRefinement, UniqueItemsRefinement,
+    };
+    use crate::thing::multiple_of::INTEGER;
+    use crate::thing::object::{Field, ObjectRefinement, PathError};
+    use crate::thing::primitives::{PrimitiveRefinement, TypeRefinementError};
+    use crate::thing::registry::RefinementRegistry;
+    use crate::thing::tests_utils::{
+        TestAssertionResult, TestConversionResult, TestConversionResultWithWarnings, TestRefinement,
+    };
+    use crate::thing::validation::ValidationIssue;
+    use crate::thing::{Thing, ThingLike};
+
+    fn integers() -> ArrayOfRefinement {
+        let mut thing = Thing::from(PrimitiveRefinement::Number);
+        thing.0.push(Box::new(INTEGER));
+        ArrayOfRefinement { items: thing }
+    }
+
+    fn point() -> TupleRefinement {
+        TupleRefinement {
+            items: vec![
+                Thing::from(PrimitiveRefinement::Number),
+                Thing::from(PrimitiveRefinement::Number),
+            ],
+        }
+    }
+
+    #[test]
+    fn array_of() {
+        integers().check(json!([])).success().assert();
+        let (value, warnings) = integers()
+            .check(json!([1, 2.4, 3, 3.6]))
+            .with_warnings(2)
+            .assert();
+        assert_eq!(value, json!([1, 2.0, 3, 4.0]));
+        let paths: Vec<_> = warnings.iter().map(|warn| warn.path.as_str()).collect();
+        assert_eq!(paths, ["/1", "/3"]);
+
+        let err = integers()
+            .check(json!([1, "2"]))
+            .error::<ValidationIssue>()
+            .assert();
+        assert_eq!(err.path, "/1");
+        assert!(err.error.is::<TypeRefinementError>());
+    }
+
+    #[test]
+    fn tuple() {
+        point().check(json!([1, 2])).success().assert();
+        point()
+            .check(json!([1, 2, 3]))
+            .error::<ArrayRefinementError>()
+            .assert();
+        let err = point()
+            .check(json!([1, null]))
+            .error::<ValidationIssue>()
+            .assert();
+        assert_eq!(err.path, "/1");
+    }
+
+    #[test]
+    fn unique_items() {
+        let strict = UniqueItemsRefinement { coerce: false };
+        strict.check(json!([1, "1", [1]])).success().assert();
+        let err = strict
+            .check(json!([1, 2, 1]))
+            .error::<ValidationIssue>()
+            .assert();
+        assert_eq!(err.path, "/2");
+
+        let coercing = UniqueItemsRefinement { coerce: true };
+        let (value, warnings) = coercing
+            .check(json!([3, 1, 3, 2, 1]))
+            .with_warnings(2)
+            .assert();
+        assert_eq!(value, json!([3, 1, 2]));
+        let removed: Vec<_> = warnings
+            .iter()
+            .map(|warn| {
+                (
+                    warn.path.as_str(),
+                    warn.original.clone(),
+                    warn.coerced.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            removed,
+            [("/2", Some(json!(3)), None), ("/4", Some(json!(1)), None)]
+        );
+
+        // Repeated items point at the first ones in the original array
+        let (_, warnings) = coercing
+            .check(json!([1, 1, 2, 2]))
+            .with_warnings(2)
+            .assert();
+        let messages: Vec<_> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "/1: Item repeats the one at index 0",
+                "/3: Item repeats the one at index 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn cast() {
+        let numbers = ArrayOfRefinement {
+            items: Thing::from(PrimitiveRefinement::Number),
+        };
+        Thing::from(integers())
+            .try_assign_to(&Thing::from(numbers.clone()))
+            .success()
+            .assert();
+        Thing::from(point())
+            .try_assign_to(&Thing::from(numbers))
+            .success()
+            .assert();
+        Thing::from(integers())
+            .try_assign_to(&Thing::from(point()))
+            .error::<ArrayRefinementError>()
+            .assert();
+
+        let labeled = TupleRefinement {
+            items: vec![
+                Thing::from(PrimitiveRefinement::Number),
+                Thing::from(PrimitiveRefinement::String),
+            ],
+        };
+        let err = Thing::from(labeled)
+            .try_assign_to(&Thing::from(point()))
+            .error::<PathError>()
+            .assert();
+        assert_eq!(err.path, "/1");
+        Thing::from(point())
+            .try_assign_to(&Thing::from(TupleRefinement {
+                items: vec![Thing::from(PrimitiveRefinement::Number)],
+            }))
+            .error::<ArrayRefinementError>()
+            .assert();
+    }
+
+    #[test]
+    fn nested() {
+        let polygon = ObjectRefinement::new([(
+            "points",
+            Field::required(ArrayOfRefinement {
+                items: Thing::from(point()),
+            }),
+        )]);
+        let err = Clone::clone(&polygon)
+            .check(json!({ "points": [[0, 0], [1, "1"]] }))
+            .error::<ValidationIssue>()
+            .assert();
+        assert_eq!(err.path, "/points/1/1");
+
+        let value = Thing::from(polygon).to_value().assert();
+        let loaded = RefinementRegistry::default()
+            .thing_from_value(value.clone())
+            .assert();
+        assert_eq!(loaded.to_value().assert(), value);
+        loaded
+            .apply(&mut json!({ "points": [[0, 0], [1, 1]] }))
+            .with_warnings(0)
+            .assert();
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Error;
+
+use crate::thing::{MissingRefinementError, Refinement, Relation, Thing};
+
+/// How one refinement of the target thing is satisfied by the assigned thing
+#[derive(Debug)]
+pub enum Requirement {
+    /// Refinement is satisfied by the refinement of the assigned thing, or by the whole thing
+    /// when it's `None`
+    Matched(Option<String>),
+    /// Refinement of the assigned thing, or the whole thing, conflicts with the refinement
+    Conflict(Error),
+    /// None of refinements of the assigned thing are related to the refinement
+    Missing(MissingRefinementError),
+}
+
+/// Outcome of assigning one thing to another, with a requirement for every refinement of the
+/// target thing in its order
+#[derive(Debug)]
+pub struct AssignmentReport {
+    pub from: String,
+    pub to: String,
+    pub requirements: Vec<(String, Requirement)>,
+}
+
+impl AssignmentReport {
+    /// Whether values of the assigned thing are always values of the target thing
+    pub fn is_assignable(&self) -> bool {
+        self.requirements
+            .iter()
+            .all(|(_, requirement)| matches!(requirement, Requirement::Matched(_)))
+    }
+
+    pub fn conflicts(&self) -> impl Iterator<Item = (&str, &Error)> {
+        self.requirements
+            .iter()
+            .filter_map(|(refinement, requirement)| match requirement {
+                Requirement::Conflict(err) => Some((refinement.as_str(), err)),
+                _ => None,
+            })
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.requirements
+            .iter()
+            .filter_map(|(refinement, requirement)| match requirement {
+                Requirement::Missing(_) => Some(refinement.as_str()),
+                _ => None,
+            })
+    }
+
+    /// The first of conflicts and missing refinements
+    pub fn into_result(self) -> anyhow::Result<()> {
+        for (_, requirement) in self.requirements {
+            match requirement {
+                Requirement::Matched(_) => {}
+                Requirement::Conflict(err) => return Err(err),
+                Requirement::Missing(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for AssignmentReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let verb = if self.is_assignable() { "can" } else { "can't" };
+        write!(f, "`{}` {} be assigned to `{}`", self.from, verb, self.to)?;
+        for (refinement, requirement) in &self.requirements {
+            match requirement {
+                Requirement::Matched(Some(by)) if by == refinement => {
+                    write!(f, "\n  ✓ {}", refinement)?
+                }
+                Requirement::Matched(Some(by)) => write!(f, "\n  ✓ {} by {}", refinement, by)?,
+                Requirement::Matched(None) => write!(f, "\n  ✓ {}", refinement)?,
+                Requirement::Conflict(err) => write!(f, "\n  ✗ {}: {}", refinement, err)?,
+                Requirement::Missing(_) => write!(f, "\n  ✗ {}: missing", refinement)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Thing {
+    /// Checks every refinement of the other thing against this one, unlike
+    /// [`Thing::try_assign_to`] which stops at the first error
+    pub fn assignment_report(&self, other: &Thing) -> AssignmentReport {
+        AssignmentReport {
+            from: self.to_string(),
+            to: other.to_string(),
+            requirements: other
+                .0
+                .iter()
+                .map(|refinement| (refinement.to_string(), self.requirement(&**refinement)))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn requirement(&self, other: &dyn Refinement) -> Requirement {
+        match other.is_superset_of(self) {
+            Relation::Unrelated => {}
+            Relation::Subset => return Requirement::Matched(None),
+            Relation::Conflict(err) => return Requirement::Conflict(err),
+        }
+        // The first related refinement decides, the rest of them are not checked
+        for refinement in &self.0 {
+            match refinement.is_subset_of(other) {
+                Relation::Unrelated => {}
+                Relation::Subset => return Requirement::Matched(Some(refinement.to_string())),
+                Relation::Conflict(err) => return Requirement::Conflict(err),
+            }
+        }
+        Requirement::Missing(MissingRefinementError(other.to_string()))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::ops::Bound;
+
+    use crate::thing::assignment::Requirement;
+    use crate::thing::bounds::BoundsRefinement;
+    use crate::thing::multiple_of::INTEGER;
+    use crate::thing::pattern::PatternRefinement;
+    use crate::thing::primitives::{PrimitiveRefinement, RefinementCastError};
+    use crate::thing::{MissingRefinementError, Refinement, Thing};
+
+    fn bounds(min: f64, max: f64) -> BoundsRefinement {
+        BoundsRefinement {
+            min: Bound::Included(min),
+            max: Bound::Included(max),
+            coerce: false,
+        }
+    }
+
+    fn number(refinements: Vec<Box<dyn Refinement>>) -> Thing {
+        let mut thing = Thing::from(PrimitiveRefinement::Number);
+        thing.0.extend(refinements);
+        thing
+    }
+
+    #[test]
+    fn complete() {
+        let from = number(vec![Box::new(bounds(0.0, 20.0))]);
+        let to = number(vec![
+            Box::new(bounds(0.0, 10.0)),
+            Box::new(INTEGER),
+            Box::new(PatternRefinement::new("^a").unwrap()),
+        ]);
+        let report = from.assignment_report(&to);
+        assert!(!report.is_assignable());
+        assert!(matches!(report.requirements[0].1, Requirement::Matched(_)));
+        let conflicts: Vec<_> = report.conflicts().collect();
+        assert_eq!(conflicts.len(), 1);
+        assert!(conflicts[0].1.is::<RefinementCastError<BoundsRefinement>>());
+        assert_eq!(report.missing().count(), 2);
+        assert!(report
+            .into_result()
+            .unwrap_err()
+            .is::<RefinementCastError<BoundsRefinement>>());
+
+        let report = from.assignment_report(&number(vec![]));
+        assert!(report.is_assignable());
+        assert!(report.into_result().is_ok());
+        let report = Thing(vec![]).assignment_report(&number(vec![]));
+        assert!(report
+            .into_result()
+            .unwrap_err()
+            .is::<MissingRefinementError>());
+    }
+
+    #[test]
+    fn display() {
+        let from = number(vec![Box::new(INTEGER)]);
+        let to = number(vec![Box::new(bounds(0.0, 10.0))]);
+        assert_eq!(
+            from.assignment_report(&to).to_string(),
+            "`Number & Integer` can't be assigned to `Number & Bounds [0; 10]`\n  \
+             ✓ Number\n  \
+             ✗ Bounds [0; 10]: missing"
+        );
+    }
+}
@@ -2,19 +2,29 @@
 use std::ops::{Bound, RangeBounds};
 
 use as_any::Downcast;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 
 use crate::thing::primitives::{inapplicable, validate_number, RefinementCastError};
-use crate::thing::{Refinement, Relation};
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Intersection, Refinement, Relation};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct BoundsRefinement {
+    #[serde(default = "unbounded")]
     pub min: Bound<f64>,
+    #[serde(default = "unbounded")]
     pub max: Bound<f64>,
+    #[serde(default)]
     pub coerce: bool,
 }
 
+fn unbounded() -> Bound<f64> {
+    Bound::Unbounded
+}
+
 impl BoundsRefinement {
     fn clamp(&self, num: f64) -> f64 {
         match (get_value(&self.min), get_value(&self.max)) {
@@ -74,7 +84,7 @@ impl RangeBounds<f64> for BoundsRefinement {
 }
 
 impl Refinement for BoundsRefinement {
-    fn apply(&self, value: &mut Value, warnings: &mut Vec<anyhow::Error>) -> anyhow::Result<()> {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
         let validated: f64 = match value {
             Value::Number(_) => validate_number(value)?,
             Value::String(str) => str.len() as f64,
@@ -90,9 +100,14 @@ impl Refinement for BoundsRefinement {
                 bounds: *self,
             }
             .into();
-            if value.is_number() {
-                *value = Value::from(self.clamp(validated));
-                warnings.push(err);
+            if self.coerce && value.is_number() {
+                let original = std::mem::replace(value, Value::from(self.clamp(validated)));
+                warnings.push(ValidationIssue::warning(
+                    self,
+                    original,
+                    Some(value.clone()),
+                    err,
+                ));
                 Ok(())
             } else {
                 Err(err)
@@ -168,6 +183,37 @@ impl Refinement for BoundsRefinement {
             Relation::Unrelated
         }
     }
+
+    fn intersect(&self, other: &dyn Refinement) -> Intersection {
+        let Some(other) = other.downcast_ref::<BoundsRefinement>() else {
+            return Intersection::Unrelated;
+        };
+        // Clamping to one of the bounds and then to the other, or checking it, is not the same
+        // as clamping to both of them, so only bounds that don't coerce are merged
+        if self.coerce || other.coerce {
+            return Intersection::Unrelated;
+        }
+        let merged = BoundsRefinement {
+            min: stricter(self.min, other.min, |a, b| a > b),
+            max: stricter(self.max, other.max, |a, b| a < b),
+            coerce: false,
+        };
+        if let (Some(min), Some(max)) = (merged.get_min(), merged.get_max()) {
+            let exclusive = is_exclusive(&merged.min) || is_exclusive(&merged.max);
+            if min > max || (min == max && exclusive) {
+                return Intersection::Empty;
+            }
+        }
+        Intersection::Merged(Box::new(merged))
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for BoundsRefinement {
+    const TAG: &'static str = "Bounds";
 }
 
 fn get_value(bound: &Bound<f64>) -> Option<f64> {
@@ -178,6 +224,29 @@ fn get_value(bound: &Bound<f64>) -> Option<f64> {
     }
 }
 
+/// The bound that excludes more values, where `further` tells whether the first value is
+/// further from the unbounded side than the second one
+fn stricter(a: Bound<f64>, b: Bound<f64>, further: fn(f64, f64) -> bool) -> Bound<f64> {
+    match (get_value(&a), get_value(&b)) {
+        (None, _) => b,
+        (_, None) => a,
+        (Some(x), Some(y)) if x == y => {
+            if is_exclusive(&a) {
+                a
+            } else {
+                b
+            }
+        }
+        (Some(x), Some(y)) => {
+            if further(x, y) {
+                a
+            } else {
+                b
+            }
+        }
+    }
+}
+
 fn is_exclusive(bound: &Bound<f64>) -> bool {
     match bound {
         Bound::Excluded(_) => true,
@@ -207,3 +276,39 @@ impl Display for BoundsRefinementError {
 fn cast_error(got: BoundsRefinement, expected: BoundsRefinement) -> Relation {
     Relation::Conflict(RefinementCastError { got, expected }.into())
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::ops::Bound;
+
+    use serde_json::json;
+
+    use crate::thing::bounds::{BoundsRefinement, BoundsRefinementError};
+    use crate::thing::tests_utils::{
+        TestAssertionResult, TestConversionResult, TestConversionResultWithWarnings, TestRefinement,
+    };
+
+    fn percentage(coerce: bool) -> BoundsRefinement {
+        BoundsRefinement {
+            min: Bound::Included(0.0),
+            max: Bound::Included(100.0),
+            coerce,
+        }
+    }
+
+    #[test]
+    fn coerce() {
+        percentage(false).check(json!(50)).success().assert();
+        percentage(false)
+            .check(json!(120))
+            .error::<BoundsRefinementError>()
+            .assert();
+        let (value, _) = percentage(true).check(json!(120)).with_warnings(1).assert();
+        assert_eq!(value, json!(100.0));
+        // Lengths can't be clamped, so they are rejected either way
+        percentage(true)
+            .check(json!("x".repeat(101)))
+            .error::<BoundsRefinementError>()
+            .assert();
+    }
+}
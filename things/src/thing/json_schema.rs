@@ -0,0 +1,966 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use anyhow::{anyhow, Result};
+use as_any::Downcast;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::thing::array::{ArrayOfRefinement, TupleRefinement, UniqueItemsRefinement};
+use crate::thing::bounds::BoundsRefinement;
+use crate::thing::multiple_of::{MultipleOfRefinement, STRICT_INTEGER};
+use crate::thing::object::{AdditionalProperties, Field, ObjectRefinement};
+use crate::thing::one_of::OneOfRefinement;
+use crate::thing::pattern::PatternRefinement;
+use crate::thing::primitives::PrimitiveRefinement;
+use crate::thing::union::{AnyOfRefinement, TaggedUnionRefinement};
+use crate::thing::{Refinement, Thing, ThingLike};
+
+pub static DRAFT_2020_12: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Keywords that only describe the schema, and are skipped without warnings
+static ANNOTATIONS: [&str; 6] = [
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "examples",
+];
+
+/// Keyword of the imported schema that has no equivalent refinement, or is only partially
+/// supported
+#[derive(Error, Debug)]
+#[error("Keyword `{keyword}` at `{path}` {reason}")]
+pub struct UnsupportedKeyword {
+    /// JSON pointer to the schema containing the keyword, as in `#/properties/name`
+    pub path: String,
+    pub keyword: String,
+    pub reason: String,
+}
+
+#[derive(Error, Debug)]
+#[error("Refinement `{0}` can't be expressed in JSON Schema")]
+pub struct UnsupportedRefinement(String);
+
+/// Converts the thing into a JSON Schema draft 2020-12 document.
+///
+/// Schemas describe refined values, so coercions are lost: coercing refinements are exported
+/// like the strict ones
+pub fn to_json_schema(thing: &Thing) -> Result<Value> {
+    if is_empty_union(thing) {
+        return Ok(Value::Bool(false));
+    }
+    let mut schema = Map::new();
+    schema.insert("$schema".to_string(), Value::from(DRAFT_2020_12));
+    merge(&mut schema, thing_schema(thing)?);
+    Ok(Value::Object(schema))
+}
+
+/// Whether the thing has a union without variants, which no value satisfies. JSON Schema
+/// doesn't allow an empty `anyOf`, so such things are written as `false`
+fn is_empty_union(thing: &Thing) -> bool {
+    thing.0.iter().any(|refinement| {
+        (**refinement)
+            .downcast_ref::<AnyOfRefinement>()
+            .is_some_and(|union| union.variants.is_empty())
+    })
+}
+
+/// Schema of a nested thing, which is `false` for things that allow no values
+fn subschema(thing: &Thing) -> Result<Value> {
+    if is_empty_union(thing) {
+        return Ok(Value::Bool(false));
+    }
+    thing_schema(thing).map(Value::Object)
+}
+
+fn thing_schema(thing: &Thing) -> Result<Map<String, Value>> {
+    let primitive = thing.get_refinement::<PrimitiveRefinement>().copied();
+    let mut schema = Map::new();
+    for refinement in &thing.0 {
+        merge(&mut schema, refinement_schema(&**refinement, primitive)?);
+    }
+    Ok(schema)
+}
+
+/// Adds keywords of the fragment to the schema, moving the fragment into `allOf` when the
+/// schema already has some of them
+fn merge(schema: &mut Map<String, Value>, fragment: Map<String, Value>) {
+    if fragment.keys().any(|key| schema.contains_key(key)) {
+        let all_of = schema
+            .entry("allOf")
+            .or_insert_with(|| Value::Array(vec![]));
+        if let Value::Array(all_of) = all_of {
+            all_of.push(Value::Object(fragment));
+        }
+    } else {
+        schema.extend(fragment);
+    }
+}
+
+fn keyword(name: &str, value: impl Into<Value>) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert(name.to_string(), value.into());
+    map
+}
+
+fn refinement_schema(
+    refinement: &dyn Refinement,
+    primitive: Option<PrimitiveRefinement>,
+) -> Result<Map<String, Value>> {
+    if let Some(primitive) = refinement.downcast_ref::<PrimitiveRefinement>() {
+        let name = match primitive {
+            PrimitiveRefinement::Number => "number",
+            PrimitiveRefinement::String => "string",
+            PrimitiveRefinement::Bool => "boolean",
+            PrimitiveRefinement::Array => "array",
+            PrimitiveRefinement::Object => "object",
+            PrimitiveRefinement::Null => "null",
+        };
+        Ok(keyword("type", name))
+    } else if let Some(bounds) = refinement.downcast_ref::<BoundsRefinement>() {
+        Ok(bounds_schema(bounds, primitive))
+    } else if let Some(multiple_of) = refinement.downcast_ref::<MultipleOfRefinement>() {
+        Ok(keyword("multipleOf", multiple_of.factor))
+    } else if let Some(pattern) = refinement.downcast_ref::<PatternRefinement>() {
+        Ok(keyword("pattern", pattern.pattern.as_str()))
+    } else if let Some(one_of) = refinement.downcast_ref::<OneOfRefinement>() {
+        Ok(keyword("enum", one_of.values.clone()))
+    } else if let Some(object) = refinement.downcast_ref::<ObjectRefinement>() {
+        object_schema(object)
+    } else if let Some(array) = refinement.downcast_ref::<ArrayOfRefinement>() {
+        Ok(keyword("items", subschema(&array.items)?))
+    } else if let Some(tuple) = refinement.downcast_ref::<TupleRefinement>() {
+        let items = tuple
+            .items
+            .iter()
+            .map(subschema)
+            .collect::<Result<Vec<_>>>()?;
+        let mut schema = keyword("minItems", items.len());
+        schema.insert("prefixItems".to_string(), Value::Array(items));
+        schema.insert("items".to_string(), Value::Bool(false));
+        Ok(schema)
+    } else if refinement.downcast_ref::<UniqueItemsRefinement>().is_some() {
+        Ok(keyword("uniqueItems", true))
+    } else if let Some(union) = refinement.downcast_ref::<AnyOfRefinement>() {
+        let variants = union
+            .variants
+            .iter()
+            .map(subschema)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(keyword("anyOf", variants))
+    } else if let Some(union) = refinement.downcast_ref::<TaggedUnionRefinement>() {
+        tagged_union_schema(union)
+    } else {
+        Err(UnsupportedRefinement(refinement.to_string()).into())
+    }
+}
+
+/// Bounds apply to numbers, string lengths and array lengths alike, while JSON Schema has
+/// separate keywords for each of them, so only the ones of the thing's primitive are written
+fn bounds_schema(
+    bounds: &BoundsRefinement,
+    primitive: Option<PrimitiveRefinement>,
+) -> Map<String, Value> {
+    let mut schema = Map::new();
+    if matches!(primitive, None | Some(PrimitiveRefinement::Number)) {
+        match bounds.min {
+            Bound::Included(min) => schema.insert("minimum".to_string(), min.into()),
+            Bound::Excluded(min) => schema.insert("exclusiveMinimum".to_string(), min.into()),
+            Bound::Unbounded => None,
+        };
+        match bounds.max {
+            Bound::Included(max) => schema.insert("maximum".to_string(), max.into()),
+            Bound::Excluded(max) => schema.insert("exclusiveMaximum".to_string(), max.into()),
+            Bound::Unbounded => None,
+        };
+    }
+    // Lengths are inclusive integers
+    let min_length = match bounds.min {
+        Bound::Included(min) => Some(min.ceil()),
+        Bound::Excluded(min) => Some(min.floor() + 1.0),
+        Bound::Unbounded => None,
+    }
+    .map(|min| min.max(0.0) as u64);
+    let max_length = match bounds.max {
+        Bound::Included(max) => Some(max.floor()),
+        Bound::Excluded(max) => Some(max.ceil() - 1.0),
+        Bound::Unbounded => None,
+    }
+    .map(|max| max.max(0.0) as u64);
+    for (kind, min_keyword, max_keyword) in [
+        (PrimitiveRefinement::String, "minLength", "maxLength"),
+        (PrimitiveRefinement::Array, "minItems", "maxItems"),
+    ] {
+        if primitive.is_some() && primitive != Some(kind) {
+            continue;
+        }
+        if let Some(min) = min_length {
+            schema.insert(min_keyword.to_string(), min.into());
+        }
+        if let Some(max) = max_length {
+            schema.insert(max_keyword.to_string(), max.into());
+        }
+    }
+    schema
+}
+
+fn object_schema(object: &ObjectRefinement) -> Result<Map<String, Value>> {
+    let mut properties = Map::new();
+    for (name, field) in &object.fields {
+        let mut schema = subschema(&field.thing)?;
+        if let (Value::Object(schema), Some(default)) = (&mut schema, &field.default) {
+            schema.insert("default".to_string(), default.clone());
+        }
+        properties.insert(name.clone(), schema);
+    }
+    let required: Vec<_> = object
+        .fields
+        .iter()
+        .filter(|(_, field)| field.required)
+        .map(|(name, _)| Value::from(name.as_str()))
+        .collect();
+
+    let mut schema = keyword("properties", properties);
+    if !required.is_empty() {
+        schema.insert("required".to_string(), Value::Array(required));
+    }
+    if object.additional_properties != AdditionalProperties::Allow {
+        schema.insert("additionalProperties".to_string(), Value::Bool(false));
+    }
+    Ok(schema)
+}
+
+/// Each variant is a `oneOf` branch that requires the tag field to hold the variant's name
+fn tagged_union_schema(union: &TaggedUnionRefinement) -> Result<Map<String, Value>> {
+    let mut branches = vec![];
+    for (tag, variant) in &union.variants {
+        let mut branch = keyword(
+            "properties",
+            keyword(&union.tag_field, keyword("const", tag.as_str())),
+        );
+        branch.insert("required".to_string(), vec![union.tag_field.clone()].into());
+        branch.insert("allOf".to_string(), vec![subschema(variant)?].into());
+        branches.push(Value::Object(branch));
+    }
+    Ok(keyword("oneOf", branches))
+}
+
+/// Reads a thing from the subset of JSON Schema that refinements can express.
+///
+/// Unsupported keywords are skipped and reported in the warnings, while schemas that can't
+/// be read at all, like ones with invalid patterns or supported keywords holding values of
+/// the wrong type, are errors
+pub fn from_json_schema(schema: &Value, warnings: &mut Vec<anyhow::Error>) -> Result<Thing> {
+    Importer { warnings }.thing("#", schema)
+}
+
+struct Importer<'a> {
+    warnings: &'a mut Vec<anyhow::Error>,
+}
+
+impl Importer<'_> {
+    fn warn(&mut self, path: &str, keyword: &str, reason: &str) {
+        self.warnings.push(
+            UnsupportedKeyword {
+                path: path.to_string(),
+                keyword: keyword.to_string(),
+                reason: reason.to_string(),
+            }
+            .into(),
+        );
+    }
+
+    fn thing(&mut self, path: &str, schema: &Value) -> Result<Thing> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok(Thing(vec![])),
+            // No value satisfies a union without variants
+            Value::Bool(false) => return Ok(Thing::from(AnyOfRefinement { variants: vec![] })),
+            Value::Object(schema) => schema,
+            _ => return Err(anyhow!("Schema at `{}` is not an object", path)),
+        };
+
+        let mut refinements: Vec<Box<dyn Refinement>> = vec![];
+        let mut types = self.types(path, schema)?;
+        self.bounds(path, schema, &mut types, &mut refinements)?;
+        if types.len() > 1 {
+            refinements.push(Box::new(AnyOfRefinement { variants: types }));
+        } else {
+            refinements.extend(types.into_iter().flat_map(|thing| thing.0));
+        }
+
+        if let Some(factor) = schema.get("multipleOf") {
+            let factor = factor
+                .as_f64()
+                .ok_or_else(|| anyhow!("`multipleOf` at `{}` is not a number", path))?;
+            refinements.push(Box::new(MultipleOfRefinement {
+                factor,
+                coerce: false,
+            }));
+        }
+        if let Some(pattern) = schema.get("pattern") {
+            let pattern = pattern
+                .as_str()
+                .ok_or_else(|| anyhow!("`pattern` at `{}` is not a string", path))?;
+            refinements.push(Box::new(PatternRefinement::new(pattern)?));
+        }
+        self.literals(path, schema, &mut refinements)?;
+        if let Some(object) = self.object(path, schema)? {
+            refinements.push(Box::new(object));
+        }
+        self.arrays(path, schema, &mut refinements)?;
+        self.unions(path, schema, &mut refinements)?;
+
+        for keyword in schema.keys() {
+            if !SUPPORTED.contains(&keyword.as_str()) && !ANNOTATIONS.contains(&keyword.as_str()) {
+                self.warn(path, keyword, "is not supported");
+            }
+        }
+        Ok(Thing(refinements))
+    }
+
+    /// Things of each of the listed types
+    fn types(&mut self, path: &str, schema: &Map<String, Value>) -> Result<Vec<Thing>> {
+        let names = match schema.get("type") {
+            None => return Ok(vec![]),
+            Some(Value::String(name)) => vec![name.as_str()],
+            Some(Value::Array(names)) => names
+                .iter()
+                .map(|name| {
+                    name.as_str()
+                        .ok_or_else(|| anyhow!("Invalid type {} at `{}`", name, path))
+                })
+                .collect::<Result<_>>()?,
+            Some(value) => return Err(anyhow!("Invalid type {} at `{}`", value, path)),
+        };
+        names
+            .into_iter()
+            .map(|name| {
+                let primitive = match name {
+                    "number" | "integer" => PrimitiveRefinement::Number,
+                    "string" => PrimitiveRefinement::String,
+                    "boolean" => PrimitiveRefinement::Bool,
+                    "array" => PrimitiveRefinement::Array,
+                    "object" => PrimitiveRefinement::Object,
+                    "null" => PrimitiveRefinement::Null,
+                    _ => return Err(anyhow!("Unknown type `{}` at `{}`", name, path)),
+                };
+                let mut thing = Thing::from(primitive);
+                if name == "integer" {
+                    thing.0.push(Box::new(STRICT_INTEGER));
+                }
+                Ok(thing)
+            })
+            .collect()
+    }
+
+    fn bounds(
+        &mut self,
+        path: &str,
+        schema: &Map<String, Value>,
+        types: &mut [Thing],
+        refinements: &mut Vec<Box<dyn Refinement>>,
+    ) -> Result<()> {
+        let number = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+        let groups = [
+            (
+                PrimitiveRefinement::Number,
+                ["minimum", "exclusiveMinimum", "maximum", "exclusiveMaximum"].as_slice(),
+            ),
+            (
+                PrimitiveRefinement::String,
+                ["minLength", "maxLength"].as_slice(),
+            ),
+            (
+                PrimitiveRefinement::Array,
+                ["minItems", "maxItems"].as_slice(),
+            ),
+        ];
+        for keyword in groups.iter().flat_map(|(_, keywords)| keywords.iter()) {
+            if schema.get(*keyword).is_some_and(|value| !value.is_number()) {
+                return Err(anyhow!("`{}` at `{}` is not a number", keyword, path));
+            }
+        }
+        for (kind, keywords) in groups {
+            let Some(first) = keywords
+                .iter()
+                .find(|keyword| schema.contains_key(**keyword))
+            else {
+                continue;
+            };
+            if kind == PrimitiveRefinement::Array && is_exported_tuple(schema) {
+                continue;
+            }
+            // Only the listed types of the matching kind are bounded
+            let mut matching: Vec<_> = types
+                .iter_mut()
+                .filter(|thing| thing.get_refinement::<PrimitiveRefinement>() == Some(&kind))
+                .collect();
+            if !schema.contains_key("type") {
+                self.warn(path, first, "applies to numbers, strings and arrays alike");
+            } else if matching.is_empty() {
+                let reason = format!("doesn't apply to {}", schema["type"]);
+                for keyword in keywords.iter().filter(|k| schema.contains_key(**k)) {
+                    self.warn(path, keyword, &reason);
+                }
+                continue;
+            }
+
+            let (min, max) = if kind == PrimitiveRefinement::Number {
+                // The larger of inclusive and exclusive minimums is stricter, and exclusive
+                // ones are stricter than the equal inclusive ones
+                let min = match (number("minimum"), number("exclusiveMinimum")) {
+                    (Some(min), Some(excl)) if min > excl => Bound::Included(min),
+                    (_, Some(excl)) => Bound::Excluded(excl),
+                    (Some(min), None) => Bound::Included(min),
+                    (None, None) => Bound::Unbounded,
+                };
+                let max = match (number("maximum"), number("exclusiveMaximum")) {
+                    (Some(max), Some(excl)) if max < excl => Bound::Included(max),
+                    (_, Some(excl)) => Bound::Excluded(excl),
+                    (Some(max), None) => Bound::Included(max),
+                    (None, None) => Bound::Unbounded,
+                };
+                (min, max)
+            } else {
+                let bound =
+                    |keyword: &str| number(keyword).map_or(Bound::Unbounded, Bound::Included);
+                (bound(keywords[0]), bound(keywords[1]))
+            };
+            let bounds = BoundsRefinement {
+                min,
+                max,
+                coerce: false,
+            };
+            if matching.is_empty() {
+                refinements.push(Box::new(bounds));
+            }
+            for thing in &mut matching {
+                thing.0.push(Box::new(bounds));
+            }
+        }
+        Ok(())
+    }
+
+    fn literals(
+        &mut self,
+        path: &str,
+        schema: &Map<String, Value>,
+        refinements: &mut Vec<Box<dyn Refinement>>,
+    ) -> Result<()> {
+        for keyword in ["enum", "const"] {
+            let values = match schema.get(keyword) {
+                None => continue,
+                Some(Value::Array(values)) if keyword == "enum" => values.clone(),
+                Some(_) if keyword == "enum" => {
+                    return Err(anyhow!("`enum` at `{}` is not an array", path))
+                }
+                Some(value) => vec![value.clone()],
+            };
+            match OneOfRefinement::new(values) {
+                Ok(one_of) => refinements.push(Box::new(one_of)),
+                Err(_) => self.warn(path, keyword, "is only supported with strings and numbers"),
+            }
+        }
+        Ok(())
+    }
+
+    fn object(
+        &mut self,
+        path: &str,
+        schema: &Map<String, Value>,
+    ) -> Result<Option<ObjectRefinement>> {
+        let properties = match schema.get("properties") {
+            None => None,
+            Some(Value::Object(properties)) => Some(properties),
+            Some(_) => return Err(anyhow!("`properties` at `{}` is not an object", path)),
+        };
+        let required = match schema.get("required") {
+            None => vec![],
+            Some(Value::Array(names)) => names
+                .iter()
+                .map(|name| {
+                    name.as_str()
+                        .ok_or_else(|| anyhow!("`required` at `{}` has a non-string name", path))
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => return Err(anyhow!("`required` at `{}` is not an array", path)),
+        };
+        let additional = schema.get("additionalProperties");
+        if properties.is_none() && !schema.contains_key("required") && additional.is_none() {
+            return Ok(None);
+        }
+
+        let mut fields = BTreeMap::new();
+        for (name, property) in properties.into_iter().flatten() {
+            let property_path = format!("{}/properties/{}", path, escape(name));
+            // Defaults belong to fields, not to things
+            let mut property = property.clone();
+            let default = property.as_object_mut().and_then(|p| p.remove("default"));
+            let thing = self.thing(&property_path, &property)?;
+            fields.insert(
+                name.clone(),
+                Field {
+                    thing,
+                    required: false,
+                    default,
+                },
+            );
+        }
+        for name in required {
+            fields
+                .entry(name.to_string())
+                .or_insert_with(|| Field::optional(Thing(vec![])))
+                .required = true;
+        }
+        let additional_properties = match additional {
+            None | Some(Value::Bool(true)) => AdditionalProperties::Allow,
+            Some(Value::Bool(false)) => AdditionalProperties::Deny,
+            Some(_) => {
+                self.warn(
+                    path,
+                    "additionalProperties",
+                    "is only supported as a boolean",
+                );
+                AdditionalProperties::Allow
+            }
+        };
+        Ok(Some(ObjectRefinement {
+            fields,
+            additional_properties,
+        }))
+    }
+
+    fn arrays(
+        &mut self,
+        path: &str,
+        schema: &Map<String, Value>,
+        refinements: &mut Vec<Box<dyn Refinement>>,
+    ) -> Result<()> {
+        match (schema.get("prefixItems"), schema.get("items")) {
+            (Some(Value::Array(prefix)), Some(Value::Bool(false))) => {
+                let items = prefix
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| self.thing(&format!("{}/prefixItems/{}", path, i), item))
+                    .collect::<Result<_>>()?;
+                refinements.push(Box::new(TupleRefinement { items }));
+            }
+            (Some(_), _) => self.warn(
+                path,
+                "prefixItems",
+                "is only supported together with `\"items\": false`",
+            ),
+            (None, Some(items)) => {
+                let items = self.thing(&format!("{}/items", path), items)?;
+                refinements.push(Box::new(ArrayOfRefinement { items }));
+            }
+            (None, None) => {}
+        }
+        match schema.get("uniqueItems") {
+            Some(Value::Bool(true)) => {
+                refinements.push(Box::new(UniqueItemsRefinement { coerce: false }))
+            }
+            Some(Value::Bool(false)) | None => {}
+            Some(_) => self.warn(path, "uniqueItems", "is not a boolean"),
+        }
+        Ok(())
+    }
+
+    fn subschemas(
+        &mut self,
+        path: &str,
+        schema: &Map<String, Value>,
+        keyword: &str,
+    ) -> Result<Option<Vec<Thing>>> {
+        let Some(subschemas) = schema.get(keyword) else {
+            return Ok(None);
+        };
+        let subschemas = subschemas
+            .as_array()
+            .ok_or_else(|| anyhow!("`{}` at `{}` is not an array", keyword, path))?;
+        subschemas
+            .iter()
+            .enumerate()
+            .map(|(i, subschema)| self.thing(&format!("{}/{}/{}", path, keyword, i), subschema))
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    fn unions(
+        &mut self,
+        path: &str,
+        schema: &Map<String, Value>,
+        refinements: &mut Vec<Box<dyn Refinement>>,
+    ) -> Result<()> {
+        // Every one of the subschemas has to be satisfied, like refinements of a thing
+        if let Some(all_of) = self.subschemas(path, schema, "allOf")? {
+            refinements.extend(all_of.into_iter().flat_map(|thing| thing.0));
+        }
+        if let Some(variants) = self.subschemas(path, schema, "anyOf")? {
+            refinements.push(Box::new(AnyOfRefinement { variants }));
+        }
+        if let Some(Value::Array(branches)) = schema.get("oneOf") {
+            if let Some(union) = self.tagged_union(path, branches)? {
+                refinements.push(Box::new(union));
+                return Ok(());
+            }
+        }
+        if let Some(variants) = self.subschemas(path, schema, "oneOf")? {
+            self.warn(path, "oneOf", "is treated as `anyOf`");
+            refinements.push(Box::new(AnyOfRefinement { variants }));
+        }
+        Ok(())
+    }
+
+    /// Reads `oneOf` branches that all require the same field to hold a string constant as a
+    /// tagged union, or returns `None` if the branches are not like that
+    fn tagged_union(
+        &mut self,
+        path: &str,
+        branches: &[Value],
+    ) -> Result<Option<TaggedUnionRefinement>> {
+        let tag_of = |branch: &Value, field: &str| -> Option<String> {
+            let branch = branch.as_object()?;
+            let tag = branch.get("properties")?.get(field)?.as_object()?;
+            let required = branch.get("required")?.as_array()?;
+            if tag.len() != 1 || !required.iter().any(|name| name == field) {
+                return None;
+            }
+            Some(tag.get("const")?.as_str()?.to_string())
+        };
+        let Some(first) = branches.first().and_then(|branch| branch.get("properties")) else {
+            return Ok(None);
+        };
+        let Some(tag_field) = first
+            .as_object()
+            .into_iter()
+            .flat_map(|properties| properties.keys())
+            .find(|field| {
+                branches
+                    .iter()
+                    .all(|branch| tag_of(branch, field).is_some())
+            })
+        else {
+            return Ok(None);
+        };
+
+        let mut variants = BTreeMap::new();
+        for (i, branch) in branches.iter().enumerate() {
+            let tag = tag_of(branch, tag_field).expect("Branches were checked to have tags");
+            // The rest of the branch describes the variant
+            let mut branch = branch.clone();
+            let object = branch.as_object_mut().expect("Branches are objects");
+            remove_tag(object, tag_field);
+            let variant = self.thing(&format!("{}/oneOf/{}", path, i), &branch)?;
+            if variants.insert(tag.clone(), variant).is_some() {
+                return Err(anyhow!(
+                    "`oneOf` at `{}` has more than one branch with the tag `{}`",
+                    path,
+                    tag
+                ));
+            }
+        }
+        Ok(Some(TaggedUnionRefinement {
+            tag_field: tag_field.clone(),
+            variants,
+        }))
+    }
+}
+
+/// Keywords that are read into refinements
+static SUPPORTED: [&str; 22] = [
+    "type",
+    "minimum",
+    "exclusiveMinimum",
+    "maximum",
+    "exclusiveMaximum",
+    "minLength",
+    "maxLength",
+    "minItems",
+    "maxItems",
+    "multipleOf",
+    "pattern",
+    "enum",
+    "const",
+    "properties",
+    "required",
+    "additionalProperties",
+    "prefixItems",
+    "items",
+    "uniqueItems",
+    "allOf",
+    "anyOf",
+    "oneOf",
+];
+
+/// Tuples are exported with `minItems` of their length, which the tuple refinement already
+/// checks
+fn is_exported_tuple(schema: &Map<String, Value>) -> bool {
+    let length = schema
+        .get("prefixItems")
+        .and_then(Value::as_array)
+        .map(Vec::len);
+    schema.get("items") == Some(&Value::Bool(false))
+        && !schema.contains_key("maxItems")
+        && schema.get("minItems").and_then(Value::as_u64) == length.map(|len| len as u64)
+}
+
+fn remove_tag(branch: &mut Map<String, Value>, tag_field: &str) {
+    if let Some(Value::Object(properties)) = branch.get_mut("properties") {
+        properties.remove(tag_field);
+        if properties.is_empty() {
+            branch.remove("properties");
+        }
+    }
+    if let Some(Value::Array(required)) = branch.get_mut("required") {
+        required.retain(|name| name != tag_field);
+        if required.is_empty() {
+            branch.remove("required");
+        }
+    }
+}
+
+fn escape(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    use serde_json::json;
+
+    use crate::thing::array::{ArrayOfRefinement, TupleRefinement, UniqueItemsRefinement};
+    use crate::thing::bounds::BoundsRefinement;
+    use crate::thing::json_schema::{from_json_schema, to_json_schema, UnsupportedKeyword};
+    use crate::thing::multiple_of::STRICT_INTEGER;
+    use crate::thing::object::{AdditionalProperties, Field, ObjectRefinement};
+    use crate::thing::one_of::OneOfRefinement;
+    use crate::thing::pattern::PatternRefinement;
+    use crate::thing::primitives::PrimitiveRefinement;
+    use crate::thing::tests_utils::{TestAssertionResult, TestConversionResultWithWarnings};
+    use crate::thing::union::TaggedUnionRefinement;
+    use crate::thing::{Refinement, Thing, ThingLike};
+
+    fn percentage(coerce: bool) -> Thing {
+        Thing(vec![
+            Box::new(PrimitiveRefinement::Number),
+            Box::new(BoundsRefinement {
+                min: Bound::Included(0.0),
+                max: Bound::Excluded(100.0),
+                coerce,
+            }),
+            Box::new(STRICT_INTEGER),
+        ])
+    }
+
+    fn user() -> Thing {
+        let name: Vec<Box<dyn Refinement>> = vec![
+            Box::new(PrimitiveRefinement::String),
+            Box::new(BoundsRefinement {
+                min: Bound::Included(1.0),
+                max: Bound::Unbounded,
+                coerce: false,
+            }),
+            Box::new(PatternRefinement::new("^[a-z]+$").unwrap()),
+        ];
+        let tags = Thing(vec![
+            Box::new(PrimitiveRefinement::Array),
+            Box::new(ArrayOfRefinement {
+                items: Thing::from(
+                    OneOfRefinement::new(vec!["admin".into(), "guest".into()]).unwrap(),
+                ),
+            }),
+            Box::new(UniqueItemsRefinement { coerce: false }),
+        ]);
+        let mut object = ObjectRefinement::new([
+            ("name", Field::required(Thing(name))),
+            (
+                "progress",
+                Field::optional(percentage(false)).with_default(json!(0)),
+            ),
+            ("tags", Field::optional(tags)),
+            (
+                "position",
+                Field::optional(TupleRefinement {
+                    items: vec![
+                        Thing::from(PrimitiveRefinement::Number),
+                        Thing::from(PrimitiveRefinement::Number),
+                    ],
+                }),
+            ),
+        ]);
+        object.additional_properties = AdditionalProperties::Deny;
+        Thing(vec![
+            Box::new(PrimitiveRefinement::Object),
+            Box::new(object),
+        ])
+    }
+
+    fn shape() -> Thing {
+        let circle = ObjectRefinement::new([
+            ("kind", Field::required(PrimitiveRefinement::String)),
+            ("radius", Field::required(PrimitiveRefinement::Number)),
+        ]);
+        let square =
+            ObjectRefinement::new([("side", Field::required(PrimitiveRefinement::Number))]);
+        Thing::from(TaggedUnionRefinement {
+            tag_field: "kind".to_string(),
+            variants: BTreeMap::from([
+                ("circle".to_string(), Thing::from(circle)),
+                ("square".to_string(), Thing::from(square)),
+            ]),
+        })
+    }
+
+    #[test]
+    fn export() {
+        assert_eq!(
+            to_json_schema(&percentage(true)).assert(),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "number",
+                "minimum": 0.0,
+                "exclusiveMaximum": 100.0,
+                "multipleOf": 1.0,
+            })
+        );
+
+        let schema = to_json_schema(&user()).assert();
+        assert_eq!(
+            schema["properties"]["name"],
+            json!({ "type": "string", "minLength": 1, "pattern": "^[a-z]+$" })
+        );
+        assert_eq!(schema["properties"]["progress"]["default"], json!(0));
+        assert_eq!(schema["required"], json!(["name"]));
+        assert_eq!(schema["additionalProperties"], json!(false));
+        assert_eq!(
+            schema["properties"]["position"],
+            json!({
+                "prefixItems": [{ "type": "number" }, { "type": "number" }],
+                "items": false,
+                "minItems": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        // Coercions are lost, so only strict things are the same after importing
+        for thing in [percentage(false), user(), shape()] {
+            let mut warnings = vec![];
+            let schema = to_json_schema(&thing).assert();
+            let imported = from_json_schema(&schema, &mut warnings).assert();
+            assert!(warnings.is_empty(), "{:?}", warnings);
+            assert_eq!(imported.to_value().assert(), thing.to_value().assert());
+        }
+        shape()
+            .apply(&mut json!({ "kind": "square", "side": 2 }))
+            .with_warnings(0)
+            .assert();
+    }
+
+    #[test]
+    fn import() {
+        let schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Order",
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer", "minimum": 1 },
+                "email": { "type": "string", "format": "email" },
+                "items": { "type": "array", "items": { "$ref": "#/$defs/item" } },
+                "note": { "type": ["string", "null"], "maxLength": 10 },
+            },
+            "required": ["id", "items"],
+        });
+        let mut warnings = vec![];
+        let thing = from_json_schema(&schema, &mut warnings).assert();
+        let warnings: Vec<_> = warnings
+            .iter()
+            .map(|warn| {
+                let warn = warn.downcast_ref::<UnsupportedKeyword>().unwrap();
+                (warn.path.as_str(), warn.keyword.as_str())
+            })
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                ("#/properties/email", "format"),
+                ("#/properties/items/items", "$ref"),
+            ]
+        );
+
+        thing
+            .apply(&mut json!({ "id": 3, "items": [], "note": null }))
+            .with_warnings(0)
+            .assert();
+        assert!(thing.apply(&mut json!({ "id": 0.5, "items": [] })).is_err());
+        assert!(thing.apply(&mut json!({ "id": 0, "items": [] })).is_err());
+        assert!(thing.apply(&mut json!({ "id": 3 })).is_err());
+        let long_note = json!({ "id": 3, "items": [], "note": "a note that is too long" });
+        assert!(thing.apply(&mut long_note.clone()).is_err());
+    }
+
+    #[test]
+    fn malformed_keywords() {
+        let error = |schema: serde_json::Value| {
+            from_json_schema(&schema, &mut vec![])
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(json!({ "properties": ["name"] })),
+            "`properties` at `#` is not an object"
+        );
+        assert_eq!(
+            error(json!({ "required": "name" })),
+            "`required` at `#` is not an array"
+        );
+        assert_eq!(
+            error(json!({ "required": ["name", 1] })),
+            "`required` at `#` has a non-string name"
+        );
+        assert_eq!(
+            error(json!({ "type": "string", "minLength": "1" })),
+            "`minLength` at `#` is not a number"
+        );
+        assert_eq!(
+            error(json!({ "type": ["string", 1] })),
+            "Invalid type 1 at `#`"
+        );
+        assert_eq!(
+            error(json!({ "enum": "north" })),
+            "`enum` at `#` is not an array"
+        );
+        let branch = json!({
+            "type": "object",
+            "properties": { "kind": { "const": "circle" } },
+            "required": ["kind"],
+        });
+        assert_eq!(
+            error(json!({ "oneOf": [branch, branch] })),
+            "`oneOf` at `#` has more than one branch with the tag `circle`"
+        );
+    }
+
+    #[test]
+    fn empty_things() {
+        let nothing = from_json_schema(&json!(false), &mut vec![]).assert();
+        assert_eq!(to_json_schema(&nothing).assert(), json!(false));
+        let list = Thing::from(ArrayOfRefinement { items: nothing });
+        assert_eq!(to_json_schema(&list).assert()["items"], json!(false));
+    }
+
+    #[test]
+    fn inapplicable_bounds() {
+        let mut warnings = vec![];
+        let thing =
+            from_json_schema(&json!({ "type": "string", "minimum": 3 }), &mut warnings).assert();
+        assert_eq!(warnings.len(), 1);
+        assert!(thing.get_refinement::<BoundsRefinement>().is_none());
+    }
+}
@@ -1,12 +1,14 @@
 ﻿use std::fmt::{Display, Formatter};
 
-use anyhow::Error;
 use as_any::Downcast;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 
 use crate::thing::primitives::{validate_number, RefinementCastError};
-use crate::thing::{Refinement, Relation};
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Intersection, Refinement, Relation};
 
 pub static INTEGER: MultipleOfRefinement = MultipleOfRefinement {
     factor: 1f64,
@@ -17,9 +19,10 @@ pub static STRICT_INTEGER: MultipleOfRefinement = MultipleOfRefinement {
     coerce: false,
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct MultipleOfRefinement {
     pub factor: f64,
+    #[serde(default)]
     pub coerce: bool,
 }
 
@@ -34,7 +37,7 @@ impl Display for MultipleOfRefinement {
 }
 
 impl Refinement for MultipleOfRefinement {
-    fn apply(&self, value: &mut Value, warnings: &mut Vec<Error>) -> anyhow::Result<()> {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
         let num = validate_number(value)?;
         if num % self.factor == 0.0 {
             return Ok(());
@@ -46,8 +49,14 @@ impl Refinement for MultipleOfRefinement {
         }
         .into();
         if self.coerce {
-            *value = Value::from((num / self.factor).round() * self.factor);
-            warnings.push(err);
+            let coerced = Value::from((num / self.factor).round() * self.factor);
+            let original = std::mem::replace(value, coerced);
+            warnings.push(ValidationIssue::warning(
+                self,
+                original,
+                Some(value.clone()),
+                err,
+            ));
             Ok(())
         } else {
             Err(err)
@@ -84,9 +93,55 @@ impl Refinement for MultipleOfRefinement {
         )
     }
 
+    fn intersect(&self, other: &dyn Refinement) -> Intersection {
+        let Some(other) = other.downcast_ref::<MultipleOfRefinement>() else {
+            return Intersection::Unrelated;
+        };
+        // Rounding to one factor and then to the other, or checking it, is not the same as
+        // rounding to their multiple, so only checks are merged
+        if self.coerce || other.coerce {
+            return Intersection::Unrelated;
+        }
+        match lcm(self.factor, other.factor) {
+            Some(factor) => Intersection::Merged(Box::new(MultipleOfRefinement {
+                factor,
+                coerce: false,
+            })),
+            None => Intersection::Unrelated,
+        }
+    }
+
     fn clone(&self) -> Box<dyn Refinement> {
         Box::new(Clone::clone(self))
     }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for MultipleOfRefinement {
+    const TAG: &'static str = "MultipleOf";
+}
+
+/// Least common multiple of integer factors, or of factors where one is a multiple of the other
+fn lcm(a: f64, b: f64) -> Option<f64> {
+    let (a, b) = (a.abs(), b.abs());
+    if a == 0.0 || b == 0.0 || !a.is_finite() || !b.is_finite() {
+        return None;
+    }
+    // Integers up to 2^53 are exact in f64
+    let exact = |x: f64| x.fract() == 0.0 && x < 2f64.powi(53);
+    if exact(a) && exact(b) {
+        let (mut x, mut y) = (a as u64, b as u64);
+        while y != 0 {
+            (x, y) = (y, x % y);
+        }
+        let lcm = (a as u64 / x).checked_mul(b as u64)?;
+        return Some(lcm as f64);
+    }
+    let (min, max) = if a < b { (a, b) } else { (b, a) };
+    ((max / min).fract() == 0.0).then_some(max)
 }
 
 #[derive(Error, Debug)]
@@ -0,0 +1,191 @@
+use thiserror::Error;
+
+use crate::thing::{Intersection, Refinement, Thing};
+
+#[derive(Error, Debug)]
+#[error("Thing is empty, no value satisfies both `{first}` and `{second}`")]
+pub struct EmptyThingError {
+    pub first: String,
+    pub second: String,
+}
+
+fn intersect(first: &dyn Refinement, second: &dyn Refinement) -> Intersection {
+    match first.intersect(second) {
+        Intersection::Unrelated => second.intersect(first),
+        intersection => intersection,
+    }
+}
+
+impl Thing {
+    /// Thing of the refinements, normalized so that empty things are rejected right away
+    pub fn checked(refinements: Vec<Box<dyn Refinement>>) -> anyhow::Result<Thing> {
+        Thing(refinements).normalize()
+    }
+
+    /// Merges refinements that can be expressed as one, like overlapping bounds, keeping the
+    /// merged refinement at the place of the first of them.
+    ///
+    /// Fails when no value can satisfy the thing, like with disjoint bounds or different
+    /// primitives. Emptiness is only detected between pairs of refinements, so some empty
+    /// things are still normalized
+    pub fn normalize(&self) -> anyhow::Result<Thing> {
+        let mut refinements: Vec<Box<dyn Refinement>> = Vec::with_capacity(self.0.len());
+        'refinements: for refinement in &self.0 {
+            for merged in refinements.iter_mut() {
+                match intersect(&**merged, &**refinement) {
+                    Intersection::Unrelated => {}
+                    Intersection::Merged(intersection) => {
+                        *merged = intersection;
+                        continue 'refinements;
+                    }
+                    Intersection::Empty => {
+                        return Err(EmptyThingError {
+                            first: merged.to_string(),
+                            second: refinement.to_string(),
+                        }
+                        .into())
+                    }
+                }
+            }
+            refinements.push((**refinement).clone());
+        }
+        Ok(Thing(refinements))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::ops::Bound;
+
+    use serde_json::json;
+
+    use crate::thing::bounds::BoundsRefinement;
+    use crate::thing::multiple_of::{MultipleOfRefinement, STRICT_INTEGER};
+    use crate::thing::normalize::EmptyThingError;
+    use crate::thing::one_of::OneOfRefinement;
+    use crate::thing::primitives::PrimitiveRefinement;
+    use crate::thing::tests_utils::TestAssertionResult;
+    use crate::thing::{Refinement, Thing, ThingLike};
+
+    fn bounds(min: Bound<f64>, max: Bound<f64>) -> BoundsRefinement {
+        BoundsRefinement {
+            min,
+            max,
+            coerce: false,
+        }
+    }
+
+    fn multiple_of(factor: f64) -> MultipleOfRefinement {
+        MultipleOfRefinement {
+            factor,
+            coerce: false,
+        }
+    }
+
+    #[test]
+    fn merging() {
+        let thing = Thing(vec![
+            Box::new(PrimitiveRefinement::Number),
+            Box::new(bounds(Bound::Included(0.0), Bound::Excluded(10.0))),
+            Box::new(bounds(Bound::Included(5.0), Bound::Included(20.0))),
+            Box::new(STRICT_INTEGER),
+            Box::new(multiple_of(2.0)),
+            Box::new(multiple_of(3.0)),
+            Box::new(PrimitiveRefinement::Number),
+        ]);
+        let normalized = thing.normalize().assert();
+        assert_eq!(normalized.0.len(), 3);
+        let merged = normalized.get_refinement::<BoundsRefinement>().unwrap();
+        assert_eq!(
+            (merged.min, merged.max),
+            (Bound::Included(5.0), Bound::Excluded(10.0))
+        );
+        let factor = normalized
+            .get_refinement::<MultipleOfRefinement>()
+            .unwrap()
+            .factor;
+        assert_eq!(factor, 6.0);
+
+        for value in [json!(6), json!(7), json!(12), json!(0)] {
+            assert_eq!(
+                thing.apply(&mut value.clone()).is_ok(),
+                normalized.apply(&mut value.clone()).is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn coercions_are_kept() {
+        let mut coercing = bounds(Bound::Included(0.0), Bound::Included(5.0));
+        coercing.coerce = true;
+        let thing = Thing(vec![
+            Box::new(coercing),
+            Box::new(bounds(Bound::Included(1.0), Bound::Unbounded)),
+        ]);
+        assert_eq!(thing.normalize().assert().0.len(), 2);
+
+        // Rounding to 2 and then to 3 turns 7 into 9, not into the closest multiple of 6
+        let rounding = Thing(vec![
+            Box::new(MultipleOfRefinement {
+                factor: 2.0,
+                coerce: true,
+            }),
+            Box::new(MultipleOfRefinement {
+                factor: 3.0,
+                coerce: true,
+            }),
+        ]);
+        let normalized = rounding.normalize().assert();
+        assert_eq!(normalized.0.len(), 2);
+        let mut value = json!(7);
+        normalized.apply(&mut value).unwrap();
+        assert_eq!(value, json!(9.0));
+
+        // Disjoint bounds that clamp still accept every number
+        let mut low = bounds(Bound::Included(0.0), Bound::Included(1.0));
+        low.coerce = true;
+        let mut high = bounds(Bound::Included(5.0), Bound::Included(6.0));
+        high.coerce = true;
+        let clamping = Thing::checked(vec![Box::new(low), Box::new(high)]).assert();
+        let mut value = json!(3);
+        clamping.apply(&mut value).unwrap();
+        assert_eq!(value, json!(5.0));
+    }
+
+    #[test]
+    fn empty() {
+        let disjoint = Thing(vec![
+            Box::new(bounds(Bound::Included(0.0), Bound::Excluded(5.0))),
+            Box::new(bounds(Bound::Included(5.0), Bound::Unbounded)),
+        ]);
+        for number in [-1.0, 0.0, 4.9, 5.0, 100.0] {
+            assert!(disjoint.apply(&mut json!(number)).is_err());
+        }
+        let err = disjoint.normalize().unwrap_err();
+        assert!(err.is::<EmptyThingError>());
+        assert!(Thing::checked(disjoint.0).is_err());
+
+        let primitives: Vec<Box<dyn Refinement>> = vec![
+            Box::new(PrimitiveRefinement::Number),
+            Box::new(PrimitiveRefinement::String),
+        ];
+        let err = Thing(primitives).normalize().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Thing is empty, no value satisfies both `Number` and `String`"
+        );
+
+        let literals = Thing(vec![
+            Box::new(OneOfRefinement::new(vec![json!("a"), json!(1)]).unwrap()),
+            Box::new(OneOfRefinement::new(vec![json!("b"), json!(1.0)]).unwrap()),
+        ]);
+        let merged = literals.normalize().assert();
+        let one_of = merged.get_refinement::<OneOfRefinement>().unwrap();
+        assert_eq!(one_of.values, [json!(1)]);
+        let literals = Thing(vec![
+            Box::new(OneOfRefinement::new(vec![json!("a"), json!("b")]).unwrap()),
+            Box::new(PrimitiveRefinement::Number),
+        ]);
+        assert!(literals.normalize().unwrap_err().is::<EmptyThingError>());
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use anyhow::Error;
+use as_any::Downcast;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::thing::primitives::inapplicable;
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Refinement, Relation, Thing};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Field {
+    pub thing: Thing,
+    #[serde(default)]
+    pub required: bool,
+    /// Value inserted when the field is missing, which is then refined like any other value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+}
+
+impl Field {
+    pub fn required(thing: impl Into<Thing>) -> Self {
+        Field {
+            thing: thing.into(),
+            required: true,
+            default: None,
+        }
+    }
+
+    pub fn optional(thing: impl Into<Thing>) -> Self {
+        Field {
+            thing: thing.into(),
+            required: false,
+            default: None,
+        }
+    }
+
+    pub fn with_default(mut self, value: impl Into<Value>) -> Self {
+        self.default = Some(value.into());
+        self
+    }
+
+    /// Whether refined objects always have the field
+    fn is_present(&self) -> bool {
+        self.required || self.default.is_some()
+    }
+}
+
+/// What happens to properties of objects that have no field describing them
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AdditionalProperties {
+    /// Properties are kept as is
+    #[default]
+    Allow,
+    /// Properties are reported as errors
+    Deny,
+    /// Properties are removed, with a warning
+    Strip,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ObjectRefinement {
+    pub fields: BTreeMap<String, Field>,
+    #[serde(default)]
+    pub additional_properties: AdditionalProperties,
+}
+
+impl ObjectRefinement {
+    pub fn new(fields: impl IntoIterator<Item = (impl Into<String>, Field)>) -> Self {
+        ObjectRefinement {
+            fields: fields
+                .into_iter()
+                .map(|(name, field)| (name.into(), field))
+                .collect(),
+            additional_properties: AdditionalProperties::Allow,
+        }
+    }
+}
+
+impl Display for ObjectRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Object {{")?;
+        for (i, (name, field)) in self.fields.iter().enumerate() {
+            let separator = if i > 0 { "," } else { "" };
+            let optional = if field.required { "" } else { "?" };
+            write!(f, "{} {}{}: {}", separator, name, optional, field.thing)?;
+        }
+        match self.additional_properties {
+            AdditionalProperties::Allow if self.fields.is_empty() => write!(f, "..."),
+            AdditionalProperties::Allow => write!(f, ", ..."),
+            _ => Ok(()),
+        }?;
+        write!(f, " }}")
+    }
+}
+
+impl Refinement for ObjectRefinement {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        let Value::Object(object) = value else {
+            return Err(inapplicable(self, value));
+        };
+        for (name, field) in &self.fields {
+            if !object.contains_key(name) {
+                match &field.default {
+                    Some(default) => {
+                        object.insert(name.clone(), default.clone());
+                    }
+                    None if field.required => {
+                        let err = ObjectRefinementError::MissingField.into();
+                        return Err(ValidationIssue::error(self, None, err).at(name).into());
+                    }
+                    None => continue,
+                }
+            }
+            let item = object.get_mut(name).expect("Field was checked to exist");
+            let mut field_warnings = vec![];
+            field
+                .thing
+                .refine(item, &mut field_warnings)
+                .map_err(|issue| issue.at(name))?;
+            warnings.extend(field_warnings.into_iter().map(|warn| warn.at(name)));
+        }
+
+        let additional: Vec<_> = object
+            .keys()
+            .filter(|name| !self.fields.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in additional {
+            match self.additional_properties {
+                AdditionalProperties::Allow => {}
+                AdditionalProperties::Deny => {
+                    let err = ObjectRefinementError::AdditionalProperty.into();
+                    let issue = ValidationIssue::error(self, object.get(&name).cloned(), err);
+                    return Err(issue.at(&name).into());
+                }
+                AdditionalProperties::Strip => {
+                    let removed = object.remove(&name).expect("Property was checked to exist");
+                    let err = ObjectRefinementError::StrippedProperty.into();
+                    warnings.push(ValidationIssue::warning(self, removed, None, err).at(&name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        let Some(other) = other.downcast_ref::<ObjectRefinement>() else {
+            return Relation::Unrelated;
+        };
+        for (name, other_field) in &other.fields {
+            let conflict =
+                |err: ObjectRefinementError| Relation::Conflict(at_path(name, err.into()));
+            match self.fields.get(name) {
+                Some(field) => {
+                    if other_field.required && !field.is_present() {
+                        return conflict(ObjectRefinementError::OptionalField);
+                    }
+                    if let Err(err) = field.thing.try_assign_to(&other_field.thing) {
+                        return Relation::Conflict(at_path(name, err));
+                    }
+                }
+                // Additional properties of own objects may hold anything under this name
+                None if self.additional_properties == AdditionalProperties::Allow => {
+                    return conflict(ObjectRefinementError::UndescribedField);
+                }
+                None if other_field.required && other_field.default.is_none() => {
+                    return conflict(ObjectRefinementError::MissingField);
+                }
+                None => {}
+            }
+        }
+
+        if other.additional_properties == AdditionalProperties::Deny {
+            if self.additional_properties == AdditionalProperties::Allow {
+                return Relation::Conflict(ObjectRefinementError::AdditionalProperties.into());
+            }
+            if let Some(name) = self
+                .fields
+                .keys()
+                .find(|name| !other.fields.contains_key(*name))
+            {
+                return Relation::Conflict(at_path(
+                    name,
+                    ObjectRefinementError::AdditionalProperty.into(),
+                ));
+            }
+        }
+        Relation::Subset
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for ObjectRefinement {
+    const TAG: &'static str = "Object";
+}
+
+#[derive(Error, Debug)]
+pub enum ObjectRefinementError {
+    #[error("Required field is missing")]
+    MissingField,
+    #[error("Field is optional where it's required")]
+    OptionalField,
+    #[error("Field is not described, so it may hold any value")]
+    UndescribedField,
+    #[error("Additional property is not allowed")]
+    AdditionalProperty,
+    #[error("Additional property was removed")]
+    StrippedProperty,
+    #[error("Additional properties are not allowed")]
+    AdditionalProperties,
+}
+
+/// Conflict of a thing nested in the refinement, located by a JSON pointer
+#[derive(Error, Debug)]
+#[error("{path}: {error}")]
+pub struct PathError {
+    pub path: String,
+    pub error: Error,
+}
+
+/// Locates the conflict inside of the field or array item, prepending the segment to the path
+/// of conflicts that are already located deeper inside of it
+pub fn at_path(segment: &str, error: Error) -> Error {
+    let segment = segment.replace('~', "~0").replace('/', "~1");
+    match error.downcast::<PathError>() {
+        Ok(PathError { path, error }) => PathError {
+            path: format!("/{}{}", segment, path),
+            error,
+        },
+        Err(error) => PathError {
+            path: format!("/{}", segment),
+            error,
+        },
+    }
+    .into()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::ops::Bound;
+
+    use serde_json::{json, Value};
+
+    use crate::thing::bounds::{BoundsRefinement, BoundsRefinementError};
+    use crate::thing::multiple_of::INTEGER;
+    use crate::thing::object::{
+        AdditionalProperties, Field, ObjectRefinement, ObjectRefinementError, PathError,
+    };
+    use crate::thing::primitives::{PrimitiveRefinement, TypeRefinementError};
+    use crate::thing::registry::RefinementRegistry;
+    use crate::thing::tests_utils::{
+        TestAssertionResult, TestConversionResult, TestConversionResultWithWarnings, TestRefinement,
+    };
+    use crate::thing::validation::ValidationIssue;
+    use crate::thing::{Thing, ThingLike};
+
+    fn level() -> Thing {
+        let bounds = BoundsRefinement {
+            min: Bound::Included(1.0),
+            max: Bound::Included(99.0),
+            coerce: true,
+        };
+        let mut thing = Thing::from(PrimitiveRefinement::Number);
+        thing.0.push(Box::new(bounds));
+        thing.0.push(Box::new(INTEGER));
+        thing
+    }
+
+    fn item() -> ObjectRefinement {
+        ObjectRefinement::new([
+            ("name", Field::required(PrimitiveRefinement::String)),
+            ("level", Field::optional(level()).with_default(1)),
+            ("icon", Field::optional(PrimitiveRefinement::String)),
+        ])
+    }
+
+    fn inventory() -> ObjectRefinement {
+        ObjectRefinement::new([("weapon", Field::required(item()))])
+    }
+
+    /// Path of the error and the error inside of it
+    fn located<T: std::error::Error + Send + Sync + 'static>(
+        result: anyhow::Result<(Value, Vec<ValidationIssue>)>,
+    ) -> (String, T) {
+        let ValidationIssue { path, error, .. } = result.error::<ValidationIssue>().assert();
+        (path, error.downcast::<T>().unwrap())
+    }
+
+    #[test]
+    fn fields() {
+        let (value, _) = item().check(json!({ "name": "Sword" })).success().assert();
+        assert_eq!(value, json!({ "name": "Sword", "level": 1 }));
+
+        let (path, _) = located::<ObjectRefinementError>(item().check(json!({ "level": 3 })));
+        assert_eq!(path, "/name");
+        let (path, _) = located::<TypeRefinementError>(item().check(json!({ "name": 1 })));
+        assert_eq!(path, "/name");
+        assert!(item().check(json!(["Sword"])).is_err());
+    }
+
+    #[test]
+    fn nested_paths() {
+        let value = json!({ "weapon": { "name": "Sword", "icon": null } });
+        let (path, _) = located::<TypeRefinementError>(inventory().check(value));
+        assert_eq!(path, "/weapon/icon");
+
+        let (value, warnings) = inventory()
+            .check(json!({ "weapon": { "name": "Sword", "level": 120.5 } }))
+            .with_warnings(1)
+            .assert();
+        assert_eq!(value["weapon"]["level"], json!(99.0));
+        let warning = &warnings[0];
+        assert_eq!(warning.path, "/weapon/level");
+        assert!(warning.error.is::<BoundsRefinementError>());
+
+        let escaped = ObjectRefinement::new([("a/b~c", Field::required(inventory()))]);
+        let (path, _) = located::<ObjectRefinementError>(escaped.check(json!({ "a/b~c": {} })));
+        assert_eq!(path, "/a~1b~0c/weapon");
+    }
+
+    #[test]
+    fn additional_properties() {
+        let value = json!({ "name": "Sword", "level": 2, "damage": 5 });
+        item().check(value.clone()).with_warnings(0).assert();
+
+        let mut denying = item();
+        denying.additional_properties = AdditionalProperties::Deny;
+        let (path, _) = located::<ObjectRefinementError>(denying.check(value.clone()));
+        assert_eq!(path, "/damage");
+
+        let mut stripping = item();
+        stripping.additional_properties = AdditionalProperties::Strip;
+        let (value, _) = stripping.check(value).with_warnings(1).assert();
+        assert_eq!(value, json!({ "name": "Sword", "level": 2 }));
+    }
+
+    #[test]
+    fn cast() {
+        let mut closed = item();
+        closed.additional_properties = AdditionalProperties::Deny;
+        let mut named =
+            ObjectRefinement::new([("name", Field::required(PrimitiveRefinement::String))]);
+        named.additional_properties = AdditionalProperties::Deny;
+
+        // Own defaults make optional fields present
+        let mut required = closed.clone();
+        required.fields.get_mut("level").unwrap().required = true;
+        Thing::from(closed.clone())
+            .try_assign_to(&Thing::from(required))
+            .success()
+            .assert();
+
+        // Open objects may hold anything under undescribed names
+        Thing::from(item())
+            .try_assign_to(&Thing::from(item()))
+            .success()
+            .assert();
+        Thing::from(ObjectRefinement::new([(
+            "name",
+            Field::required(PrimitiveRefinement::String),
+        )]))
+        .try_assign_to(&Thing::from(item()))
+        .error::<PathError>()
+        .assert();
+        Thing::from(named.clone())
+            .try_assign_to(&Thing::from(item()))
+            .success()
+            .assert();
+        Thing::from(closed)
+            .try_assign_to(&Thing::from(named))
+            .error::<PathError>()
+            .assert();
+
+        let mut numbered = item();
+        numbered.fields.get_mut("name").unwrap().thing = Thing::from(PrimitiveRefinement::Number);
+        let err = Thing::from(inventory())
+            .try_assign_to(&Thing::from(ObjectRefinement::new([(
+                "weapon",
+                Field::required(numbered),
+            )])))
+            .error::<PathError>()
+            .assert();
+        assert_eq!(err.path, "/weapon/name");
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = Thing::from(inventory()).to_value().assert();
+        let loaded = RefinementRegistry::default()
+            .thing_from_value(value.clone())
+            .assert();
+        assert_eq!(loaded.to_value().assert(), value);
+
+        let mut value = json!({ "weapon": { "name": "Axe", "level": 4.2 } });
+        loaded.apply(&mut value).with_warnings(1).assert();
+        assert_eq!(value, json!({ "weapon": { "name": "Axe", "level": 4.0 } }));
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt::{Display, Formatter};
+
+use as_any::Downcast;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::thing::primitives::RefinementCastError;
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Intersection, Refinement, Relation};
+
+/// Values equal to one of the string or number literals, with numbers compared by value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OneOfRefinement {
+    #[serde(deserialize_with = "deserialize_literals")]
+    pub values: Vec<Value>,
+}
+
+impl OneOfRefinement {
+    /// Fails when some of the values are neither strings nor numbers
+    pub fn new(values: Vec<Value>) -> Result<Self, NotALiteralError> {
+        check_literals(&values)?;
+        Ok(OneOfRefinement { values })
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        self.values.iter().any(|literal| literal_eq(literal, value))
+    }
+}
+
+fn check_literals(values: &[Value]) -> Result<(), NotALiteralError> {
+    match values
+        .iter()
+        .find(|value| !value.is_string() && !value.is_number())
+    {
+        Some(value) => Err(NotALiteralError(value.clone())),
+        None => Ok(()),
+    }
+}
+
+fn literal_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (a, b) => a == b,
+    }
+}
+
+fn deserialize_literals<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Value>, D::Error> {
+    let values = Vec::<Value>::deserialize(deserializer)?;
+    check_literals(&values).map_err(serde::de::Error::custom)?;
+    Ok(values)
+}
+
+impl Display for OneOfRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let values: Vec<_> = self.values.iter().map(Value::to_string).collect();
+        write!(f, "OneOf({})", values.join(", "))
+    }
+}
+
+impl Refinement for OneOfRefinement {
+    fn apply(&self, value: &mut Value, _warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        if self.contains(value) {
+            Ok(())
+        } else {
+            Err(OneOfRefinementError {
+                value: value.clone(),
+                refinement: Clone::clone(self),
+            }
+            .into())
+        }
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        if let Some(other) = other.downcast_ref::<OneOfRefinement>() {
+            return if self.values.iter().all(|value| other.contains(value)) {
+                Relation::Subset
+            } else {
+                Relation::Conflict(
+                    RefinementCastError {
+                        got: Clone::clone(self),
+                        expected: Clone::clone(other),
+                    }
+                    .into(),
+                )
+            };
+        }
+        // Any refinement can be checked against the finite set of allowed values, which only
+        // fits in when every one of them passes the other refinement
+        for literal in &self.values {
+            if let Err(err) = other.apply(&mut literal.clone(), &mut vec![]) {
+                return Relation::Conflict(err.context(format!(
+                    "{} of {} does not satisfy {}",
+                    literal, self, other
+                )));
+            }
+        }
+        Relation::Subset
+    }
+
+    fn intersect(&self, other: &dyn Refinement) -> Intersection {
+        if let Some(other) = other.downcast_ref::<OneOfRefinement>() {
+            let values: Vec<_> = self
+                .values
+                .iter()
+                .filter(|value| other.contains(value))
+                .cloned()
+                .collect();
+            return if values.is_empty() {
+                Intersection::Empty
+            } else {
+                Intersection::Merged(Box::new(OneOfRefinement { values }))
+            };
+        }
+        // Other refinements are kept, but the literals still have to pass some of them
+        if self
+            .values
+            .iter()
+            .all(|literal| other.apply(&mut literal.clone(), &mut vec![]).is_err())
+        {
+            return Intersection::Empty;
+        }
+        Intersection::Unrelated
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for OneOfRefinement {
+    const TAG: &'static str = "OneOf";
+}
+
+#[derive(Error, Debug)]
+#[error("{value} is not allowed by {refinement}")]
+pub struct OneOfRefinementError {
+    value: Value,
+    refinement: OneOfRefinement,
+}
+
+#[derive(Error, Debug)]
+#[error("{0} is not a string or number literal")]
+pub struct NotALiteralError(pub Value);
+
+#[cfg(test)]
+pub mod tests {
+    use std::ops::Bound;
+
+    use serde_json::{json, Value};
+
+    use crate::thing::bounds::{BoundsRefinement, BoundsRefinementError};
+    use crate::thing::one_of::{OneOfRefinement, OneOfRefinementError};
+    use crate::thing::primitives::{PrimitiveRefinement, RefinementCastError, TypeRefinementError};
+    use crate::thing::registry::RefinementRegistry;
+    use crate::thing::tests_utils::{
+        subset, unrelated, TestAssertionResult, TestConversionResult, TestRefinement,
+    };
+    use crate::thing::Thing;
+
+    fn directions() -> OneOfRefinement {
+        OneOfRefinement::new(vec!["north".into(), "south".into(), 3.into()]).unwrap()
+    }
+
+    #[test]
+    fn members() {
+        directions().check("north").success().assert();
+        directions().check(3.0).success().assert();
+        directions()
+            .check("east")
+            .error::<OneOfRefinementError>()
+            .assert();
+        directions()
+            .check(Value::Null)
+            .error::<OneOfRefinementError>()
+            .assert();
+    }
+
+    #[test]
+    fn non_literals() {
+        assert!(OneOfRefinement::new(vec!["north".into(), json!({ "north": 1 })]).is_err());
+    }
+
+    #[test]
+    fn deserialize_non_literals() {
+        assert!(RefinementRegistry::default()
+            .thing_from_value(json!([{ "type": "OneOf", "values": ["north", null] }]))
+            .is_err());
+    }
+
+    #[test]
+    fn cast_to_one_of() {
+        let narrow = OneOfRefinement::new(vec!["south".into(), 3.0.into()]).unwrap();
+        subset(&narrow, &directions()).assert();
+        Thing::from(directions())
+            .try_assign_to(&Thing::from(narrow))
+            .error::<RefinementCastError<OneOfRefinement>>()
+            .assert();
+    }
+
+    #[test]
+    fn cast_to_other_refinements() {
+        let words = OneOfRefinement::new(vec!["north".into(), "south".into()]).unwrap();
+        subset(&words, &PrimitiveRefinement::String).assert();
+        Thing::from(directions())
+            .try_assign_to(&Thing::from(PrimitiveRefinement::String))
+            .error::<TypeRefinementError>()
+            .assert();
+
+        let short = BoundsRefinement {
+            min: Bound::Unbounded,
+            max: Bound::Included(4.0),
+            coerce: false,
+        };
+        Thing::from(words)
+            .try_assign_to(&Thing::from(short))
+            .error::<BoundsRefinementError>()
+            .assert();
+        unrelated(&PrimitiveRefinement::String, &directions()).assert();
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt::{Display, Formatter};
+
+use as_any::Downcast;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::thing::primitives::{inapplicable, RefinementCastError};
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Refinement, Relation};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum StringCoercion {
+    Trim,
+    Lowercase,
+    Uppercase,
+}
+
+impl StringCoercion {
+    fn coerce(&self, str: &str) -> String {
+        match self {
+            StringCoercion::Trim => str.trim().to_string(),
+            StringCoercion::Lowercase => str.to_lowercase(),
+            StringCoercion::Uppercase => str.to_uppercase(),
+        }
+    }
+}
+
+/// Strings that contain a match of the regular expression, like `pattern` of JSON Schema.
+///
+/// Coercions are applied in order before matching, and patterns that should match the whole
+/// string need to be anchored with `^` and `$`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternRefinement {
+    #[serde(with = "serde_regex")]
+    pub pattern: Regex,
+    #[serde(default)]
+    pub coerce: Vec<StringCoercion>,
+}
+
+impl PatternRefinement {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(PatternRefinement {
+            pattern: Regex::new(pattern)?,
+            coerce: vec![],
+        })
+    }
+
+    fn coerced(&self, str: &str) -> String {
+        self.coerce
+            .iter()
+            .fold(str.to_string(), |str, coercion| coercion.coerce(&str))
+    }
+}
+
+impl Display for PatternRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pattern /{}/", self.pattern)
+    }
+}
+
+impl Refinement for PatternRefinement {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        let Value::String(str) = value else {
+            return Err(inapplicable(self, value));
+        };
+        let coerced = self.coerced(str);
+        if !self.pattern.is_match(&coerced) {
+            return Err(PatternRefinementError {
+                value: coerced,
+                pattern: self.pattern.to_string(),
+            }
+            .into());
+        }
+        if coerced != *str {
+            let from = std::mem::replace(str, coerced);
+            let err = StringCoercionWarning {
+                from: from.clone(),
+                to: str.clone(),
+            };
+            warnings.push(ValidationIssue::warning(
+                self,
+                Value::String(from),
+                Some(value.clone()),
+                err.into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        let Some(other) = other.downcast_ref::<PatternRefinement>() else {
+            return Relation::Unrelated;
+        };
+        // Inclusion of arbitrary regular expressions can't be checked cheaply, so only the same
+        // patterns are related. Every coercion is idempotent, so repeating them changes nothing
+        if self.pattern.as_str() == other.pattern.as_str()
+            && (other.coerce.is_empty() || other.coerce == self.coerce)
+        {
+            return Relation::Subset;
+        }
+        Relation::Conflict(
+            RefinementCastError {
+                got: Clone::clone(self),
+                expected: Clone::clone(other),
+            }
+            .into(),
+        )
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for PatternRefinement {
+    const TAG: &'static str = "Pattern";
+}
+
+#[derive(Error, Debug)]
+#[error("`{value}` does not match /{pattern}/")]
+pub struct PatternRefinementError {
+    value: String,
+    pattern: String,
+}
+
+#[derive(Error, Debug)]
+#[error("`{from}` was coerced to `{to}`")]
+pub struct StringCoercionWarning {
+    from: String,
+    to: String,
+}
+
+mod serde_regex {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+        Regex::new(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use test_strategy::proptest;
+
+    use crate::thing::one_of::OneOfRefinement;
+    use crate::thing::pattern::{
+        PatternRefinement, PatternRefinementError, StringCoercion, StringCoercionWarning,
+    };
+    use crate::thing::primitives::RefinementCastError;
+    use crate::thing::tests_utils::{
+        prop_unwrap, subset, TestAssertionResult, TestConversionResult,
+        TestConversionResultWithWarnings, TestRefinement,
+    };
+    use crate::thing::Thing;
+
+    fn identifier() -> PatternRefinement {
+        PatternRefinement::new("^[a-z_][a-z0-9_]*$").unwrap()
+    }
+
+    #[test]
+    fn matching() {
+        identifier().check("snake_case").success().assert();
+        identifier()
+            .check("CamelCase")
+            .error::<PatternRefinementError>()
+            .assert();
+        identifier()
+            .check(" padded")
+            .error::<PatternRefinementError>()
+            .assert();
+        assert!(identifier().check(12).is_err());
+    }
+
+    #[proptest]
+    fn coercion(#[strategy("[A-Za-z]{1,8}")] word: String, #[strategy(" {0,2}")] pad: String) {
+        let mut refinement = identifier();
+        refinement.coerce = vec![StringCoercion::Trim, StringCoercion::Lowercase];
+        let padded = format!("{pad}{word}{pad}");
+        let (value, warnings) = prop_unwrap!(refinement.check(padded.clone()).success());
+        let expected = word.to_lowercase();
+        proptest::prop_assert_eq!(value.as_str(), Some(expected.as_str()));
+        proptest::prop_assert_eq!(warnings.len(), usize::from(value != padded.as_str()));
+        if let Some(warning) = warnings.into_iter().next() {
+            prop_unwrap!(warning.error.downcast::<StringCoercionWarning>());
+        }
+    }
+
+    #[test]
+    fn unchanged_without_warnings() {
+        let mut refinement = identifier();
+        refinement.coerce = vec![StringCoercion::Trim];
+        refinement.check("name").with_warnings(0).assert();
+    }
+
+    #[test]
+    fn cast() {
+        let mut coercing = identifier();
+        coercing.coerce = vec![StringCoercion::Lowercase];
+
+        Thing::from(identifier())
+            .try_assign_to(&Thing::from(identifier()))
+            .success()
+            .assert();
+        Thing::from(coercing.clone())
+            .try_assign_to(&Thing::from(identifier()))
+            .success()
+            .assert();
+        Thing::from(identifier())
+            .try_assign_to(&Thing::from(coercing))
+            .error::<RefinementCastError<PatternRefinement>>()
+            .assert();
+        Thing::from(identifier())
+            .try_assign_to(&Thing::from(PatternRefinement::new("^[a-z]+$").unwrap()))
+            .error::<RefinementCastError<PatternRefinement>>()
+            .assert();
+    }
+
+    #[test]
+    fn one_of_subset() {
+        let members = OneOfRefinement::new(vec!["north".into(), "south".into()]).unwrap();
+        subset(&members, &identifier()).assert();
+
+        let capitalized = OneOfRefinement::new(vec!["north".into(), "South".into()]).unwrap();
+        Thing::from(capitalized)
+            .try_assign_to(&Thing::from(identifier()))
+            .error::<PatternRefinementError>()
+            .assert();
+    }
+}
@@ -1,12 +1,15 @@
 ﻿use std::fmt::{Display, Formatter};
 
 use as_any::Downcast;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use thiserror::Error;
 
-use crate::thing::{Refinement, Relation};
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Intersection, Refinement, Relation};
 
 #[derive(Error, Debug)]
 pub struct TypeRefinementError {
@@ -78,8 +81,9 @@ pub struct RefinementCastError<T: Display + Refinement> {
     pub got: T,
 }
 
-#[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(test, derive(test_strategy::Arbitrary))]
+#[serde(tag = "primitive")]
 pub enum PrimitiveRefinement {
     Number,
     String,
@@ -104,7 +108,7 @@ impl Display for PrimitiveRefinement {
 }
 
 impl Refinement for PrimitiveRefinement {
-    fn apply(&self, value: &mut Value, _warnings: &mut Vec<anyhow::Error>) -> anyhow::Result<()> {
+    fn apply(&self, value: &mut Value, _warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
         let valid = match self {
             PrimitiveRefinement::Number => value.is_number(),
             PrimitiveRefinement::String => value.is_string(),
@@ -144,6 +148,22 @@ impl Refinement for PrimitiveRefinement {
             .into(),
         )
     }
+
+    fn intersect(&self, other: &dyn Refinement) -> Intersection {
+        match other.downcast_ref::<PrimitiveRefinement>() {
+            None => Intersection::Unrelated,
+            Some(other) if other == self => Intersection::Merged(Box::new(*self)),
+            Some(_) => Intersection::Empty,
+        }
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for PrimitiveRefinement {
+    const TAG: &'static str = "Primitive";
 }
 
 pub fn validate_number(val: &Value) -> anyhow::Result<f64> {
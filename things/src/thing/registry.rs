@@ -0,0 +1,408 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use rustc_hash::FxHashMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::thing::array::{ArrayOfRefinement, TupleRefinement, UniqueItemsRefinement};
+use crate::thing::bounds::BoundsRefinement;
+use crate::thing::multiple_of::MultipleOfRefinement;
+use crate::thing::object::ObjectRefinement;
+use crate::thing::one_of::OneOfRefinement;
+use crate::thing::pattern::PatternRefinement;
+use crate::thing::primitives::PrimitiveRefinement;
+use crate::thing::union::{AnyOfRefinement, TaggedUnionRefinement};
+use crate::thing::{Refinement, Thing};
+
+/// Key holding the tag in serialized refinements
+pub static TAG_KEY: &str = "type";
+
+/// Refinement that can be saved as a part of a thing and loaded back through the registry
+pub trait SerializableRefinement: Refinement + Serialize + DeserializeOwned {
+    /// Name of the refinement in serialized things, unique within a registry
+    const TAG: &'static str;
+}
+
+/// Serializes the refinement as a JSON object, with its tag stored under [`TAG_KEY`]
+pub fn serialize_tagged<R: SerializableRefinement>(refinement: &R) -> Result<Value> {
+    let mut value = serde_json::to_value(refinement)?;
+    let Value::Object(fields) = &mut value else {
+        return Err(anyhow!(
+            "Refinement `{}` is not serialized as an object",
+            R::TAG
+        ));
+    };
+    fields.insert(TAG_KEY.to_string(), Value::from(R::TAG));
+    Ok(value)
+}
+
+type DeserializeFn = fn(Value) -> Result<Box<dyn Refinement>>;
+
+thread_local! {
+    /// Registry that is deserializing a refinement, which things nested in it are read with
+    static CURRENT: RefCell<Option<RefinementRegistry>> = const { RefCell::new(None) };
+}
+
+/// Deserializes refinements by their tags.
+///
+/// Default registry knows every refinement of this crate, refinements defined elsewhere have
+/// to be registered before loading things that use them.
+#[derive(Clone)]
+pub struct RefinementRegistry {
+    deserializers: FxHashMap<&'static str, DeserializeFn>,
+}
+
+impl Default for RefinementRegistry {
+    fn default() -> Self {
+        let mut registry = RefinementRegistry::empty();
+        registry.register::<PrimitiveRefinement>();
+        registry.register::<BoundsRefinement>();
+        registry.register::<MultipleOfRefinement>();
+        registry.register::<PatternRefinement>();
+        registry.register::<OneOfRefinement>();
+        registry.register::<ObjectRefinement>();
+        registry.register::<ArrayOfRefinement>();
+        registry.register::<TupleRefinement>();
+        registry.register::<UniqueItemsRefinement>();
+        registry.register::<AnyOfRefinement>();
+        registry.register::<TaggedUnionRefinement>();
+        registry
+    }
+}
+
+impl RefinementRegistry {
+    pub fn empty() -> Self {
+        RefinementRegistry {
+            deserializers: Default::default(),
+        }
+    }
+
+    /// Registers the refinement under its tag, replacing the one registered before
+    pub fn register<R: SerializableRefinement>(&mut self) {
+        self.deserializers.insert(R::TAG, |value| {
+            Ok(Box::new(serde_json::from_value::<R>(value)?))
+        });
+    }
+
+    pub fn refinement_from_value(&self, mut value: Value) -> Result<Box<dyn Refinement>> {
+        let Value::Object(fields) = &mut value else {
+            return Err(anyhow!("Refinement must be an object, got {}", value));
+        };
+        let tag = match fields.remove(TAG_KEY) {
+            Some(Value::String(tag)) => tag,
+            Some(tag) => return Err(anyhow!("Refinement tag must be a string, got {}", tag)),
+            None => return Err(anyhow!("Refinement has no `{}` field", TAG_KEY)),
+        };
+        let deserialize = self
+            .deserializers
+            .get(tag.as_str())
+            .ok_or_else(|| anyhow!("Unknown refinement `{}`", tag))?;
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        let result = deserialize(value);
+        CURRENT.with(|current| current.replace(previous));
+        result.with_context(|| format!("Invalid refinement `{}`", tag))
+    }
+
+    /// Reads a thing from an array of tagged refinements
+    pub fn thing_from_value(&self, value: Value) -> Result<Thing> {
+        let Value::Array(items) = value else {
+            return Err(anyhow!(
+                "Thing must be an array of refinements, got {}",
+                value
+            ));
+        };
+        let refinements = items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| {
+                self.refinement_from_value(item)
+                    .with_context(|| format!("Invalid refinement at index {}", i))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Thing(refinements))
+    }
+
+    /// Loads a thing from a JSON file, conventionally named `<name>.thing.json`
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Thing> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let value = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        self.thing_from_value(value)
+            .with_context(|| format!("Failed to load {}", path.display()))
+    }
+}
+
+impl Thing {
+    pub fn to_value(&self) -> Result<Value> {
+        self.0
+            .iter()
+            .map(|refinement| refinement.serialize())
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array)
+    }
+
+    /// Saves the thing as a JSON file, which [`RefinementRegistry::load`] reads back
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(&self.to_value()?)?;
+        fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl Serialize for Thing {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_value()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+/// Things nested in refinements are read with the registry that reads the refinement, and
+/// other ones only with the default registry
+impl<'de> Deserialize<'de> for Thing {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let registry = CURRENT.with(|current| current.borrow().clone());
+        registry
+            .unwrap_or_default()
+            .thing_from_value(Value::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::ops::Bound;
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+    use test_strategy::proptest;
+
+    use crate::thing::bounds::BoundsRefinement;
+    use crate::thing::multiple_of::{MultipleOfRefinement, INTEGER};
+    use crate::thing::primitives::PrimitiveRefinement;
+    use crate::thing::registry::{RefinementRegistry, SerializableRefinement};
+    use crate::thing::tests_utils::{do_assert, prop_unwrap, TestAssertionResult};
+    use crate::thing::validation::ValidationIssue;
+    use crate::thing::{Refinement, Thing, ThingLike};
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("{}.thing.json", name))
+    }
+
+    fn score() -> Thing {
+        let bounds = BoundsRefinement {
+            min: Bound::Included(0.0),
+            max: Bound::Excluded(100.0),
+            coerce: true,
+        };
+        let mut thing = Thing::from(PrimitiveRefinement::Number);
+        thing.0.push(Box::new(bounds));
+        thing.0.push(Box::new(INTEGER));
+        thing
+    }
+
+    fn price() -> Thing {
+        let bounds = BoundsRefinement {
+            min: Bound::Excluded(0.0),
+            max: Bound::Unbounded,
+            coerce: false,
+        };
+        let multiple_of = MultipleOfRefinement {
+            factor: 0.25,
+            coerce: false,
+        };
+        let mut thing = Thing::from(PrimitiveRefinement::Number);
+        thing.0.push(Box::new(bounds));
+        thing.0.push(Box::new(multiple_of));
+        thing
+    }
+
+    fn name() -> Thing {
+        let bounds = BoundsRefinement {
+            min: Bound::Included(1.0),
+            max: Bound::Included(16.0),
+            coerce: false,
+        };
+        let mut thing = Thing::from(PrimitiveRefinement::String);
+        thing.0.push(Box::new(bounds));
+        thing
+    }
+
+    /// Both things produce the same value, warnings and errors
+    fn same_validation(a: &Thing, b: &Thing, value: Value) -> anyhow::Result<()> {
+        let describe = |thing: &Thing| {
+            let mut value = value.clone();
+            match thing.apply(&mut value) {
+                Ok(warnings) => {
+                    let warnings: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+                    format!("{} with warnings {:?}", value, warnings)
+                }
+                Err(err) => format!("Error: {}", err),
+            }
+        };
+        let (a, b) = (describe(a), describe(b));
+        do_assert(
+            a == b,
+            &format!("Validation differs for {}: {} and {}", value, a, b),
+        )
+    }
+
+    #[proptest]
+    fn loaded_validates_like_built(n: f64, s: String) {
+        let registry = RefinementRegistry::default();
+        for (name, thing) in [("score", score()), ("price", price()), ("name", name())] {
+            let loaded = prop_unwrap!(registry.load(fixture(name)));
+            proptest::prop_assert_eq!(
+                prop_unwrap!(loaded.to_value()),
+                prop_unwrap!(thing.to_value())
+            );
+            prop_unwrap!(same_validation(&loaded, &thing, Value::from(n)));
+            prop_unwrap!(same_validation(&loaded, &thing, Value::from(s.clone())));
+        }
+    }
+
+    #[proptest]
+    fn round_trip(primitive: PrimitiveRefinement, factor: i32, min: i32, coerce: bool) {
+        let mut thing = Thing::from(primitive);
+        thing.0.push(Box::new(BoundsRefinement {
+            min: Bound::Included(min as f64),
+            max: Bound::Unbounded,
+            coerce,
+        }));
+        thing.0.push(Box::new(MultipleOfRefinement {
+            factor: factor as f64 / 100.0,
+            coerce,
+        }));
+
+        let value = prop_unwrap!(thing.to_value());
+        let loaded = prop_unwrap!(RefinementRegistry::default().thing_from_value(value.clone()));
+        proptest::prop_assert_eq!(prop_unwrap!(loaded.to_value()), value);
+        for n in [min as f64 - 0.5, min as f64, factor as f64 / 100.0 * 3.0] {
+            prop_unwrap!(same_validation(&loaded, &thing, Value::from(n)));
+        }
+    }
+
+    #[test]
+    fn tagged_representation() {
+        assert_eq!(
+            Thing::from(PrimitiveRefinement::Bool).to_value().assert(),
+            json!([{ "type": "Primitive", "primitive": "Bool" }])
+        );
+        assert_eq!(
+            Thing::from(INTEGER).to_value().assert(),
+            json!([{ "type": "MultipleOf", "factor": 1.0, "coerce": true }])
+        );
+    }
+
+    #[test]
+    fn omitted_fields() {
+        let thing: Thing = serde_json::from_value(json!([
+            { "type": "Bounds", "max": { "Included": 3 } },
+            { "type": "MultipleOf", "factor": 2 }
+        ]))
+        .unwrap();
+        let bounds = thing.get_refinement::<BoundsRefinement>().unwrap();
+        assert_eq!(bounds.min, Bound::Unbounded);
+        assert!(!bounds.coerce);
+        assert!(
+            !thing
+                .get_refinement::<MultipleOfRefinement>()
+                .unwrap()
+                .coerce
+        );
+    }
+
+    #[test]
+    fn invalid_refinements() {
+        let registry = RefinementRegistry::default();
+        let error = |value: Value| format!("{:#}", registry.thing_from_value(value).unwrap_err());
+
+        assert_eq!(
+            error(json!([{ "type": "Primitive", "primitive": "Number" }, { "type": "Regex" }])),
+            "Invalid refinement at index 1: Unknown refinement `Regex`"
+        );
+        assert_eq!(
+            error(json!([{ "primitive": "Number" }])),
+            "Invalid refinement at index 0: Refinement has no `type` field"
+        );
+        assert!(error(json!([{ "type": "MultipleOf", "coerce": true }]))
+            .starts_with("Invalid refinement at index 0: Invalid refinement `MultipleOf`"));
+        assert!(RefinementRegistry::empty()
+            .thing_from_value(json!([{ "type": "Primitive", "primitive": "Null" }]))
+            .is_err());
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct NonEmptyRefinement {}
+
+    impl std::fmt::Display for NonEmptyRefinement {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "NonEmpty")
+        }
+    }
+
+    impl Refinement for NonEmptyRefinement {
+        fn apply(
+            &self,
+            value: &mut Value,
+            _warnings: &mut Vec<ValidationIssue>,
+        ) -> anyhow::Result<()> {
+            if value.as_str() == Some("") {
+                return Err(anyhow::anyhow!("String is empty"));
+            }
+            Ok(())
+        }
+
+        fn strict(&self) -> Box<dyn Refinement> {
+            Box::new(NonEmptyRefinement {})
+        }
+
+        fn clone(&self) -> Box<dyn Refinement> {
+            Box::new(NonEmptyRefinement {})
+        }
+
+        fn is_subset_of(&self, _other: &dyn Refinement) -> crate::thing::Relation {
+            crate::thing::Relation::Unrelated
+        }
+
+        fn serialize(&self) -> anyhow::Result<Value> {
+            super::serialize_tagged(self)
+        }
+    }
+
+    impl SerializableRefinement for NonEmptyRefinement {
+        const TAG: &'static str = "NonEmpty";
+    }
+
+    #[test]
+    fn custom_refinement() {
+        let value = json!([{ "type": "NonEmpty" }]);
+        assert!(RefinementRegistry::default()
+            .thing_from_value(value.clone())
+            .is_err());
+
+        let mut registry = RefinementRegistry::default();
+        registry.register::<NonEmptyRefinement>();
+        let thing = registry.thing_from_value(value.clone()).assert();
+        assert_eq!(thing.to_value().assert(), value);
+        assert!(thing.apply(&mut Value::from("")).is_err());
+        assert!(thing.apply(&mut Value::from("name")).is_ok());
+
+        // Things nested in other refinements are read with the same registry
+        let nested = json!([{
+            "type": "Object",
+            "fields": { "name": { "thing": value, "required": true } }
+        }]);
+        let thing = registry.thing_from_value(nested).assert();
+        assert!(thing.apply(&mut json!({ "name": "" })).is_err());
+    }
+}
@@ -0,0 +1,440 @@
+use std::ops::{Bound, RangeInclusive};
+
+use as_any::Downcast;
+use proptest::prelude::*;
+use proptest::sample::select;
+use proptest::strategy::Union;
+use proptest::test_runner::{Config, TestRunner};
+use serde_json::{Map, Value};
+
+use crate::thing::array::{ArrayOfRefinement, TupleRefinement, UniqueItemsRefinement};
+use crate::thing::bounds::BoundsRefinement;
+use crate::thing::multiple_of::MultipleOfRefinement;
+use crate::thing::object::ObjectRefinement;
+use crate::thing::one_of::OneOfRefinement;
+use crate::thing::pattern::PatternRefinement;
+use crate::thing::primitives::PrimitiveRefinement;
+use crate::thing::union::{AnyOfRefinement, TaggedUnionRefinement};
+use crate::thing::{Refinement, Thing, ThingLike};
+
+/// Range of generated numbers that are not bounded on some side
+const NUMBER_RANGE: f64 = 1e6;
+/// Longest generated string or array that is not bounded from above
+const MAX_LENGTH: usize = 8;
+
+/// Whether the value is already refined by the thing, so applying it neither fails nor
+/// changes the value
+pub fn satisfies(thing: &Thing, value: &Value) -> bool {
+    let mut refined = value.clone();
+    matches!(thing.apply(&mut refined), Ok(warnings) if warnings.is_empty()) && refined == *value
+}
+
+/// Values that satisfy the thing.
+///
+/// Values are generated from the known refinements and then filtered by the whole thing, so
+/// things with refinements that are hard to hit, like custom ones, may exhaust the rejects
+/// of the test runner. Things that allow no values, like an empty `OneOf`, always do
+pub fn values(thing: &Thing) -> BoxedStrategy<Value> {
+    let filter = thing.clone();
+    candidates(thing)
+        .prop_filter("Value must satisfy the thing", move |value| {
+            satisfies(&filter, value)
+        })
+        .boxed()
+}
+
+/// Values that violate exactly one refinement of the thing, along with its index.
+///
+/// Some refinements can't be violated alone, like `Number` next to `MultipleOf` that only
+/// applies to numbers, or repeated refinements that should be merged with
+/// [`Thing::normalize`] first. Refinements without a violating value among
+/// [`VIOLATION_PROBES`] candidates are skipped
+///
+/// # Panics
+/// When none of the refinements can be violated alone
+pub fn violations(thing: &Thing) -> BoxedStrategy<(usize, Value)> {
+    let strategies: Vec<_> = (0..thing.0.len())
+        .filter_map(|i| violation(thing, i))
+        .collect();
+    assert!(
+        !strategies.is_empty(),
+        "No refinement of `{}` can be violated alone",
+        thing
+    );
+    Union::new(strategies).boxed()
+}
+
+/// Number of candidates checked for violating values of each refinement
+pub const VIOLATION_PROBES: u32 = 4096;
+
+fn violation(thing: &Thing, index: usize) -> Option<BoxedStrategy<(usize, Value)>> {
+    let violated = Thing(vec![(*thing.0[index]).clone()]);
+    let others: Vec<_> = (0..thing.0.len())
+        .filter(|i| *i != index)
+        .map(|i| Thing(vec![(*thing.0[i]).clone()]))
+        .collect();
+    // Without the violated refinement, values may not even be of the thing's kind, so the
+    // rest of refinements are checked one at a time
+    let rest = Thing(others.iter().map(|other| (*other.0[0]).clone()).collect());
+    let strategy = prop_oneof![candidates(&rest), any_value()]
+        .prop_filter("Value must violate one refinement", move |value| {
+            !satisfies(&violated, value) && others.iter().all(|other| satisfies(other, value))
+        })
+        .prop_map(move |value| (index, value))
+        .boxed();
+
+    let mut runner = TestRunner::new(Config {
+        max_local_rejects: VIOLATION_PROBES,
+        ..Config::default()
+    });
+    strategy.new_tree(&mut runner).ok().map(|_| strategy)
+}
+
+/// Any JSON value
+pub fn any_value() -> BoxedStrategy<Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i32>().prop_map(Value::from),
+        (-NUMBER_RANGE..NUMBER_RANGE).prop_map(Value::from),
+        "[a-z]{0,8}".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(2, 16, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+            prop::collection::btree_map("[a-z]{1,4}", inner, 0..4)
+                .prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+    .boxed()
+}
+
+/// Values that mostly satisfy the thing, built from refinements that describe its shape
+fn candidates(thing: &Thing) -> BoxedStrategy<Value> {
+    if let Some(one_of) = thing.get_refinement::<OneOfRefinement>() {
+        // An empty `OneOf` allows nothing, so it's left to reject whatever the rest generates
+        if !one_of.values.is_empty() {
+            return select(one_of.values.clone()).boxed();
+        }
+    }
+    if let Some(union) = thing.get_refinement::<AnyOfRefinement>() {
+        if !union.variants.is_empty() {
+            return Union::new(union.variants.iter().map(values)).boxed();
+        }
+    }
+    if let Some(union) = thing.get_refinement::<TaggedUnionRefinement>() {
+        if !union.variants.is_empty() {
+            return Union::new(union.variants.iter().map(|(tag, variant)| {
+                let (tag_field, tag) = (union.tag_field.clone(), Value::from(tag.as_str()));
+                candidates(variant)
+                    .prop_map(move |mut value| {
+                        if let Value::Object(object) = &mut value {
+                            object.insert(tag_field.clone(), tag.clone());
+                        }
+                        value
+                    })
+                    .boxed()
+            }))
+            .boxed();
+        }
+    }
+
+    let bounds = thing.get_refinement::<BoundsRefinement>();
+    match primitive(thing) {
+        Some(PrimitiveRefinement::Number) => {
+            number(bounds, thing.get_refinement::<MultipleOfRefinement>())
+        }
+        Some(PrimitiveRefinement::String) => string(bounds, thing.get_refinement()),
+        Some(PrimitiveRefinement::Bool) => any::<bool>().prop_map(Value::from).boxed(),
+        Some(PrimitiveRefinement::Null) => Just(Value::Null).boxed(),
+        Some(PrimitiveRefinement::Array) => array(thing, bounds),
+        Some(PrimitiveRefinement::Object) => object(thing.get_refinement()),
+        None => any_value(),
+    }
+}
+
+/// Primitive of the thing, or the one implied by its refinements
+fn primitive(thing: &Thing) -> Option<PrimitiveRefinement> {
+    if let Some(primitive) = thing.get_refinement::<PrimitiveRefinement>() {
+        return Some(*primitive);
+    }
+    let implies = |refinement: &dyn Refinement| {
+        if refinement.is::<MultipleOfRefinement>() || refinement.is::<BoundsRefinement>() {
+            Some(PrimitiveRefinement::Number)
+        } else if refinement.is::<PatternRefinement>() {
+            Some(PrimitiveRefinement::String)
+        } else if refinement.is::<ArrayOfRefinement>()
+            || refinement.is::<TupleRefinement>()
+            || refinement.is::<UniqueItemsRefinement>()
+        {
+            Some(PrimitiveRefinement::Array)
+        } else if refinement.is::<ObjectRefinement>() {
+            Some(PrimitiveRefinement::Object)
+        } else {
+            None
+        }
+    };
+    // Bounds apply to strings and arrays too, so other refinements decide first
+    let mut implied: Vec<_> = thing.0.iter().filter_map(|r| implies(&**r)).collect();
+    implied.sort_by_key(|primitive| *primitive == PrimitiveRefinement::Number);
+    implied.first().copied()
+}
+
+fn bound(bound: &Bound<f64>) -> Option<f64> {
+    match bound {
+        Bound::Included(x) | Bound::Excluded(x) => Some(*x),
+        Bound::Unbounded => None,
+    }
+}
+
+fn number(
+    bounds: Option<&BoundsRefinement>,
+    multiple_of: Option<&MultipleOfRefinement>,
+) -> BoxedStrategy<Value> {
+    let min = bounds.and_then(|bounds| bound(&bounds.min));
+    let max = bounds.and_then(|bounds| bound(&bounds.max));
+    let (min, max) = match (min, max) {
+        (Some(min), Some(max)) => (min, max),
+        (Some(min), None) => (min, min + NUMBER_RANGE),
+        (None, Some(max)) => (max - NUMBER_RANGE, max),
+        (None, None) => (-NUMBER_RANGE, NUMBER_RANGE),
+    };
+    if min > max {
+        return any::<f64>().prop_map(Value::from).boxed();
+    }
+    match multiple_of.map(|multiple_of| multiple_of.factor.abs()) {
+        Some(factor) if factor > 0.0 => {
+            let (first, last) = ((min / factor).ceil() as i64, (max / factor).floor() as i64);
+            (first..=last.max(first))
+                .prop_map(move |k| {
+                    let value = k as f64 * factor;
+                    if factor.fract() == 0.0 {
+                        Value::from(value as i64)
+                    } else {
+                        Value::from(value)
+                    }
+                })
+                .boxed()
+        }
+        _ => (min..=max).prop_map(Value::from).boxed(),
+    }
+}
+
+/// Lengths within the bounds, or up to [`MAX_LENGTH`] above the lower one
+fn lengths(bounds: Option<&BoundsRefinement>) -> RangeInclusive<usize> {
+    let min = bounds
+        .and_then(|bounds| bound(&bounds.min))
+        .map_or(0, |min| min.max(0.0).ceil() as usize);
+    let max = bounds
+        .and_then(|bounds| bound(&bounds.max))
+        .map_or(min + MAX_LENGTH, |max| max.max(0.0) as usize);
+    min..=max.max(min)
+}
+
+fn string(
+    bounds: Option<&BoundsRefinement>,
+    pattern: Option<&PatternRefinement>,
+) -> BoxedStrategy<Value> {
+    if let Some(pattern) = pattern {
+        // Generated strings match as a whole, so anchors change nothing
+        let pattern = pattern.pattern.as_str();
+        let unanchored = pattern.strip_prefix('^').unwrap_or(pattern);
+        let unanchored = match unanchored.strip_suffix('$') {
+            Some(rest) if !rest.ends_with('\\') => rest,
+            _ => unanchored,
+        };
+        if let Ok(strings) = proptest::string::string_regex(unanchored) {
+            return strings.prop_map(Value::from).boxed();
+        }
+    }
+    // Lengths are counted in bytes, so only ASCII characters are generated
+    let lengths = lengths(bounds);
+    prop::collection::vec(proptest::char::range(' ', '~'), lengths)
+        .prop_map(|chars| Value::from(chars.into_iter().collect::<String>()))
+        .boxed()
+}
+
+fn array(thing: &Thing, bounds: Option<&BoundsRefinement>) -> BoxedStrategy<Value> {
+    if let Some(tuple) = thing.get_refinement::<TupleRefinement>() {
+        let items: Vec<_> = tuple.items.iter().map(values).collect();
+        return items.prop_map(Value::from).boxed();
+    }
+    let items = match thing.get_refinement::<ArrayOfRefinement>() {
+        Some(array) => values(&array.items),
+        None => any_value(),
+    };
+    prop::collection::vec(items, lengths(bounds))
+        .prop_map(Value::from)
+        .boxed()
+}
+
+fn object(object: Option<&ObjectRefinement>) -> BoxedStrategy<Value> {
+    let Some(object) = object else {
+        return prop::collection::btree_map("[a-z]{1,4}", any_value(), 0..4)
+            .prop_map(|map| Value::Object(map.into_iter().collect()))
+            .boxed();
+    };
+    // Missing fields with defaults are inserted by the refinement, so they are always present
+    let fields: Vec<_> = object
+        .fields
+        .iter()
+        .map(|(name, field)| {
+            let name = name.clone();
+            let values = values(&field.thing).prop_map(move |value| Some((name.clone(), value)));
+            if field.required || field.default.is_some() {
+                values.boxed()
+            } else {
+                prop_oneof![Just(None), values].boxed()
+            }
+        })
+        .collect();
+    fields
+        .prop_map(|fields| Value::Object(fields.into_iter().flatten().collect::<Map<_, _>>()))
+        .boxed()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    use proptest::prelude::*;
+    use proptest::strategy::ValueTree;
+    use proptest::test_runner::TestRunner;
+    use serde_json::{json, Value};
+    use test_strategy::proptest;
+
+    use crate::thing::array::{ArrayOfRefinement, TupleRefinement, UniqueItemsRefinement};
+    use crate::thing::bounds::BoundsRefinement;
+    use crate::thing::multiple_of::{MultipleOfRefinement, STRICT_INTEGER};
+    use crate::thing::object::{AdditionalProperties, Field, ObjectRefinement};
+    use crate::thing::one_of::OneOfRefinement;
+    use crate::thing::pattern::PatternRefinement;
+    use crate::thing::primitives::PrimitiveRefinement;
+    use crate::thing::strategy::{satisfies, values, violations};
+    use crate::thing::union::{AnyOfRefinement, TaggedUnionRefinement};
+    use crate::thing::Thing;
+
+    fn bounds(min: f64, max: f64) -> BoundsRefinement {
+        BoundsRefinement {
+            min: Bound::Included(min),
+            max: Bound::Excluded(max),
+            coerce: false,
+        }
+    }
+
+    fn score() -> Thing {
+        Thing(vec![
+            Box::new(PrimitiveRefinement::Number),
+            Box::new(bounds(0.0, 100.0)),
+            Box::new(MultipleOfRefinement {
+                factor: 5.0,
+                coerce: false,
+            }),
+        ])
+    }
+
+    fn name() -> Thing {
+        Thing(vec![
+            Box::new(PrimitiveRefinement::String),
+            Box::new(bounds(1.0, 12.0)),
+            Box::new(PatternRefinement::new("^[a-z]+$").unwrap()),
+        ])
+    }
+
+    fn player() -> Thing {
+        let tags = Thing(vec![
+            Box::new(ArrayOfRefinement {
+                items: Thing::from(OneOfRefinement::new(vec!["new".into(), "pro".into()]).unwrap()),
+            }),
+            Box::new(UniqueItemsRefinement { coerce: false }),
+        ]);
+        let mut object = ObjectRefinement::new([
+            ("name", Field::required(name())),
+            ("score", Field::optional(score()).with_default(0)),
+            ("tags", Field::optional(tags)),
+            (
+                "position",
+                Field::optional(TupleRefinement {
+                    items: vec![Thing::from(STRICT_INTEGER), Thing::from(STRICT_INTEGER)],
+                }),
+            ),
+        ]);
+        object.additional_properties = AdditionalProperties::Deny;
+        Thing(vec![
+            Box::new(PrimitiveRefinement::Object),
+            Box::new(object),
+        ])
+    }
+
+    fn shape() -> Thing {
+        let circle = ObjectRefinement::new([("radius", Field::required(score()))]);
+        let point = ObjectRefinement::new([("x", Field::required(STRICT_INTEGER))]);
+        Thing::from(AnyOfRefinement {
+            variants: vec![
+                Thing::from(TaggedUnionRefinement {
+                    tag_field: "kind".to_string(),
+                    variants: BTreeMap::from([
+                        ("circle".to_string(), Thing::from(circle)),
+                        ("point".to_string(), Thing::from(point)),
+                    ]),
+                }),
+                Thing::from(PrimitiveRefinement::Null),
+            ],
+        })
+    }
+
+    fn things() -> Vec<Thing> {
+        vec![score(), name(), player(), shape()]
+    }
+
+    #[test]
+    fn satisfying() {
+        assert!(satisfies(&score(), &json!(15)));
+        assert!(!satisfies(&score(), &json!(16)));
+        // Inserting a default changes the value
+        assert!(!satisfies(&player(), &json!({ "name": "ann" })));
+        assert!(satisfies(&player(), &json!({ "name": "ann", "score": 0 })));
+    }
+
+    #[test]
+    fn empty_one_of() {
+        let nothing = Thing::from(OneOfRefinement::new(vec![]).unwrap());
+        let mut runner = TestRunner::default();
+        assert!(values(&nothing).new_tree(&mut runner).is_err());
+        let tree = violations(&nothing).new_tree(&mut runner).unwrap();
+        assert_eq!(tree.current().0, 0);
+    }
+
+    fn samples() -> impl Strategy<Value = (usize, Value)> {
+        (0..things().len()).prop_flat_map(|i| values(&things()[i]).prop_map(move |v| (i, v)))
+    }
+
+    #[proptest]
+    fn valid(#[strategy(samples())] sample: (usize, Value)) {
+        let (index, value) = sample;
+        let thing = &things()[index];
+        prop_assert!(satisfies(thing, &value), "{} is not {}", value, thing);
+    }
+
+    #[proptest]
+    fn invalid(#[strategy(violations(&score()))] violation: (usize, Value)) {
+        let (index, value) = violation;
+        let thing = score();
+        for (i, refinement) in thing.0.iter().enumerate() {
+            let single = Thing(vec![(**refinement).clone()]);
+            prop_assert_eq!(satisfies(&single, &value), i != index);
+        }
+    }
+
+    #[proptest]
+    fn assignable_values(#[strategy(values(&player()))] value: Value) {
+        // Values of a thing are values of every thing it can be assigned to
+        let wide = Thing::from(ObjectRefinement::new([(
+            "name",
+            Field::required(PrimitiveRefinement::String),
+        )]));
+        player().try_assign_to(&wide).unwrap();
+        prop_assert!(satisfies(&wide, &value));
+    }
+}
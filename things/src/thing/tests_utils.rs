@@ -1,9 +1,11 @@
 use std::any::type_name;
 use std::error::Error;
+use std::fmt::Display;
 
 use serde_json::Value;
 
-use crate::thing::{Refinement, Relation, Thing};
+use crate::thing::validation::ValidationIssue;
+use crate::thing::{Refinement, Relation};
 
 macro_rules! prop_unwrap {
     ($expression:expr) => {
@@ -68,7 +70,7 @@ pub(crate) trait TestConversionResultWithWarnings<R> {
     fn with_warnings(self, warnings: usize) -> anyhow::Result<R>;
 }
 
-impl<R> TestConversionResultWithWarnings<Vec<R>> for anyhow::Result<Vec<R>> {
+impl<R, E: Display> TestConversionResultWithWarnings<Vec<R>> for Result<Vec<R>, E> {
     fn with_warnings(self, warnings: usize) -> anyhow::Result<Vec<R>> {
         match self {
             Ok(warns) => {
@@ -121,14 +123,14 @@ impl<R> TestConversionResult<R> for anyhow::Result<R> {
 }
 
 pub(crate) trait TestRefinement {
-    fn check<T: Into<Value>>(self, value: T) -> anyhow::Result<(Value, Vec<anyhow::Error>)>;
+    fn check<T: Into<Value>>(self, value: T) -> anyhow::Result<(Value, Vec<ValidationIssue>)>;
 }
 
 impl<R: Refinement> TestRefinement for R {
-    fn check<T: Into<Value>>(self, value: T) -> anyhow::Result<(Value, Vec<anyhow::Error>)> {
-        let thing = Thing::from(self);
+    fn check<T: Into<Value>>(self, value: T) -> anyhow::Result<(Value, Vec<ValidationIssue>)> {
         let mut value = value.into();
-        let warnings = thing.apply(&mut value)?;
+        let mut warnings = vec![];
+        Refinement::apply(&self, &mut value, &mut warnings)?;
         Ok((value, warnings))
     }
 }
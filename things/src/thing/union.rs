@@ -0,0 +1,533 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use anyhow::Error;
+use as_any::Downcast;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::thing::object::{at_path, ObjectRefinementError, PathError};
+use crate::thing::primitives::inapplicable;
+use crate::thing::registry::{serialize_tagged, SerializableRefinement};
+use crate::thing::validation::{validate, ValidationIssue};
+use crate::thing::{MissingRefinementError, Refinement, Relation, Thing};
+
+/// Values that satisfy at least one of the variants, coerced by the first one of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnyOfRefinement {
+    pub variants: Vec<Thing>,
+}
+
+/// Objects refined by the variant named by their tag field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaggedUnionRefinement {
+    pub tag_field: String,
+    pub variants: BTreeMap<String, Thing>,
+}
+
+/// How far the value got into the variant before failing, as the depth of the error path and
+/// the number of refinements it passed
+type Progress = (usize, usize);
+
+/// Applies the thing to a copy of the value, returning the refined copy and its warnings
+fn attempt(
+    thing: &Thing,
+    value: &Value,
+) -> Result<(Value, Vec<ValidationIssue>), (Progress, Error)> {
+    let mut value = value.clone();
+    let mut warnings = vec![];
+    for (i, refinement) in thing.0.iter().enumerate() {
+        if let Err(issue) = validate([&**refinement], &mut value, &mut warnings) {
+            return Err(((issue.path.matches('/').count(), i), (*issue).into()));
+        }
+    }
+    Ok((value, warnings))
+}
+
+fn depth(err: &Error) -> usize {
+    err.downcast_ref::<PathError>()
+        .map_or(0, |err| err.path.matches('/').count())
+}
+
+/// Failure of the variant that came the closest to matching, preferring earlier ones
+fn closest(failures: Vec<(&Thing, Progress, Error)>) -> Error {
+    let mut closest: Option<(&Thing, Progress, Error)> = None;
+    for failure in failures {
+        let further = match &closest {
+            Some(closest) => failure.1 > closest.1,
+            None => true,
+        };
+        if further {
+            closest = Some(failure);
+        }
+    }
+    match closest {
+        Some((variant, _, error)) => UnionRefinementError::NoMatch {
+            closest: variant.to_string(),
+            error,
+        }
+        .into(),
+        None => UnionRefinementError::NoVariants.into(),
+    }
+}
+
+/// Unions in the thing split into things with one of the variants each, or `None` when there
+/// are no unions in it
+fn expand(thing: &Thing) -> Option<Vec<Thing>> {
+    let (i, variants) = thing.0.iter().enumerate().find_map(|(i, refinement)| {
+        if let Some(union) = (**refinement).downcast_ref::<AnyOfRefinement>() {
+            Some((i, union.variants.iter().collect::<Vec<_>>()))
+        } else {
+            // Tag field only selects the variant, so variants alone are a superset of the union
+            let union = (**refinement).downcast_ref::<TaggedUnionRefinement>()?;
+            Some((i, union.variants.values().collect()))
+        }
+    })?;
+    let expanded = variants
+        .into_iter()
+        .map(|variant| {
+            let mut expanded = thing.clone();
+            expanded.0.remove(i);
+            expanded
+                .0
+                .extend(variant.0.iter().map(|refinement| (**refinement).clone()));
+            expanded
+        })
+        .collect();
+    Some(expanded)
+}
+
+/// Relation of the thing to the union, given by its variants: it has to be assignable to one
+/// of them, or each of its own variants has to be
+fn thing_to_variants<'a>(thing: &Thing, variants: impl IntoIterator<Item = &'a Thing>) -> Relation {
+    if let Some(expanded) = expand(thing) {
+        let variants: Vec<_> = variants.into_iter().collect();
+        for thing in expanded {
+            if let Relation::Conflict(err) = thing_to_variants(&thing, variants.iter().copied()) {
+                return Relation::Conflict(err);
+            }
+        }
+        return Relation::Subset;
+    }
+
+    let mut failures = vec![];
+    for variant in variants {
+        match thing.try_assign_to(variant) {
+            Ok(()) => return Relation::Subset,
+            Err(err) => failures.push((variant, (depth(&err), 0), err)),
+        }
+    }
+    Relation::Conflict(closest(failures))
+}
+
+/// Relation of the union to a refinement outside of it, where every variant has to be a subset
+/// of the refinement. Variants that are all unrelated to it leave the union unrelated too
+fn variants_to_refinement<'a>(
+    variants: impl IntoIterator<Item = &'a Thing>,
+    other: &dyn Refinement,
+) -> Relation {
+    let other = Thing(vec![Refinement::clone(other)]);
+    let mut related = false;
+    let mut missing = None;
+    for variant in variants {
+        match variant.try_assign_to(&other) {
+            Ok(()) => related = true,
+            Err(err) if err.is::<MissingRefinementError>() => missing = Some(err),
+            Err(err) => return Relation::Conflict(err),
+        }
+    }
+    match missing {
+        Some(err) if related => Relation::Conflict(err),
+        Some(_) => Relation::Unrelated,
+        None => Relation::Subset,
+    }
+}
+
+impl Display for AnyOfRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let variants: Vec<_> = self.variants.iter().map(Thing::to_string).collect();
+        write!(f, "AnyOf({})", variants.join(" | "))
+    }
+}
+
+impl Refinement for AnyOfRefinement {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        let mut failures = vec![];
+        for variant in &self.variants {
+            match attempt(variant, value) {
+                Ok((refined, variant_warnings)) => {
+                    *value = refined;
+                    warnings.extend(variant_warnings);
+                    return Ok(());
+                }
+                Err((progress, err)) => failures.push((variant, progress, err)),
+            }
+        }
+        Err(closest(failures))
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        // Unions on both sides are related by `is_superset_of` of the other one
+        if other.downcast_ref::<AnyOfRefinement>().is_some() {
+            return Relation::Unrelated;
+        }
+        variants_to_refinement(&self.variants, other)
+    }
+
+    fn is_superset_of(&self, thing: &Thing) -> Relation {
+        thing_to_variants(thing, &self.variants)
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for AnyOfRefinement {
+    const TAG: &'static str = "AnyOf";
+}
+
+impl Display for TaggedUnionRefinement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let variants: Vec<_> = self
+            .variants
+            .iter()
+            .map(|(tag, variant)| format!("{}: {}", tag, variant))
+            .collect();
+        write!(
+            f,
+            "TaggedUnion {}({})",
+            self.tag_field,
+            variants.join(" | ")
+        )
+    }
+}
+
+impl Refinement for TaggedUnionRefinement {
+    fn apply(&self, value: &mut Value, warnings: &mut Vec<ValidationIssue>) -> anyhow::Result<()> {
+        let Value::Object(object) = value else {
+            return Err(inapplicable(self, value));
+        };
+        let variant = match object.get(&self.tag_field) {
+            None => {
+                let err = ObjectRefinementError::MissingField.into();
+                let issue = ValidationIssue::error(self, None, err);
+                return Err(issue.at(&self.tag_field).into());
+            }
+            Some(tag) => tag
+                .as_str()
+                .and_then(|tag| self.variants.get(tag))
+                .ok_or_else(|| {
+                    let err = UnionRefinementError::UnknownTag {
+                        tag: tag.clone(),
+                        known: self.variants.keys().cloned().collect(),
+                    };
+                    let issue = ValidationIssue::error(self, Some(tag.clone()), err.into());
+                    issue.at(&self.tag_field)
+                })?,
+        };
+        // Issues of the variant are reported as its own, rather than of the whole union
+        variant.refine(value, warnings).map_err(|issue| *issue)?;
+        Ok(())
+    }
+
+    fn strict(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn clone(&self) -> Box<dyn Refinement> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn is_subset_of(&self, other: &dyn Refinement) -> Relation {
+        if other.downcast_ref::<AnyOfRefinement>().is_some() {
+            return Relation::Unrelated;
+        }
+        let Some(other) = other.downcast_ref::<TaggedUnionRefinement>() else {
+            return variants_to_refinement(self.variants.values(), other);
+        };
+        if self.tag_field != other.tag_field {
+            return Relation::Conflict(
+                UnionRefinementError::TagField {
+                    expected: other.tag_field.clone(),
+                    got: self.tag_field.clone(),
+                }
+                .into(),
+            );
+        }
+        for (tag, variant) in &self.variants {
+            let Some(other_variant) = other.variants.get(tag) else {
+                let err = UnionRefinementError::UnknownTag {
+                    tag: Value::from(tag.as_str()),
+                    known: other.variants.keys().cloned().collect(),
+                };
+                return Relation::Conflict(at_path(&self.tag_field, err.into()));
+            };
+            if let Err(err) = variant.try_assign_to(other_variant) {
+                return Relation::Conflict(
+                    err.context(format!("Variant `{}` is incompatible", tag)),
+                );
+            }
+        }
+        Relation::Subset
+    }
+
+    fn is_superset_of(&self, thing: &Thing) -> Relation {
+        // Things without unions can't be related to tag fields of the union, and tagged unions
+        // are related by `is_subset_of`
+        if thing.0.iter().any(|refinement| {
+            (**refinement)
+                .downcast_ref::<TaggedUnionRefinement>()
+                .is_some()
+        }) {
+            return Relation::Unrelated;
+        }
+        let Some(expanded) = expand(thing) else {
+            return Relation::Unrelated;
+        };
+        for thing in expanded {
+            if let Err(err) = thing.try_assign_to(&Thing(vec![Refinement::clone(self)])) {
+                return Relation::Conflict(err);
+            }
+        }
+        Relation::Subset
+    }
+
+    fn serialize(&self) -> anyhow::Result<Value> {
+        serialize_tagged(self)
+    }
+}
+
+impl SerializableRefinement for TaggedUnionRefinement {
+    const TAG: &'static str = "TaggedUnion";
+}
+
+#[derive(Error, Debug)]
+pub enum UnionRefinementError {
+    #[error("None of the variants matched, the closest one is `{closest}`: {error}")]
+    NoMatch { closest: String, error: Error },
+    #[error("Union has no variants")]
+    NoVariants,
+    #[error("Unknown tag {tag}, expected one of: {}", .known.join(", "))]
+    UnknownTag { tag: Value, known: Vec<String> },
+    #[error("Union is tagged by `{got}` where `{expected}` was expected")]
+    TagField { expected: String, got: String },
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::{json, Value};
+
+    use crate::thing::multiple_of::INTEGER;
+    use crate::thing::object::{
+        AdditionalProperties, Field, ObjectRefinement, ObjectRefinementError, PathError,
+    };
+    use crate::thing::one_of::OneOfRefinement;
+    use crate::thing::primitives::{PrimitiveRefinement, RefinementCastError};
+    use crate::thing::registry::RefinementRegistry;
+    use crate::thing::tests_utils::{
+        TestAssertionResult, TestConversionResult, TestConversionResultWithWarnings, TestRefinement,
+    };
+    use crate::thing::union::{AnyOfRefinement, TaggedUnionRefinement, UnionRefinementError};
+    use crate::thing::validation::ValidationIssue;
+    use crate::thing::{MissingRefinementError, Thing};
+
+    fn integer() -> Thing {
+        let mut thing = Thing::from(PrimitiveRefinement::Number);
+        thing.0.push(Box::new(INTEGER));
+        thing
+    }
+
+    fn any_of(variants: Vec<Thing>) -> Thing {
+        Thing::from(AnyOfRefinement { variants })
+    }
+
+    fn optional_number() -> Thing {
+        any_of(vec![
+            Thing::from(PrimitiveRefinement::Number),
+            Thing::from(PrimitiveRefinement::Null),
+        ])
+    }
+
+    fn shape() -> TaggedUnionRefinement {
+        let circle = ObjectRefinement::new([
+            ("kind", Field::required(PrimitiveRefinement::String)),
+            ("radius", Field::required(PrimitiveRefinement::Number)),
+        ]);
+        let mut square = ObjectRefinement::new([
+            ("kind", Field::required(PrimitiveRefinement::String)),
+            ("side", Field::required(integer())),
+        ]);
+        square.additional_properties = AdditionalProperties::Deny;
+        TaggedUnionRefinement {
+            tag_field: "kind".to_string(),
+            variants: BTreeMap::from([
+                ("circle".to_string(), Thing::from(circle)),
+                ("square".to_string(), Thing::from(square)),
+            ]),
+        }
+    }
+
+    #[test]
+    fn any_of_apply() {
+        let number_or_null = AnyOfRefinement {
+            variants: vec![integer(), Thing::from(PrimitiveRefinement::Null)],
+        };
+        Clone::clone(&number_or_null)
+            .check(Value::Null)
+            .success()
+            .assert();
+        let (value, _) = Clone::clone(&number_or_null)
+            .check(2.5)
+            .with_warnings(1)
+            .assert();
+        assert_eq!(value, json!(3.0));
+        number_or_null
+            .check("2")
+            .error::<UnionRefinementError>()
+            .assert();
+    }
+
+    #[test]
+    fn closest_variant() {
+        let named = ObjectRefinement::new([("name", Field::required(PrimitiveRefinement::String))]);
+        let union = AnyOfRefinement {
+            variants: vec![Thing::from(PrimitiveRefinement::String), Thing::from(named)],
+        };
+        let err = union
+            .check(json!({ "name": 1 }))
+            .error::<UnionRefinementError>()
+            .assert();
+        let UnionRefinementError::NoMatch { closest, error } = err else {
+            panic!("Unexpected error {}", err);
+        };
+        assert_eq!(closest, "Object { name: String, ... }");
+        assert_eq!(
+            error.downcast_ref::<ValidationIssue>().unwrap().path,
+            "/name"
+        );
+    }
+
+    #[test]
+    fn tagged_union_apply() {
+        shape()
+            .check(json!({ "kind": "circle", "radius": 0.5 }))
+            .success()
+            .assert();
+        let (value, _) = shape()
+            .check(json!({ "kind": "square", "side": 1.8 }))
+            .with_warnings(1)
+            .assert();
+        assert_eq!(value["side"], json!(2.0));
+
+        let err = shape()
+            .check(json!({ "kind": "square", "radius": 1 }))
+            .error::<ValidationIssue>()
+            .assert();
+        assert_eq!(err.path, "/side");
+        let err = shape()
+            .check(json!({ "kind": "triangle" }))
+            .error::<ValidationIssue>()
+            .assert();
+        assert_eq!(err.path, "/kind");
+        assert_eq!(
+            err.error.to_string(),
+            "Unknown tag \"triangle\", expected one of: circle, square"
+        );
+        let err = shape().check(json!({})).error::<ValidationIssue>().assert();
+        assert!(err.error.is::<ObjectRefinementError>());
+    }
+
+    #[test]
+    fn union_on_the_right() {
+        Thing::from(PrimitiveRefinement::Null)
+            .try_assign_to(&optional_number())
+            .success()
+            .assert();
+        // Variants are matched by whole things, not by refinements one at a time
+        integer()
+            .try_assign_to(&any_of(vec![
+                integer(),
+                Thing::from(PrimitiveRefinement::Null),
+            ]))
+            .success()
+            .assert();
+        Thing::from(PrimitiveRefinement::Number)
+            .try_assign_to(&any_of(vec![
+                integer(),
+                Thing::from(PrimitiveRefinement::Null),
+            ]))
+            .error::<UnionRefinementError>()
+            .assert();
+    }
+
+    #[test]
+    fn union_on_the_left() {
+        any_of(vec![integer(), Thing::from(PrimitiveRefinement::Number)])
+            .try_assign_to(&Thing::from(PrimitiveRefinement::Number))
+            .success()
+            .assert();
+        optional_number()
+            .try_assign_to(&Thing::from(PrimitiveRefinement::Number))
+            .error::<RefinementCastError<PrimitiveRefinement>>()
+            .assert();
+        optional_number()
+            .try_assign_to(&Thing::from(OneOfRefinement::new(vec![1.into()]).unwrap()))
+            .error::<MissingRefinementError>()
+            .assert();
+    }
+
+    #[test]
+    fn unions_on_both_sides() {
+        let wide = any_of(vec![
+            Thing::from(PrimitiveRefinement::String),
+            Thing::from(PrimitiveRefinement::Null),
+            Thing::from(PrimitiveRefinement::Number),
+        ]);
+        optional_number().try_assign_to(&wide).success().assert();
+        wide.try_assign_to(&optional_number())
+            .error::<UnionRefinementError>()
+            .assert();
+
+        let mut circles = shape();
+        circles.variants.remove("square");
+        Thing::from(Clone::clone(&circles))
+            .try_assign_to(&Thing::from(shape()))
+            .success()
+            .assert();
+        Thing::from(shape())
+            .try_assign_to(&Thing::from(circles))
+            .error::<PathError>()
+            .assert();
+    }
+
+    #[test]
+    fn round_trip() {
+        let thing = Thing::from(shape());
+        let value = thing.to_value().assert();
+        let loaded = RefinementRegistry::default()
+            .thing_from_value(value.clone())
+            .assert();
+        assert_eq!(loaded.to_value().assert(), value);
+        let optional = optional_number().to_value().assert();
+        assert_eq!(
+            RefinementRegistry::default()
+                .thing_from_value(optional.clone())
+                .assert()
+                .to_value()
+                .assert(),
+            optional
+        );
+    }
+}
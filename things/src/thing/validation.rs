@@ -0,0 +1,270 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Error;
+use serde_json::Value;
+
+use crate::thing::Refinement;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Severity {
+    /// Value was rejected
+    Error,
+    /// Value was coerced to satisfy the refinement
+    Warning,
+}
+
+/// Error or warning of applying a thing, located at the value that caused it
+#[derive(Debug)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// JSON pointer to the value inside of the applied one, empty for the value itself
+    pub path: String,
+    /// `Display` of the refinement that reported the issue
+    pub refinement: String,
+    /// Value at the path that the refinement rejected or coerced, `None` when it was missing
+    pub original: Option<Value>,
+    /// Value at the path after the coercion, `None` for errors and removed values
+    pub coerced: Option<Value>,
+    /// The reported error, without its location
+    pub error: Error,
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.error)
+        } else {
+            write!(f, "{}: {}", self.path, self.error)
+        }
+    }
+}
+
+impl std::error::Error for ValidationIssue {}
+
+impl ValidationIssue {
+    /// Warning of the refinement that coerced the original value, where the coerced one is
+    /// `None` when the value was removed
+    pub fn warning(
+        refinement: &dyn Refinement,
+        original: Value,
+        coerced: Option<Value>,
+        error: Error,
+    ) -> Self {
+        ValidationIssue {
+            severity: Severity::Warning,
+            path: String::new(),
+            refinement: refinement.to_string(),
+            original: Some(original),
+            coerced,
+            error,
+        }
+    }
+
+    /// Error of the refinement that rejected the value, or `None` when it was missing
+    pub fn error(refinement: &dyn Refinement, value: Option<Value>, error: Error) -> Self {
+        ValidationIssue {
+            severity: Severity::Error,
+            path: String::new(),
+            refinement: refinement.to_string(),
+            original: value,
+            coerced: None,
+            error,
+        }
+    }
+
+    /// Locates the issue of a nested value under the field name or array index
+    pub fn at(mut self, segment: &str) -> Self {
+        let segment = segment.replace('~', "~0").replace('/', "~1");
+        self.path = format!("/{}{}", segment, self.path);
+        self
+    }
+}
+
+/// Applies refinements in order, reporting errors that aren't issues of nested values as
+/// issues of the refinement that rejected the value
+pub(crate) fn validate<'a>(
+    refinements: impl IntoIterator<Item = &'a dyn Refinement>,
+    value: &mut Value,
+    warnings: &mut Vec<ValidationIssue>,
+) -> Result<(), Box<ValidationIssue>> {
+    for refinement in refinements {
+        if let Err(err) = refinement.apply(value, warnings) {
+            let issue = err
+                .downcast::<ValidationIssue>()
+                .unwrap_or_else(|err| ValidationIssue::error(refinement, Some(value.clone()), err));
+            return Err(Box::new(issue));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::ops::Bound;
+
+    use serde_json::json;
+
+    use crate::thing::array::{ArrayOfRefinement, UniqueItemsRefinement};
+    use crate::thing::bounds::BoundsRefinement;
+    use crate::thing::multiple_of::{MultipleOfRefinement, INTEGER};
+    use crate::thing::object::{Field, ObjectRefinement, ObjectRefinementError};
+    use crate::thing::primitives::{PrimitiveRefinement, TypeRefinementError};
+    use crate::thing::validation::Severity;
+    use crate::thing::{Thing, ThingLike};
+
+    fn level() -> Thing {
+        Thing(vec![
+            Box::new(PrimitiveRefinement::Number),
+            Box::new(BoundsRefinement {
+                min: Bound::Included(1.0),
+                max: Bound::Included(10.0),
+                coerce: true,
+            }),
+            Box::new(INTEGER),
+        ])
+    }
+
+    fn character() -> Thing {
+        let levels = ArrayOfRefinement { items: level() };
+        Thing::from(ObjectRefinement::new([
+            ("name", Field::required(PrimitiveRefinement::String)),
+            ("levels", Field::required(levels)),
+        ]))
+    }
+
+    #[test]
+    fn warnings() {
+        let mut value = json!({ "name": "Ann", "levels": [3, 12, 4.2] });
+        let issues = character().apply(&mut value).unwrap();
+        assert_eq!(value, json!({ "name": "Ann", "levels": [3, 10.0, 4.0] }));
+
+        let issues: Vec<_> = issues
+            .iter()
+            .map(|issue| {
+                assert_eq!(issue.severity, Severity::Warning);
+                (
+                    issue.path.as_str(),
+                    issue.refinement.as_str(),
+                    issue.original.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                ("/levels/1", "Bounds [1; 10]", Some(json!(12))),
+                ("/levels/2", "Integer", Some(json!(4.2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let issue = character()
+            .apply(&mut json!({ "name": 5, "levels": [] }))
+            .unwrap_err();
+        assert_eq!(issue.severity, Severity::Error);
+        assert_eq!(issue.path, "/name");
+        assert_eq!(issue.refinement, "String");
+        assert_eq!(issue.original, Some(json!(5)));
+        assert_eq!(issue.coerced, None);
+        assert!(issue.error.is::<TypeRefinementError>());
+        assert_eq!(issue.to_string(), format!("/name: {}", issue.error));
+
+        let issue = character()
+            .apply(&mut json!({ "name": "Ann" }))
+            .unwrap_err();
+        assert_eq!(issue.path, "/levels");
+        assert!(issue.refinement.starts_with("Object"));
+        assert_eq!(issue.original, None);
+        assert!(issue.error.is::<ObjectRefinementError>());
+
+        let issue = ThingLike::apply(&PrimitiveRefinement::Null, &mut json!(1)).unwrap_err();
+        assert_eq!(
+            (issue.path.as_str(), issue.refinement.as_str()),
+            ("", "Null")
+        );
+    }
+
+    #[test]
+    fn coerced_values() {
+        let mut value = json!([0.4, 5.5]);
+        let thing = Thing::from(ArrayOfRefinement { items: level() });
+        let issues: Vec<_> = thing
+            .apply(&mut value)
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.refinement, issue.original, issue.coerced))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (
+                    "Bounds [1; 10]".to_string(),
+                    Some(json!(0.4)),
+                    Some(json!(1.0))
+                ),
+                ("Integer".to_string(), Some(json!(5.5)), Some(json!(6.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn chained_coercions() {
+        let mut even = level();
+        even.0[2] = Box::new(MultipleOfRefinement {
+            factor: 2.0,
+            coerce: true,
+        });
+        let mut value = json!([0.4]);
+        let issues: Vec<_> = Thing::from(ArrayOfRefinement { items: even })
+            .apply(&mut value)
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.refinement, issue.original, issue.coerced))
+            .collect();
+        assert_eq!(value, json!([2.0]));
+        assert_eq!(
+            issues,
+            [
+                (
+                    "Bounds [1; 10]".to_string(),
+                    Some(json!(0.4)),
+                    Some(json!(1.0))
+                ),
+                (
+                    "MultipleOf(2)".to_string(),
+                    Some(json!(1.0)),
+                    Some(json!(2.0))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn removed_values() {
+        let unique = UniqueItemsRefinement { coerce: true };
+        let issues: Vec<_> = ThingLike::apply(&unique, &mut json!([3, 1, 3, 2, 1]))
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.path, issue.refinement, issue.original, issue.coerced))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (
+                    "/2".to_string(),
+                    "UniqueItems".to_string(),
+                    Some(json!(3)),
+                    None
+                ),
+                (
+                    "/4".to_string(),
+                    "UniqueItems".to_string(),
+                    Some(json!(1)),
+                    None
+                ),
+            ]
+        );
+    }
+}